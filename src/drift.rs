@@ -0,0 +1,374 @@
+//! `PARSE_MODE=strict`: noticing when Linear's payloads grow fields the
+//! bridge has never seen.
+//!
+//! The models in `main.rs` stay lenient so delivery never depends on this.
+//! In strict mode every payload is also compared against the keys Linear
+//! is known to send, and anything new is logged as a one-line summary of
+//! unknown keys per object instead of the payload itself, and counted in
+//! `linear_lark_payload_drift_total`.
+
+use std::fmt;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    #[default]
+    Lenient,
+    Strict,
+}
+
+impl std::str::FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!("expected lenient or strict, got {other:?}")),
+        }
+    }
+}
+
+const ENVELOPE: &[&str] = &[
+    "action",
+    "actor",
+    "createdAt",
+    "data",
+    "organizationId",
+    "type",
+    "updatedFrom",
+    "url",
+    "webhookId",
+    "webhookTimestamp",
+];
+
+const USER: &[&str] = &["avatarUrl", "email", "id", "name", "type", "url"];
+
+const TEAM: &[&str] = &["id", "key", "name"];
+
+const ISSUE: &[&str] = &[
+    "addedToCycleAt",
+    "addedToProjectAt",
+    "addedToTeamAt",
+    "archivedAt",
+    "assignee",
+    "assigneeId",
+    "autoArchivedAt",
+    "autoClosedAt",
+    "boardOrder",
+    "botActor",
+    "canceledAt",
+    "completedAt",
+    "createdAt",
+    "creator",
+    "creatorId",
+    "cycle",
+    "cycleId",
+    "description",
+    "descriptionData",
+    "dueDate",
+    "estimate",
+    "id",
+    "identifier",
+    "integrationSourceType",
+    "labelIds",
+    "labels",
+    "lastAppliedTemplateId",
+    "number",
+    "parentId",
+    "previousIdentifiers",
+    "priority",
+    "priorityLabel",
+    "prioritySortOrder",
+    "project",
+    "projectId",
+    "projectMilestoneId",
+    "reactionData",
+    "slaBreachesAt",
+    "slaStartedAt",
+    "slaType",
+    "snoozedUntilAt",
+    "sortOrder",
+    "startedAt",
+    "startedTriageAt",
+    "state",
+    "stateId",
+    "subIssueSortOrder",
+    "subscriberIds",
+    "team",
+    "teamId",
+    "title",
+    "trashed",
+    "triagedAt",
+    "updatedAt",
+    "url",
+];
+
+const COMMENT: &[&str] = &[
+    "archivedAt",
+    "body",
+    "bodyData",
+    "botActor",
+    "createdAt",
+    "editedAt",
+    "externalUserId",
+    "id",
+    "issue",
+    "issueId",
+    "parentId",
+    "quotedText",
+    "reactionData",
+    "resolvedAt",
+    "resolvingCommentId",
+    "resolvingUserId",
+    "updatedAt",
+    "user",
+    "userId",
+];
+
+const PROJECT: &[&str] = &[
+    "archivedAt",
+    "autoArchivedAt",
+    "canceledAt",
+    "color",
+    "completedAt",
+    "content",
+    "createdAt",
+    "creatorId",
+    "description",
+    "health",
+    "icon",
+    "id",
+    "labelIds",
+    "lead",
+    "leadId",
+    "memberIds",
+    "name",
+    "priority",
+    "priorityLabel",
+    "prioritySortOrder",
+    "progress",
+    "scope",
+    "slugId",
+    "sortOrder",
+    "startDate",
+    "startedAt",
+    "state",
+    "status",
+    "statusId",
+    "targetDate",
+    "teamIds",
+    "trashed",
+    "updatedAt",
+    "url",
+];
+
+const PROJECT_UPDATE: &[&str] = &[
+    "archivedAt",
+    "body",
+    "bodyData",
+    "createdAt",
+    "diff",
+    "diffMarkdown",
+    "editedAt",
+    "health",
+    "id",
+    "infoSnapshot",
+    "isDiffHidden",
+    "project",
+    "projectId",
+    "reactionData",
+    "slugId",
+    "updatedAt",
+    "url",
+    "user",
+    "userId",
+];
+
+const CYCLE: &[&str] = &[
+    "archivedAt",
+    "autoArchivedAt",
+    "completedAt",
+    "completedIssueCountHistory",
+    "completedScopeHistory",
+    "createdAt",
+    "description",
+    "endsAt",
+    "id",
+    "inProgressScopeHistory",
+    "issueCountHistory",
+    "name",
+    "number",
+    "progress",
+    "scopeHistory",
+    "startsAt",
+    "teamId",
+    "uncompletedIssuesUponCloseIds",
+    "updatedAt",
+];
+
+/// Known keys of the objects nested in `data`, by field name.
+const NESTED: &[(&str, &[&str])] = &[
+    ("assignee", USER),
+    ("creator", USER),
+    ("cycle", &["endsAt", "id", "name", "number", "startsAt"]),
+    (
+        "issue",
+        &["id", "identifier", "team", "teamId", "title", "url"],
+    ),
+    ("labels", &["color", "id", "name", "parentId"]),
+    ("lead", USER),
+    ("project", &["id", "name", "url"]),
+    ("state", &["color", "id", "name", "type"]),
+    ("team", TEAM),
+    ("user", USER),
+];
+
+/// The unknown keys of one object, e.g. `data.state: category`.
+#[derive(Debug, PartialEq)]
+pub struct Drift {
+    pub path: String,
+    pub keys: Vec<String>,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.keys.join(", "))
+    }
+}
+
+/// Every object in `payload` with keys outside the known shape of its
+/// `type`. Kinds the bridge has no model for only have their envelope
+/// checked.
+pub fn unknown_keys(payload: &Value) -> Vec<Drift> {
+    let mut drift = Vec::new();
+    check(&mut drift, "payload", payload, ENVELOPE);
+    if let Some(actor) = payload.get("actor") {
+        check(&mut drift, "actor", actor, USER);
+    }
+
+    let known = match payload.get("type").and_then(Value::as_str) {
+        Some("Issue") => ISSUE,
+        Some("Comment") => COMMENT,
+        Some("Project") => PROJECT,
+        Some("ProjectUpdate") => PROJECT_UPDATE,
+        Some("Cycle") => CYCLE,
+        _ => return drift,
+    };
+    let Some(data) = payload.get("data") else {
+        return drift;
+    };
+    check(&mut drift, "data", data, known);
+    // Only the fields of `data` that changed, under the same names.
+    if let Some(from) = payload.get("updatedFrom") {
+        check(&mut drift, "updatedFrom", from, known);
+    }
+
+    for (field, nested_known) in NESTED {
+        let path = format!("data.{field}");
+        match data.get(field) {
+            Some(Value::Array(items)) => {
+                for item in items {
+                    check(&mut drift, &format!("{path}[]"), item, nested_known);
+                }
+            }
+            Some(value) => check(&mut drift, &path, value, nested_known),
+            None => {}
+        }
+    }
+    // Comments carry their issue, which carries its team.
+    if let Some(team) = data.get("issue").and_then(|issue| issue.get("team")) {
+        check(&mut drift, "data.issue.team", team, TEAM);
+    }
+
+    drift
+}
+
+fn check(drift: &mut Vec<Drift>, path: &str, value: &Value, known: &[&str]) {
+    let Value::Object(object) = value else {
+        return;
+    };
+    let keys: Vec<String> = object
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    if keys.is_empty() {
+        return;
+    }
+    // Array items report under one path; merge their keys.
+    match drift.iter_mut().find(|d| d.path == path) {
+        Some(existing) => {
+            for key in keys {
+                if !existing.keys.contains(&key) {
+                    existing.keys.push(key);
+                }
+            }
+        }
+        None => drift.push(Drift {
+            path: path.to_string(),
+            keys,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Value {
+        let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fixtures_match_the_known_shape() {
+        for name in [
+            "issue_create.json",
+            "issue_update.json",
+            "issue_update_description.json",
+            "issue_delete.json",
+            "issue_archive.json",
+            "comment_create.json",
+            "project_update.json",
+            "project_status_update.json",
+            "test_ping.json",
+        ] {
+            assert_eq!(unknown_keys(&fixture(name)), vec![], "{name}");
+        }
+    }
+
+    #[test]
+    fn reports_unknown_keys_per_object() {
+        let mut payload = fixture("issue_update.json");
+        payload["data"]["sentiment"] = "happy".into();
+        payload["data"]["state"]["category"] = "active".into();
+        payload["data"]["labels"] = serde_json::json!([
+            { "id": "l1", "name": "bug", "weight": 1 },
+            { "id": "l2", "name": "ux", "weight": 2, "icon": "x" },
+        ]);
+        payload["trace"] = "abc".into();
+
+        let summary: Vec<String> = unknown_keys(&payload)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                "payload: trace",
+                "data: sentiment",
+                "data.labels[]: weight, icon",
+                "data.state: category",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_kinds_only_check_the_envelope() {
+        let mut payload = fixture("test_ping.json");
+        payload["data"]["anything"] = true.into();
+        assert_eq!(unknown_keys(&payload), vec![]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Optional card enrichment from the Linear API.
+//!
+//! Enrichment is best effort: every lookup runs under a strict time budget
+//! and any failure simply leaves the card as the webhook payload built it.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::linear::api::{Comment, LinearClient, LinearError};
+
+/// How long a fetched description is reused for the same issue. Long enough
+/// to absorb a burst of updates, short enough that edits show up.
+const DESCRIPTION_TTL: Duration = Duration::from_secs(60);
+
+/// Comments change less often than the issue itself, and the card only
+/// wants the one explaining the latest transition.
+const COMMENT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Outcome counters for one kind of enrichment.
+#[derive(Debug, Default)]
+pub struct EnrichmentStats {
+    pub hit: AtomicU64,
+    pub miss: AtomicU64,
+    pub timeout: AtomicU64,
+}
+
+/// Per-issue cache of an optional value fetched from Linear.
+struct TtlCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Option<T>)>>,
+}
+
+impl<T: Clone> TtlCache<T> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Option<T>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn insert(&self, key: &str, value: Option<T>) {
+        let mut entries = self.entries.lock().unwrap();
+        // Expired entries are pruned on write, which keeps the map bounded by
+        // the number of issues touched within one TTL.
+        entries.retain(|_, (at, _)| at.elapsed() < self.ttl);
+        entries.insert(key.to_string(), (Instant::now(), value));
+    }
+}
+
+pub struct Enricher {
+    budget: Duration,
+    descriptions: TtlCache<String>,
+    comments: TtlCache<Comment>,
+    pub description_stats: EnrichmentStats,
+    pub comment_stats: EnrichmentStats,
+}
+
+impl Enricher {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            descriptions: TtlCache::new(DESCRIPTION_TTL),
+            comments: TtlCache::new(COMMENT_TTL),
+            description_stats: EnrichmentStats::default(),
+            comment_stats: EnrichmentStats::default(),
+        }
+    }
+
+    /// How long each lookup may take before the card goes without it.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// The issue's current description, or `None` when it is empty, the
+    /// lookup failed, or it did not finish within the budget.
+    pub async fn description(&self, linear: &LinearClient, issue_id: &str) -> Option<String> {
+        self.lookup(
+            &self.descriptions,
+            &self.description_stats,
+            "description",
+            issue_id,
+            linear.issue_description(issue_id),
+        )
+        .await
+    }
+
+    /// The issue's most recent comment, `None` when it has none or the
+    /// lookup did not succeed in time.
+    pub async fn latest_comment(&self, linear: &LinearClient, issue_id: &str) -> Option<Comment> {
+        self.lookup(
+            &self.comments,
+            &self.comment_stats,
+            "comment",
+            issue_id,
+            linear.latest_comment(issue_id),
+        )
+        .await
+    }
+
+    async fn lookup<T: Clone>(
+        &self,
+        cache: &TtlCache<T>,
+        stats: &EnrichmentStats,
+        kind: &str,
+        issue_id: &str,
+        fetch: impl Future<Output = Result<Option<T>, LinearError>>,
+    ) -> Option<T> {
+        let value = match cache.get(issue_id) {
+            Some(cached) => cached,
+            None => match tokio::time::timeout(self.budget, fetch).await {
+                Ok(Ok(value)) => {
+                    cache.insert(issue_id, value.clone());
+                    value
+                }
+                Ok(Err(e)) => {
+                    debug!("{kind} enrichment for {issue_id} failed: {e}");
+                    None
+                }
+                Err(_) => {
+                    stats.timeout.fetch_add(1, Ordering::Relaxed);
+                    debug!("{kind} enrichment for {issue_id} timed out");
+                    return None;
+                }
+            },
+        };
+
+        let counter = if value.is_some() {
+            &stats.hit
+        } else {
+            &stats.miss
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+}
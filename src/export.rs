@@ -0,0 +1,159 @@
+//! CSV rendering of stored events for `GET /export/events.csv`.
+
+use std::borrow::Cow;
+
+use crate::storage::Event;
+
+/// Every exportable column, in output order.
+pub const COLUMNS: &[&str] = &[
+    "timestamp",
+    "type",
+    "action",
+    "identifier",
+    "team",
+    "title",
+    "state",
+    "priority",
+    "assignee",
+    "disposition",
+    "target",
+    "outcome",
+];
+
+/// Columns holding issue text, dropped when content is redacted.
+const CONTENT_COLUMNS: &[&str] = &["title"];
+
+/// Resolves a `fields=` list against [`COLUMNS`], keeping the canonical
+/// order. No list means every column; `redact` removes content columns
+/// either way.
+pub fn select_columns(fields: Option<&str>, redact: bool) -> Result<Vec<&'static str>, String> {
+    let requested: Option<Vec<&str>> = fields.map(|f| {
+        f.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect()
+    });
+    if let Some(unknown) = requested
+        .iter()
+        .flatten()
+        .find(|field| !COLUMNS.contains(field))
+    {
+        return Err(format!("unknown field {unknown:?}"));
+    }
+
+    Ok(COLUMNS
+        .iter()
+        .copied()
+        .filter(|column| requested.as_ref().is_none_or(|r| r.contains(column)))
+        .filter(|column| !(redact && CONTENT_COLUMNS.contains(column)))
+        .collect())
+}
+
+pub fn header(columns: &[&str]) -> String {
+    line(columns.iter().map(|c| Cow::Borrowed(*c)))
+}
+
+pub fn row(event: &Event, columns: &[&str]) -> String {
+    line(columns.iter().map(|column| value(event, column)))
+}
+
+fn value<'a>(event: &'a Event, column: &str) -> Cow<'a, str> {
+    let optional = |v: &'a Option<String>| Cow::Borrowed(v.as_deref().unwrap_or(""));
+    match column {
+        "timestamp" => Cow::Owned(event.received_at.to_rfc3339()),
+        "type" => Cow::Borrowed(&event.kind),
+        "action" => Cow::Borrowed(&event.action),
+        "identifier" => Cow::Borrowed(&event.identifier),
+        "team" => optional(&event.team_key),
+        "title" => Cow::Borrowed(&event.title),
+        "state" => Cow::Borrowed(&event.state),
+        "priority" => Cow::Owned(event.priority.to_string()),
+        "assignee" => optional(&event.assignee),
+        "disposition" => Cow::Borrowed(&event.disposition),
+        "target" => optional(&event.target),
+        "outcome" => optional(&event.outcome),
+        _ => Cow::Borrowed(""),
+    }
+}
+
+fn line<'a>(values: impl Iterator<Item = Cow<'a, str>>) -> String {
+    let mut line = values
+        .map(|v| escape(&v).into_owned())
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// RFC 4180 quoting: fields with a comma, quote or line break are wrapped
+/// in quotes, with inner quotes doubled.
+fn escape(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(title: &str) -> Event {
+        Event {
+            received_at: "2026-10-06T10:00:00Z".parse().unwrap(),
+            kind: "Issue".into(),
+            action: "update".into(),
+            issue_id: "i1".into(),
+            identifier: "ENG-1".into(),
+            team_key: Some("ENG".into()),
+            title: title.into(),
+            state: "In Progress".into(),
+            state_type: Some("started".into()),
+            priority: 2,
+            previous_priority: None,
+            state_changed: true,
+            assignee: None,
+            issue_created_at: None,
+            completed_at: None,
+            disposition: "sent".into(),
+            target: Some("webhook".into()),
+            outcome: None,
+        }
+    }
+
+    #[test]
+    fn escapes_commas_quotes_and_newlines() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a, b"), "\"a, b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn renders_rows_in_column_order() {
+        let columns = select_columns(None, false).unwrap();
+        assert_eq!(header(&columns), format!("{}\r\n", COLUMNS.join(",")));
+        assert_eq!(
+            row(&event("Fix \"login\", again"), &columns),
+            "2026-10-06T10:00:00+00:00,Issue,update,ENG-1,ENG,\"Fix \"\"login\"\", again\",\
+             In Progress,2,,sent,webhook,\r\n"
+        );
+    }
+
+    #[test]
+    fn fields_select_columns_in_canonical_order() {
+        let columns = select_columns(Some("state, identifier"), false).unwrap();
+        assert_eq!(columns, vec!["identifier", "state"]);
+        assert!(select_columns(Some("identifier,body"), false).is_err());
+    }
+
+    #[test]
+    fn redaction_drops_content_columns() {
+        assert!(!select_columns(None, true).unwrap().contains(&"title"));
+        assert_eq!(
+            select_columns(Some("identifier,title"), true).unwrap(),
+            vec!["identifier"]
+        );
+    }
+}
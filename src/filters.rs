@@ -0,0 +1,106 @@
+//! `IGNORE_STATES`, `IGNORE_LABELS` and `IGNORE_ACTORS`: events that never
+//! become cards, however notable the change.
+//!
+//! States match by name (`Backlog`) or type (`canceled`), actors by name,
+//! email or id, all case-insensitively. Ignored events still show in the
+//! event log.
+
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    states: Vec<String>,
+    labels: Vec<String>,
+    actors: Vec<String>,
+}
+
+impl IgnoreRules {
+    pub fn new(states: Vec<String>, labels: Vec<String>, actors: Vec<String>) -> Self {
+        let lower = |items: Vec<String>| items.into_iter().map(|i| i.to_lowercase()).collect();
+        Self {
+            states: lower(states),
+            labels: lower(labels),
+            actors: lower(actors),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len() + self.labels.len() + self.actors.len()
+    }
+
+    /// Whether any state is ignored, i.e. whether [`Self::state`] can
+    /// ever match.
+    pub fn has_states(&self) -> bool {
+        !self.states.is_empty()
+    }
+
+    pub fn has_labels(&self) -> bool {
+        !self.labels.is_empty()
+    }
+
+    pub fn has_actors(&self) -> bool {
+        !self.actors.is_empty()
+    }
+
+    /// Whether a state with any of `names` (its name and type) is ignored.
+    pub fn state(&self, names: &[&str]) -> bool {
+        matches(&self.states, names)
+    }
+
+    /// The first of `labels` that is ignored.
+    pub fn label<'a>(&self, labels: &[&'a str]) -> Option<&'a str> {
+        labels
+            .iter()
+            .copied()
+            .find(|label| matches(&self.labels, &[label]))
+    }
+
+    /// Whether an actor going by any of `names` is ignored.
+    pub fn actor(&self, names: &[&str]) -> bool {
+        matches(&self.actors, names)
+    }
+}
+
+fn matches(rules: &[String], names: &[&str]) -> bool {
+    names
+        .iter()
+        .any(|name| rules.contains(&name.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> IgnoreRules {
+        let list = |items: &[&str]| items.iter().map(|i| i.to_string()).collect();
+        IgnoreRules::new(
+            list(&["Backlog", "canceled"]),
+            list(&["chore"]),
+            list(&["dependabot@acme.test", "Release Bot"]),
+        )
+    }
+
+    #[test]
+    fn states_match_by_name_or_type() {
+        let rules = rules();
+        assert!(rules.state(&["backlog", "backlog"]));
+        assert!(rules.state(&["Won't fix", "canceled"]));
+        assert!(!rules.state(&["Todo", "unstarted"]));
+    }
+
+    #[test]
+    fn labels_and_actors_match_case_insensitively() {
+        let rules = rules();
+        assert_eq!(rules.label(&["bug", "Chore"]), Some("Chore"));
+        assert_eq!(rules.label(&["bug"]), None);
+        assert!(rules.actor(&["Dependabot", "Dependabot@acme.test"]));
+        assert!(rules.actor(&["release bot"]));
+        assert!(!rules.actor(&["Ann Lee", "ann@acme.test"]));
+    }
+
+    #[test]
+    fn nothing_is_ignored_by_default() {
+        let rules = IgnoreRules::default();
+        assert_eq!(rules.len(), 0);
+        assert!(!rules.state(&["Backlog"]));
+        assert!(!rules.actor(&["Ann Lee"]));
+    }
+}
@@ -0,0 +1,83 @@
+//! Delivery counter snapshots, so totals survive restarts.
+//!
+//! Every `COUNTER_SNAPSHOT_SECS` and on graceful shutdown, the counters
+//! named in `PERSISTED_COUNTERS` are written to the store as totals: the
+//! baseline restored at startup plus what was counted since. `GET /health`
+//! reports those totals next to the counters since startup.
+//!
+//! Prometheus counters at `GET /metrics` still start from zero with every
+//! process, as Prometheus expects; its `rate()` and `increase()` handle
+//! the reset. Only the totals in `/health` carry over, so the two diverge
+//! after the first restart.
+
+use tracing::{error, info};
+
+use crate::AppState;
+use crate::lark::delivery::DeliveryTotals;
+
+const STORE_KEY: &str = "delivery_totals";
+
+/// Counters that can be persisted, as named in `PERSISTED_COUNTERS`.
+pub const COUNTERS: &[&str] = &["sent", "retried", "dropped"];
+
+/// Loads the last snapshot into the delivery queue as its baseline.
+pub async fn restore(state: &AppState) {
+    match state.storage.kv().get::<DeliveryTotals>(STORE_KEY).await {
+        Ok(Some(totals)) => {
+            info!(
+                "restored delivery totals: {} sent, {} retried, {} dropped",
+                totals.sent, totals.retried, totals.dropped
+            );
+            state.delivery.set_baseline(totals);
+        }
+        Ok(None) => {}
+        Err(e) => error!("failed to restore delivery totals: {e}"),
+    }
+}
+
+/// Stores the current totals of the persisted counters; the others are
+/// stored as zero and start over with the next process.
+pub async fn snapshot(state: &AppState) {
+    let totals = persisted(state.delivery.totals(), &state.persisted_counters);
+    if let Err(e) = state.storage.kv().set(STORE_KEY, &totals).await {
+        error!("failed to snapshot delivery totals: {e}");
+    }
+}
+
+fn persisted(totals: DeliveryTotals, counters: &[String]) -> DeliveryTotals {
+    let keep = |name: &str, value: u64| {
+        if counters.iter().any(|c| c == name) {
+            value
+        } else {
+            0
+        }
+    };
+    DeliveryTotals {
+        sent: keep("sent", totals.sent),
+        retried: keep("retried", totals.retried),
+        dropped: keep("dropped", totals.dropped),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_persisted_counters_are_kept() {
+        let totals = DeliveryTotals {
+            sent: 10,
+            retried: 3,
+            dropped: 1,
+        };
+        let counters = ["sent".to_string(), "dropped".to_string()];
+        assert_eq!(
+            persisted(totals, &counters),
+            DeliveryTotals {
+                sent: 10,
+                retried: 0,
+                dropped: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! End-of-day digest of what flowed through the bridge.
+//!
+//! Runs on `DIGEST_CRON` (or `POST /admin/digest/run`) and posts one card
+//! per team, to the team's routes, with the issues created, completed and
+//! escalated since midnight in `TIMEZONE`. Built from the event log rather than the Linear
+//! API, so it shows exactly what the bridge saw. Teams without activity
+//! get no card.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveTime, Utc};
+use tracing::{error, info};
+
+use crate::jobs::send_to_team;
+use crate::lark::markdown::escape;
+use crate::reports;
+use crate::storage::Event;
+use crate::{AppState, LarkCard, LarkHeader, LarkMessage, LarkTitle};
+
+/// Longest list per section before the rest collapse into "+N more".
+const MAX_LISTED: usize = 15;
+
+#[derive(Default)]
+struct TeamDigest<'a> {
+    created: Vec<&'a Event>,
+    completed: Vec<&'a Event>,
+    escalated: Vec<&'a Event>,
+}
+
+impl TeamDigest<'_> {
+    fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.completed.is_empty() && self.escalated.is_empty()
+    }
+}
+
+/// Sends today's digest cards and returns how many went out.
+pub async fn run(state: &AppState) -> usize {
+    let now = Utc::now();
+    let midnight = now
+        .with_timezone(&state.timezone)
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(state.timezone)
+        .earliest()
+        .map_or(now, |at| at.with_timezone(&Utc));
+
+    let events = match state.storage.events().between(midnight, now).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("daily digest failed to read the event log: {e}");
+            return 0;
+        }
+    };
+
+    let mut by_team: BTreeMap<&str, TeamDigest> = BTreeMap::new();
+    let issue_events = events
+        .iter()
+        .filter(|e| e.kind == "Issue" && e.disposition != "ignored");
+    for event in issue_events {
+        let digest = by_team
+            .entry(event.team_key.as_deref().unwrap_or(reports::NO_TEAM))
+            .or_default();
+        let lists = [
+            (event.action == "create", &mut digest.created),
+            (event.completed(), &mut digest.completed),
+            (event.escalated(), &mut digest.escalated),
+        ];
+        for (_, list) in lists.into_iter().filter(|(matches, _)| *matches) {
+            // One line per issue, showing its latest title.
+            list.retain(|e| e.issue_id != event.issue_id);
+            list.push(event);
+        }
+    }
+
+    by_team.retain(|_, digest| !digest.is_empty());
+    if by_team.is_empty() {
+        info!("daily digest: no activity today");
+    }
+
+    let mut sent = 0;
+    for (team, digest) in &by_team {
+        if send_to_team(state, vec![team], &build_card(team, digest)).await > 0 {
+            info!("daily digest sent for {team}");
+            sent += 1;
+        }
+    }
+    sent
+}
+
+fn build_card(team: &str, digest: &TeamDigest) -> LarkMessage {
+    let sections = [
+        ("Created", &digest.created),
+        ("Completed", &digest.completed),
+        ("Escalated", &digest.escalated),
+    ];
+
+    let elements = sections
+        .iter()
+        .filter(|(_, events)| !events.is_empty())
+        .map(|(label, events)| {
+            let mut lines: Vec<String> = events
+                .iter()
+                .take(MAX_LISTED)
+                .map(|e| format!("- {} {}", escape(&e.identifier), escape(&e.title)))
+                .collect();
+            if events.len() > MAX_LISTED {
+                lines.push(format!("+{} more", events.len() - MAX_LISTED));
+            }
+            serde_json::json!({
+                "tag": "div",
+                "text": {
+                    "tag": "lark_md",
+                    "content": format!("**{label} ({})**\n{}", events.len(), lines.join("\n")),
+                }
+            })
+        })
+        .collect();
+
+    LarkMessage {
+        msg_type: "interactive",
+        card: LarkCard {
+            config: None,
+            header: LarkHeader {
+                template: "blue",
+                title: LarkTitle {
+                    content: format!(
+                        "[Linear] Daily digest: {team} · {} created · {} completed · {} escalated",
+                        digest.created.len(),
+                        digest.completed.len(),
+                        digest.escalated.len()
+                    ),
+                    tag: "plain_text",
+                    i18n: None,
+                },
+            },
+            elements,
+            i18n_elements: None,
+        },
+    }
+}
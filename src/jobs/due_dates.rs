@@ -0,0 +1,306 @@
+//! Reminder cards for issues that are due soon or overdue.
+//!
+//! Runs on `DUE_REMINDER_CRON` and posts one card per team, to the team's
+//! routes, listing the team's open issues due within `DUE_REMINDER_DAYS`
+//! grouped by assignee. A per-issue marker in the local store keeps the
+//! same issue from being listed every day unless `DUE_REMINDER_REPEAT=true`.
+//! Issues beyond a card's limit are not marked, so later runs list them.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+use tracing::{error, info};
+
+use crate::jobs::send_to_team;
+use crate::lark::markdown::escape;
+use crate::linear::api::DueIssue;
+use crate::storage::DueMarker;
+use crate::{AppState, LarkCard, LarkHeader, LarkMessage, LarkTitle};
+
+/// Longest list per card, earliest due first, before the rest collapse into
+/// "+N more".
+const MAX_LISTED: usize = 20;
+
+pub struct DueDateConfig {
+    /// Team keys to sweep; empty means every team the key can see.
+    pub team_keys: Vec<String>,
+    pub days_ahead: u64,
+    /// Remind about the same issue every run instead of once per due date.
+    pub repeat: bool,
+}
+
+pub async fn run(state: &AppState, config: &DueDateConfig) {
+    let Some(linear) = &state.linear else {
+        return;
+    };
+
+    let today = local_today(Utc::now(), state.timezone);
+    let horizon = today + Days::new(config.days_ahead);
+
+    let issues = match linear
+        .issues_due_by(&config.team_keys, &horizon.to_string())
+        .await
+    {
+        Ok(issues) => issues,
+        Err(e) => {
+            error!("due date sweep failed: {e}");
+            return;
+        }
+    };
+
+    let reminders = state.storage.reminders();
+    // Issues that were completed or lost their due date are forgotten, so
+    // the markers only ever cover currently open issues.
+    let open = issues.iter().map(|i| i.id.clone()).collect();
+    if let Err(e) = reminders.forget_due_except(open).await {
+        error!("failed to prune due date reminders: {e}");
+    }
+    let reminded = match reminders.due_markers().await {
+        Ok(reminded) => reminded,
+        Err(e) => {
+            error!("failed to load due date reminders: {e}");
+            return;
+        }
+    };
+
+    let mut by_team: BTreeMap<String, Vec<&DueIssue>> = BTreeMap::new();
+    for issue in &issues {
+        if !already_reminded(reminded.get(&issue.id), issue, today, config.repeat) {
+            by_team
+                .entry(issue.team.key.clone())
+                .or_default()
+                .push(issue);
+        }
+    }
+
+    if by_team.is_empty() {
+        info!("due date sweep: nothing new to remind about");
+    }
+
+    for (team, mut issues) in by_team {
+        issues.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+        let listed = &issues[..issues.len().min(MAX_LISTED)];
+        let name = &issues[0].team.name;
+        let card = build_card(name, listed, issues.len(), today);
+        if send_to_team(state, vec![&team, name], &card).await == 0 {
+            continue;
+        }
+        info!(
+            "due date reminder sent for {team} ({} of {} issues)",
+            listed.len(),
+            issues.len()
+        );
+        for issue in listed {
+            if let Err(e) = reminders.mark_due(&issue.id, &issue.due_date, today).await {
+                error!(
+                    "failed to persist due date reminder for {}: {e}",
+                    issue.identifier
+                );
+            }
+        }
+    }
+}
+
+/// The date in `tz` at `now`, which "due today" and "overdue" go by.
+fn local_today(now: DateTime<Utc>, tz: Tz) -> NaiveDate {
+    now.with_timezone(&tz).date_naive()
+}
+
+/// Whether `issue` was listed today already, or for its current due date
+/// unless reminders `repeat` every run.
+fn already_reminded(
+    marker: Option<&DueMarker>,
+    issue: &DueIssue,
+    today: NaiveDate,
+    repeat: bool,
+) -> bool {
+    marker.is_some_and(|m| m.on == today || (!repeat && m.due_date == issue.due_date))
+}
+
+/// Lists `issues`, the first of `total` due, grouped by assignee.
+fn build_card(team: &str, issues: &[&DueIssue], total: usize, today: NaiveDate) -> LarkMessage {
+    let is_overdue = |issue: &DueIssue| {
+        NaiveDate::parse_from_str(&issue.due_date, "%Y-%m-%d").is_ok_and(|due| due < today)
+    };
+    let any_overdue = issues.iter().any(|i| is_overdue(i));
+
+    // Unassigned sorts last.
+    let mut by_assignee: BTreeMap<(bool, String), Vec<&DueIssue>> = BTreeMap::new();
+    for issue in issues {
+        let key = match &issue.assignee {
+            Some(user) => (false, user.name.clone()),
+            None => (true, "Unassigned".to_string()),
+        };
+        by_assignee.entry(key).or_default().push(issue);
+    }
+
+    let mut elements = Vec::new();
+    for ((_, assignee), mut issues) in by_assignee {
+        issues.sort_by(|a, b| a.due_date.cmp(&b.due_date));
+        let lines: Vec<String> = issues
+            .iter()
+            .map(|issue| {
+                let due = if is_overdue(issue) {
+                    format!("<font color='red'>overdue since {}</font>", issue.due_date)
+                } else if issue.due_date == today.to_string() {
+                    "due today".to_string()
+                } else {
+                    format!("due {}", issue.due_date)
+                };
+                format!(
+                    "- [{}]({}) {} · {due}",
+                    escape(&issue.identifier),
+                    issue.url,
+                    escape(&issue.title)
+                )
+            })
+            .collect();
+
+        elements.push(serde_json::json!({
+            "tag": "div",
+            "text": {
+                "tag": "lark_md",
+                "content": format!("**{}**\n{}", escape(&assignee), lines.join("\n")),
+            }
+        }));
+    }
+    if total > issues.len() {
+        elements.push(serde_json::json!({
+            "tag": "div",
+            "text": {
+                "tag": "lark_md",
+                "content": format!("+{} more", total - issues.len()),
+            }
+        }));
+    }
+
+    LarkMessage {
+        msg_type: "interactive",
+        card: LarkCard {
+            config: None,
+            header: LarkHeader {
+                template: if any_overdue { "red" } else { "orange" },
+                title: LarkTitle {
+                    content: format!("[Linear] Due soon: {team} ({total})"),
+                    tag: "plain_text",
+                    i18n: None,
+                },
+            },
+            elements,
+            i18n_elements: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear::api::{TeamRef, User};
+
+    fn issue(identifier: &str, due_date: &str, assignee: Option<&str>) -> DueIssue {
+        DueIssue {
+            id: format!("id-{identifier}"),
+            identifier: identifier.into(),
+            title: format!("{identifier} title"),
+            url: format!("https://linear.app/acme/issue/{identifier}"),
+            due_date: due_date.into(),
+            assignee: assignee.map(|name| User { name: name.into() }),
+            team: TeamRef {
+                key: "ENG".into(),
+                name: "Engineering".into(),
+            },
+        }
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn marker(due_date: &str, on: &str) -> DueMarker {
+        DueMarker {
+            due_date: due_date.into(),
+            on: day(on),
+        }
+    }
+
+    fn contents(card: &LarkMessage) -> Vec<&str> {
+        card.card
+            .elements
+            .iter()
+            .map(|e| e["text"]["content"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn today_is_the_date_in_the_configured_timezone() {
+        let now = "2026-10-14T20:00:00Z".parse().unwrap();
+        assert_eq!(local_today(now, chrono_tz::UTC), day("2026-10-14"));
+        assert_eq!(
+            local_today(now, chrono_tz::Asia::Shanghai),
+            day("2026-10-15")
+        );
+    }
+
+    #[test]
+    fn issues_are_reminded_once_per_due_date() {
+        let today = day("2026-10-14");
+        let due = issue("ENG-1", "2026-10-16", None);
+        assert!(!already_reminded(None, &due, today, false));
+
+        let yesterday = marker("2026-10-16", "2026-10-13");
+        assert!(already_reminded(Some(&yesterday), &due, today, false));
+        assert!(!already_reminded(Some(&yesterday), &due, today, true));
+
+        let moved = marker("2026-10-15", "2026-10-13");
+        assert!(!already_reminded(Some(&moved), &due, today, false));
+    }
+
+    #[test]
+    fn repeated_reminders_still_come_once_a_day() {
+        let due = issue("ENG-1", "2026-10-16", None);
+        let earlier_today = marker("2026-10-16", "2026-10-14");
+        assert!(already_reminded(
+            Some(&earlier_today),
+            &due,
+            day("2026-10-14"),
+            true
+        ));
+    }
+
+    #[test]
+    fn overdue_issues_are_red_and_today_is_called_out() {
+        let issues = [
+            issue("ENG-1", "2026-10-12", Some("Ann")),
+            issue("ENG-2", "2026-10-14", Some("Ann")),
+            issue("ENG-3", "2026-10-20", None),
+        ];
+        let listed: Vec<&DueIssue> = issues.iter().collect();
+        let card = build_card("Engineering", &listed, 3, day("2026-10-14"));
+
+        assert_eq!(card.card.header.template, "red");
+        let contents = contents(&card);
+        assert!(contents[0].starts_with("**Ann**"));
+        assert!(contents[0].contains("<font color='red'>overdue since 2026-10-12</font>"));
+        assert!(contents[0].contains("ENG-2 title · due today"));
+        assert!(contents[1].starts_with("**Unassigned**"));
+        assert!(contents[1].contains("due 2026-10-20"));
+
+        let upcoming: Vec<&DueIssue> = issues[1..].iter().collect();
+        let card = build_card("Engineering", &upcoming, 2, day("2026-10-14"));
+        assert_eq!(card.card.header.template, "orange");
+    }
+
+    #[test]
+    fn cards_past_the_limit_say_how_many_more() {
+        let issues = [issue("ENG-1", "2026-10-14", None)];
+        let listed: Vec<&DueIssue> = issues.iter().collect();
+        let card = build_card("Engineering", &listed, 6, day("2026-10-14"));
+
+        assert_eq!(contents(&card).last(), Some(&"+5 more"));
+        assert_eq!(
+            card.card.header.title.content,
+            "[Linear] Due soon: Engineering (6)"
+        );
+    }
+}
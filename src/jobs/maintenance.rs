@@ -0,0 +1,45 @@
+//! Store maintenance: retention deletes and an incremental vacuum.
+//!
+//! Runs on `MAINTENANCE_CRON` (daily by default) or on demand through
+//! `POST /admin/maintenance/run`.
+
+use tracing::{error, info};
+
+use crate::AppState;
+use crate::metrics;
+use crate::storage::{MaintenanceReport, Retention};
+
+pub async fn run(state: &AppState, retention: &Retention) -> Option<MaintenanceReport> {
+    match state.storage.maintain(retention).await {
+        Ok(report) => {
+            info!(
+                "store maintenance removed {} events, {} dedup keys, {} dead letters, {} bot messages and {} card issues, reclaimed {} pages",
+                report.events_deleted,
+                report.dedup_deleted,
+                report.dead_letters_deleted,
+                report.lark_messages_deleted,
+                report.issue_cards_deleted,
+                report.pages_reclaimed
+            );
+            let tables = [
+                ("events", report.events_deleted),
+                ("dedup", report.dedup_deleted),
+                ("dead_letters", report.dead_letters_deleted),
+                ("lark_messages", report.lark_messages_deleted),
+                ("issue_cards", report.issue_cards_deleted),
+            ];
+            for (table, deleted) in tables {
+                state.metrics.add(
+                    metrics::MAINTENANCE_DELETED,
+                    &[("table", table)],
+                    deleted as u64,
+                );
+            }
+            Some(report)
+        }
+        Err(e) => {
+            error!("store maintenance failed: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Scheduled jobs that run independently of incoming webhooks.
+
+pub mod counters;
+pub mod digest;
+pub mod due_dates;
+pub mod maintenance;
+pub mod sla;
+pub mod stale;
+pub mod weekly;
+
+use std::{future::Future, time::Duration};
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use cron::Schedule;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::AppState;
+use crate::routes::RouteKeys;
+
+/// Runs `job` at every upcoming time of `schedule`, evaluated in `tz`,
+/// until the process exits. Runs never overlap: the next one is scheduled
+/// after the previous one finished.
+pub fn spawn<F, Fut>(name: &'static str, schedule: Schedule, tz: Tz, mut job: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    tokio::spawn(async move {
+        loop {
+            let Some(next) = schedule.upcoming(tz).next() else {
+                warn!("{name}: schedule has no upcoming run, stopping");
+                return;
+            };
+            info!("{name}: next run at {next}");
+
+            let wait = (next.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            info!("{name}: running");
+            job().await;
+        }
+    });
+}
+
+/// Runs `job` every `period`, starting right away, until the process
+/// exits. Like [`spawn`], runs never overlap.
+pub fn spawn_interval<F, Fut>(name: &'static str, period: Duration, mut job: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            info!("{name}: running");
+            job().await;
+        }
+    });
+}
+
+/// Posts a job's `card` about a team, known by every name in `teams`, to
+/// the destinations the team's route gives it, falling back to
+/// `LARK_WEBHOOK_URL` like webhook cards do. Returns how many took it.
+pub async fn send_to_team(state: &AppState, teams: Vec<&str>, card: &impl Serialize) -> usize {
+    let keys = RouteKeys {
+        teams,
+        ..Default::default()
+    };
+    let destinations = state.routes.resolve(&keys, state.default_webhook());
+    if destinations.is_empty() {
+        warn!(
+            "no route for team {} and no LARK_WEBHOOK_URL, not sending",
+            keys.teams.join("/")
+        );
+    }
+
+    let mut sent = 0;
+    for destination in &destinations {
+        match crate::send_to_lark_at(state, &destination.url, card).await {
+            Ok(_) => sent += 1,
+            Err(e) => crate::report_send_failure(state, &e).await,
+        }
+    }
+    sent
+}
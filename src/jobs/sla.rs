@@ -0,0 +1,283 @@
+//! SLA breach monitor that does not wait for webhooks.
+//!
+//! Polls Linear every `SLA_MONITOR_INTERVAL_SECS` for open issues whose
+//! SLA breaches within `SLA_LOOKAHEAD_MINUTES` or already has, and posts a
+//! red card per issue to `SLA_ALERT_WEBHOOK_URL` (the main webhook when
+//! unset). Every issue is alerted once when at risk and once more when it
+//! breaches; with `SLA_REALERT=hourly` breached issues are repeated every
+//! hour until resolved.
+
+use std::{collections::HashSet, str::FromStr};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tracing::{error, info};
+
+use crate::lark::markdown::escape;
+use crate::linear::api::SlaIssue;
+use crate::storage::SlaMarker;
+use crate::{AppState, LarkCard, LarkHeader, LarkMessage, LarkTitle};
+
+const REALERT_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RealertPolicy {
+    Once,
+    Hourly,
+}
+
+impl FromStr for RealertPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "once" => Ok(Self::Once),
+            "hourly" => Ok(Self::Hourly),
+            other => Err(format!("expected once or hourly, got {other:?}")),
+        }
+    }
+}
+
+pub struct SlaConfig {
+    /// Team keys to watch; empty means every team the key can see.
+    pub team_keys: Vec<String>,
+    pub lookahead: TimeDelta,
+    pub realert: RealertPolicy,
+    pub webhook_url: Option<String>,
+}
+
+pub async fn run(state: &AppState, config: &SlaConfig) {
+    let Some(linear) = &state.linear else {
+        return;
+    };
+
+    let issues = match linear.open_issues_with_sla(&config.team_keys).await {
+        Ok(issues) => issues,
+        Err(e) => {
+            error!("sla monitor failed: {e}");
+            return;
+        }
+    };
+
+    let now = Utc::now();
+    let watched: Vec<(&SlaIssue, DateTime<Utc>)> = issues
+        .iter()
+        .filter_map(|i| i.sla_breaches_at.map(|at| (i, at)))
+        .filter(|(_, at)| *at <= now + config.lookahead)
+        .collect();
+
+    let alerts = state.storage.alerts();
+    // Markers of resolved or no longer urgent issues are dropped, so a
+    // later breach of the same issue alerts again.
+    let ids: HashSet<String> = watched.iter().map(|(i, _)| i.id.clone()).collect();
+    if let Err(e) = alerts.forget_sla_except(ids).await {
+        error!("failed to prune sla alert markers: {e}");
+    }
+    let markers = match alerts.sla_markers().await {
+        Ok(markers) => markers,
+        Err(e) => {
+            error!("failed to load sla alert markers: {e}");
+            return;
+        }
+    };
+
+    for (issue, breaches_at) in watched {
+        if !alert_due(markers.get(&issue.id), breaches_at, now, config.realert) {
+            continue;
+        }
+
+        let breached = breaches_at <= now;
+        let card = build_card(state, issue, breaches_at, now);
+        let url = config
+            .webhook_url
+            .as_deref()
+            .unwrap_or(&state.lark_webhook_url);
+        match crate::send_to_lark_at(state, url, &card).await {
+            Ok(_) => {
+                info!(
+                    "sla alert sent for {} (breached: {breached})",
+                    issue.identifier
+                );
+                let marker = SlaMarker {
+                    breaches_at,
+                    breached,
+                    alerted_at: now,
+                };
+                if let Err(e) = alerts.mark_sla(&issue.id, marker).await {
+                    error!("failed to persist sla alert for {}: {e}", issue.identifier);
+                }
+            }
+            Err(e) => crate::report_send_failure(state, &e).await,
+        }
+    }
+}
+
+fn alert_due(
+    marker: Option<&SlaMarker>,
+    breaches_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    realert: RealertPolicy,
+) -> bool {
+    let breached = breaches_at <= now;
+    match marker {
+        None => true,
+        Some(m) if m.breaches_at != breaches_at => true,
+        Some(m) if breached && !m.breached => true,
+        Some(m) => {
+            breached && realert == RealertPolicy::Hourly && now - m.alerted_at >= REALERT_INTERVAL
+        }
+    }
+}
+
+fn format_minutes(delta: TimeDelta) -> String {
+    match delta.num_minutes() {
+        m if m >= 60 => format!("{}h {}m", m / 60, m % 60),
+        m => format!("{m}m"),
+    }
+}
+
+fn build_card(
+    state: &AppState,
+    issue: &SlaIssue,
+    breaches_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> LarkMessage {
+    let local = breaches_at
+        .with_timezone(&state.timezone)
+        .format("%Y-%m-%d %H:%M %Z");
+    let (status, when) = if breaches_at <= now {
+        (
+            "SLA breached",
+            format!(
+                "breached {} ago ({local})",
+                format_minutes(now - breaches_at)
+            ),
+        )
+    } else {
+        (
+            "SLA at risk",
+            format!(
+                "breaches in {} ({local})",
+                format_minutes(breaches_at - now)
+            ),
+        )
+    };
+    let assignee = issue
+        .assignee
+        .as_ref()
+        .map(|u| u.name.as_str())
+        .unwrap_or("Unassigned");
+
+    LarkMessage {
+        msg_type: "interactive",
+        card: LarkCard {
+            config: None,
+            header: LarkHeader {
+                template: "red",
+                title: LarkTitle {
+                    content: format!("[Linear] {status}: {}", issue.identifier),
+                    tag: "plain_text",
+                    i18n: None,
+                },
+            },
+            elements: vec![serde_json::json!({
+                "tag": "div",
+                "text": {
+                    "tag": "lark_md",
+                    "content": format!(
+                        "[{}]({}) {}\n{} · {} · {when}",
+                        escape(&issue.identifier),
+                        issue.url,
+                        escape(&issue.title),
+                        escape(&issue.team.name),
+                        escape(assignee)
+                    ),
+                }
+            })],
+            i18n_elements: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ts: &str) -> DateTime<Utc> {
+        ts.parse().unwrap()
+    }
+
+    fn marker(breaches_at: &str, breached: bool, alerted_at: &str) -> SlaMarker {
+        SlaMarker {
+            breaches_at: at(breaches_at),
+            breached,
+            alerted_at: at(alerted_at),
+        }
+    }
+
+    const NOW: &str = "2026-10-14T12:00:00Z";
+
+    #[test]
+    fn alerts_new_issues_and_reset_slas() {
+        let breaches = at("2026-10-14T12:30:00Z");
+        assert!(alert_due(None, breaches, at(NOW), RealertPolicy::Once));
+
+        let old = marker("2026-10-13T12:30:00Z", true, "2026-10-13T13:00:00Z");
+        assert!(alert_due(
+            Some(&old),
+            breaches,
+            at(NOW),
+            RealertPolicy::Once
+        ));
+    }
+
+    #[test]
+    fn warns_once_then_alerts_again_on_breach() {
+        let breaches = "2026-10-14T12:30:00Z";
+        let warned = marker(breaches, false, "2026-10-14T11:45:00Z");
+        assert!(!alert_due(
+            Some(&warned),
+            at(breaches),
+            at(NOW),
+            RealertPolicy::Once
+        ));
+        assert!(alert_due(
+            Some(&warned),
+            at(breaches),
+            at("2026-10-14T12:31:00Z"),
+            RealertPolicy::Once
+        ));
+    }
+
+    #[test]
+    fn hourly_policy_repeats_breaches_only() {
+        let breaches = "2026-10-14T10:00:00Z";
+        let alerted = marker(breaches, true, "2026-10-14T11:30:00Z");
+        let later = at("2026-10-14T12:30:00Z");
+        assert!(!alert_due(
+            Some(&alerted),
+            at(breaches),
+            at(NOW),
+            RealertPolicy::Hourly
+        ));
+        assert!(alert_due(
+            Some(&alerted),
+            at(breaches),
+            later,
+            RealertPolicy::Hourly
+        ));
+        assert!(!alert_due(
+            Some(&alerted),
+            at(breaches),
+            later,
+            RealertPolicy::Once
+        ));
+
+        let warned = marker("2026-10-14T14:00:00Z", false, "2026-10-14T10:00:00Z");
+        assert!(!alert_due(
+            Some(&warned),
+            at("2026-10-14T14:00:00Z"),
+            at(NOW),
+            RealertPolicy::Hourly
+        ));
+    }
+}
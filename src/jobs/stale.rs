@@ -0,0 +1,225 @@
+//! Nudges for issues stuck in progress.
+//!
+//! Runs on `STALE_ISSUES_CRON` (typically weekly) and posts one card per
+//! team, to the team's routes, listing started issues that have not been
+//! updated for `STALE_ISSUES_DAYS`, most idle first. Individual issues can
+//! be snoozed through `POST /admin/mute` so the list stays actionable.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use tracing::{error, info};
+
+use crate::jobs::send_to_team;
+use crate::lark::markdown::escape;
+use crate::linear::api::IdleIssue;
+use crate::{AppState, LarkCard, LarkHeader, LarkMessage, LarkTitle};
+
+/// Longest list per card before the rest collapse into "+N more".
+const MAX_LISTED: usize = 20;
+
+pub struct StaleConfig {
+    /// Team keys to audit; empty means every team the key can see.
+    pub team_keys: Vec<String>,
+    pub idle_days: i64,
+}
+
+pub async fn run(state: &AppState, config: &StaleConfig) {
+    let Some(linear) = &state.linear else {
+        return;
+    };
+
+    let now = Utc::now();
+    let today = now.with_timezone(&state.timezone).date_naive();
+    let cutoff = now - Duration::days(config.idle_days);
+
+    let issues = match linear
+        .started_issues_idle_since(
+            &config.team_keys,
+            &cutoff.to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+        .await
+    {
+        Ok(issues) => issues,
+        Err(e) => {
+            error!("stale issue audit failed: {e}");
+            return;
+        }
+    };
+
+    let muted = match state.storage.reminders().muted(today).await {
+        Ok(muted) => muted,
+        Err(e) => {
+            error!("failed to load stale issue mutes: {e}");
+            return;
+        }
+    };
+
+    let by_team = by_team(&issues, &muted, now, config.idle_days);
+    if by_team.is_empty() {
+        info!("stale issue audit: nothing stale");
+    }
+
+    for (team, issues) in by_team {
+        let name = &issues[0].1.team.name;
+        let card = build_card(name, &issues);
+        if send_to_team(state, vec![&team, name], &card).await > 0 {
+            info!(
+                "stale issues card sent for {team} ({} issues)",
+                issues.len()
+            );
+        }
+    }
+}
+
+/// The issues that are not muted, with their idle days, per team key and
+/// most idle first. Unparsable update times count as `idle_days`.
+fn by_team<'a>(
+    issues: &'a [IdleIssue],
+    muted: &HashSet<String>,
+    now: DateTime<Utc>,
+    idle_days: i64,
+) -> BTreeMap<String, Vec<(i64, &'a IdleIssue)>> {
+    let mut by_team: BTreeMap<String, Vec<(i64, &IdleIssue)>> = BTreeMap::new();
+    for issue in issues {
+        if muted.contains(&issue.identifier) {
+            continue;
+        }
+        let idle = DateTime::parse_from_rfc3339(&issue.updated_at)
+            .map(|at| (now - at.with_timezone(&Utc)).num_days())
+            .unwrap_or(idle_days);
+        by_team
+            .entry(issue.team.key.clone())
+            .or_default()
+            .push((idle, issue));
+    }
+    for issues in by_team.values_mut() {
+        issues.sort_by_key(|(idle, _)| Reverse(*idle));
+    }
+    by_team
+}
+
+fn build_card(team: &str, issues: &[(i64, &IdleIssue)]) -> LarkMessage {
+    let mut lines: Vec<String> = issues
+        .iter()
+        .take(MAX_LISTED)
+        .map(|(idle_days, issue)| {
+            let assignee = issue
+                .assignee
+                .as_ref()
+                .map(|u| u.name.as_str())
+                .unwrap_or("Unassigned");
+            format!(
+                "- [{}]({}) {} · {} · idle {idle_days}d",
+                escape(&issue.identifier),
+                issue.url,
+                escape(&issue.title),
+                escape(assignee)
+            )
+        })
+        .collect();
+    if issues.len() > MAX_LISTED {
+        lines.push(format!("+{} more", issues.len() - MAX_LISTED));
+    }
+
+    LarkMessage {
+        msg_type: "interactive",
+        card: LarkCard {
+            config: None,
+            header: LarkHeader {
+                template: "grey",
+                title: LarkTitle {
+                    content: format!("[Linear] Stale issues: {team} ({})", issues.len()),
+                    tag: "plain_text",
+                    i18n: None,
+                },
+            },
+            elements: vec![serde_json::json!({
+                "tag": "div",
+                "text": {
+                    "tag": "lark_md",
+                    "content": lines.join("\n"),
+                }
+            })],
+            i18n_elements: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear::api::TeamRef;
+
+    fn issue(identifier: &str, team: &str, updated_at: &str) -> IdleIssue {
+        IdleIssue {
+            identifier: identifier.into(),
+            title: format!("{identifier} title"),
+            url: format!("https://linear.app/acme/issue/{identifier}"),
+            updated_at: updated_at.into(),
+            assignee: None,
+            team: TeamRef {
+                key: team.into(),
+                name: team.into(),
+            },
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2026-10-14T12:00:00Z".parse().unwrap()
+    }
+
+    fn listed(issues: &[(i64, &IdleIssue)]) -> Vec<(i64, String)> {
+        issues
+            .iter()
+            .map(|(idle, issue)| (*idle, issue.identifier.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn most_idle_issues_come_first_per_team() {
+        let issues = [
+            issue("ENG-1", "ENG", "2026-10-04T12:00:00Z"),
+            issue("OPS-1", "OPS", "2026-10-01T12:00:00Z"),
+            issue("ENG-2", "ENG", "2026-09-14T12:00:00Z"),
+            issue("ENG-3", "ENG", "not a time"),
+        ];
+        let by_team = by_team(&issues, &HashSet::new(), now(), 7);
+
+        assert_eq!(
+            listed(&by_team["ENG"]),
+            [
+                (30, "ENG-2".into()),
+                (10, "ENG-1".into()),
+                (7, "ENG-3".into())
+            ]
+        );
+        assert_eq!(listed(&by_team["OPS"]), [(13, "OPS-1".into())]);
+    }
+
+    #[test]
+    fn muted_issues_are_left_out() {
+        let issues = [
+            issue("ENG-1", "ENG", "2026-10-04T12:00:00Z"),
+            issue("OPS-1", "OPS", "2026-10-01T12:00:00Z"),
+        ];
+        let muted = HashSet::from(["OPS-1".to_string()]);
+        let by_team = by_team(&issues, &muted, now(), 7);
+
+        assert_eq!(by_team.keys().collect::<Vec<_>>(), ["ENG"]);
+    }
+
+    #[test]
+    fn long_lists_are_cut() {
+        let issues: Vec<IdleIssue> = (0..MAX_LISTED + 5)
+            .map(|i| issue(&format!("ENG-{i}"), "ENG", "2026-10-04T12:00:00Z"))
+            .collect();
+        let by_team = by_team(&issues, &HashSet::new(), now(), 7);
+        let card = build_card("ENG", &by_team["ENG"]);
+
+        let content = card.card.elements[0]["text"]["content"].as_str().unwrap();
+        assert_eq!(content.lines().count(), MAX_LISTED + 1);
+        assert!(content.ends_with("+5 more"));
+    }
+}
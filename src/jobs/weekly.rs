@@ -0,0 +1,116 @@
+//! Monday summary of the previous week, per team.
+//!
+//! Runs on `WEEKLY_SUMMARY_CRON` and posts to each team's routes. The
+//! numbers come from [`crate::reports`] over the event log, so like the
+//! daily digest they only cover what flowed through the bridge.
+
+use chrono::{NaiveDate, NaiveTime, TimeDelta, Utc};
+use tracing::{error, info};
+
+use crate::jobs::send_to_team;
+use crate::lark::markdown::escape;
+use crate::reports::{self, WeeklySummary};
+use crate::{AppState, LarkCard, LarkHeader, LarkMessage, LarkTitle};
+
+pub async fn run(state: &AppState) {
+    let today = Utc::now().with_timezone(&state.timezone).date_naive();
+    let (from, to) = reports::last_week(today);
+    let start_of = |day: NaiveDate| {
+        day.and_time(NaiveTime::MIN)
+            .and_local_timezone(state.timezone)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+    };
+    let (Some(start), Some(end)) = (start_of(from), start_of(to)) else {
+        error!(
+            "weekly summary: cannot resolve midnight of {from} in {}",
+            state.timezone
+        );
+        return;
+    };
+
+    let events = match state.storage.events().between(start, end).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("weekly summary failed to read the event log: {e}");
+            return;
+        }
+    };
+
+    let summaries = reports::weekly_summaries(&events);
+    if summaries.is_empty() {
+        info!("weekly summary: no activity last week");
+    }
+
+    for (team, summary) in &summaries {
+        let card = build_card(team, from, summary);
+        if send_to_team(state, vec![team], &card).await > 0 {
+            info!("weekly summary sent for {team}");
+        }
+    }
+}
+
+fn field(label: &str, value: impl std::fmt::Display) -> serde_json::Value {
+    serde_json::json!({
+        "is_short": true,
+        "text": {
+            "tag": "lark_md",
+            "content": format!("**{label}:** {value}"),
+        }
+    })
+}
+
+fn format_duration(duration: TimeDelta) -> String {
+    let hours = duration.num_hours();
+    match (hours / 24, hours % 24) {
+        (0, 0) => format!("{}m", duration.num_minutes()),
+        (0, h) => format!("{h}h"),
+        (d, 0) => format!("{d}d"),
+        (d, h) => format!("{d}d {h}h"),
+    }
+}
+
+fn build_card(team: &str, week_of: NaiveDate, summary: &WeeklySummary) -> LarkMessage {
+    let cycle_time = summary
+        .median_cycle_time
+        .map_or_else(|| "–".to_string(), format_duration);
+    let top_assignees = if summary.top_assignees.is_empty() {
+        "–".to_string()
+    } else {
+        summary
+            .top_assignees
+            .iter()
+            .map(|(name, count)| format!("{} ({count})", escape(name)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let elements = vec![serde_json::json!({
+        "tag": "div",
+        "fields": [
+            field("Created", summary.created),
+            field("Completed", summary.completed),
+            field("Backlog change", format!("{:+}", summary.backlog_change)),
+            field("Median cycle time", cycle_time),
+            field("Escalated", summary.escalated),
+            field("Top assignees", top_assignees),
+        ]
+    })];
+
+    LarkMessage {
+        msg_type: "interactive",
+        card: LarkCard {
+            config: None,
+            header: LarkHeader {
+                template: "indigo",
+                title: LarkTitle {
+                    content: format!("[Linear] Week of {week_of}: {team}"),
+                    tag: "plain_text",
+                    i18n: None,
+                },
+            },
+            elements,
+            i18n_elements: None,
+        },
+    }
+}
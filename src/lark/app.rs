@@ -0,0 +1,404 @@
+//! A Lark app (`LARK_APP_ID` / `LARK_APP_SECRET`) calling the Open
+//! Platform API as itself, for contact lookups and bot messages.
+//!
+//! Calls authenticate with a tenant access token, fetched on first use and
+//! renewed shortly before it expires, or when Lark rejects it. One fetch
+//! runs at a time; concurrent callers wait for its token. Failed fetches
+//! are retried with backoff, except when Lark rejects the app credentials:
+//! then every call fails fast until a restart with fixed ones.
+//!
+//! Bot messages are built in the incoming-webhook format the rest of the
+//! bridge renders (`msg_type` plus `card` or `content`) and converted here,
+//! so every card can go to a webhook or a chat alike.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tracing::{error, warn};
+
+use crate::lark::errors::{self, ErrorClass, LarkError};
+
+/// Tenant tokens live two hours; they are renewed this long before expiry.
+const TOKEN_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Token fetches per call before the failure is returned.
+const TOKEN_ATTEMPTS: u32 = 3;
+
+/// Wait before the second token fetch, doubled for each one after.
+const TOKEN_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Codes for a missing, invalid or expired access token: the call is
+/// retried once with a fresh one.
+const TOKEN_REJECTED: &[i64] = &[99991661, 99991663, 99991668];
+
+/// Codes for a message that can no longer be edited: recalled (230011),
+/// not the bot's (230071), edited too often (230072), too old (230075) or
+/// deleted (230110).
+const MESSAGE_GONE: &[i64] = &[230011, 230071, 230072, 230075, 230110];
+
+pub struct LarkApp {
+    /// `https://open.larksuite.com`, or `https://open.feishu.cn` for Feishu.
+    base_url: String,
+    app_id: String,
+    app_secret: String,
+    /// Tenant access token and when to stop using it. Held across the
+    /// fetch, so only one runs at a time.
+    token: tokio::sync::Mutex<Option<(String, Instant)>>,
+    /// Why Lark refused the app credentials, once it has.
+    credentials_rejected: Mutex<Option<LarkError>>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    tenant_access_token: String,
+    /// Seconds.
+    expire: u64,
+}
+
+#[derive(Deserialize)]
+struct MessageResponse {
+    data: MessageData,
+}
+
+#[derive(Deserialize)]
+struct MessageData {
+    message_id: String,
+}
+
+/// Whether a failed [`LarkApp::update_message`] means the message is beyond
+/// editing, so a new one has to take its place. Other failures are worth
+/// retrying or reporting instead, since a new message would duplicate it.
+pub fn message_gone(err: &LarkError) -> bool {
+    err.code.is_some_and(|code| MESSAGE_GONE.contains(&code))
+}
+
+/// Whether `target` names a Lark chat (`oc_…`) rather than a webhook URL.
+pub fn is_chat_id(target: &str) -> bool {
+    target.starts_with("oc_")
+}
+
+impl LarkApp {
+    pub fn new(base_url: &str, app_id: String, app_secret: String) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            app_id,
+            app_secret,
+            token: tokio::sync::Mutex::new(None),
+            credentials_rejected: Mutex::new(None),
+        }
+    }
+
+    /// The API URL for `path`, which starts with `/open-apis/`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// Whether Lark refused the app id or secret, so no call can succeed
+    /// until they are fixed.
+    pub fn credentials_rejected(&self) -> bool {
+        self.credentials_rejected.lock().unwrap().is_some()
+    }
+
+    async fn tenant_token(&self, http: &Client) -> Result<String, LarkError> {
+        let mut cached = self.token.lock().await;
+        if let Some(err) = &*self.credentials_rejected.lock().unwrap() {
+            return Err(err.clone());
+        }
+        if let Some((token, until)) = &*cached {
+            if Instant::now() < *until {
+                return Ok(token.clone());
+            }
+        }
+
+        let mut attempt = 1;
+        let response = loop {
+            match self.fetch_token(http).await {
+                Ok(response) => break response,
+                Err(e) if e.class == ErrorClass::Configuration => {
+                    error!(
+                        "lark rejected the app credentials, bot calls fail until they are fixed: {e}"
+                    );
+                    *self.credentials_rejected.lock().unwrap() = Some(e.clone());
+                    return Err(e);
+                }
+                Err(e) if e.class == ErrorClass::Retryable && attempt < TOKEN_ATTEMPTS => {
+                    let wait = TOKEN_BACKOFF * 2u32.pow(attempt - 1);
+                    warn!(
+                        "lark token fetch failed (attempt {attempt}/{TOKEN_ATTEMPTS}), retrying in {wait:?}: {e}"
+                    );
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let lifetime = Duration::from_secs(response.expire).saturating_sub(TOKEN_MARGIN);
+        *cached = Some((
+            response.tenant_access_token.clone(),
+            Instant::now() + lifetime,
+        ));
+        Ok(response.tenant_access_token)
+    }
+
+    async fn fetch_token(&self, http: &Client) -> Result<TokenResponse, LarkError> {
+        let body = send(
+            http.post(self.url("/open-apis/auth/v3/tenant_access_token/internal"))
+                .json(&serde_json::json!({
+                    "app_id": self.app_id,
+                    "app_secret": self.app_secret,
+                })),
+        )
+        .await?;
+        parse(&body)
+    }
+
+    /// Forgets `token` if it is still the cached one, so the next call
+    /// fetches another.
+    async fn reject_token(&self, token: &str) {
+        let mut cached = self.token.lock().await;
+        if cached.as_ref().is_some_and(|(t, _)| t == token) {
+            *cached = None;
+        }
+    }
+
+    /// Sends the request `build` makes for a tenant token and returns the
+    /// body of a successful answer. A rejected token is replaced and the
+    /// request sent once more.
+    pub async fn call(
+        &self,
+        http: &Client,
+        build: impl Fn(&str) -> RequestBuilder,
+    ) -> Result<String, LarkError> {
+        let token = self.tenant_token(http).await?;
+        match send(build(&token)).await {
+            Err(e) if e.code.is_some_and(|code| TOKEN_REJECTED.contains(&code)) => {
+                self.reject_token(&token).await;
+                let token = self.tenant_token(http).await?;
+                send(build(&token)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Posts `message` to `chat_id` as the bot and returns the new
+    /// message's id.
+    pub async fn send_message(
+        &self,
+        http: &Client,
+        chat_id: &str,
+        message: &Value,
+    ) -> Result<String, LarkError> {
+        let (msg_type, content) = bot_content(message);
+        let url = self.url("/open-apis/im/v1/messages?receive_id_type=chat_id");
+        let body = self
+            .call(http, |token| {
+                http.post(&url).bearer_auth(token).json(&serde_json::json!({
+                    "receive_id": chat_id,
+                    "msg_type": msg_type,
+                    "content": content,
+                }))
+            })
+            .await?;
+        let response: MessageResponse = parse(&body)?;
+        Ok(response.data.message_id)
+    }
+
+    /// Replaces the card of the bot's message `message_id` with the one in
+    /// `message`. Lark only allows this for cards sent with `update_multi`.
+    pub async fn update_message(
+        &self,
+        http: &Client,
+        message_id: &str,
+        message: &Value,
+    ) -> Result<(), LarkError> {
+        let (_, content) = bot_content(message);
+        let url = self.url(&format!("/open-apis/im/v1/messages/{message_id}"));
+        self.call(http, |token| {
+            http.patch(&url)
+                .bearer_auth(token)
+                .json(&serde_json::json!({ "content": content }))
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// The `msg_type` of a webhook-format message and its body as the JSON
+/// string the message API expects.
+fn bot_content(message: &Value) -> (&str, String) {
+    let msg_type = message["msg_type"].as_str().unwrap_or("text");
+    let content = message.get("card").or_else(|| message.get("content"));
+    let content = content.map(Value::to_string).unwrap_or_default();
+    (msg_type, content)
+}
+
+/// Sends `request` and returns the body of a successful answer.
+pub async fn send(request: RequestBuilder) -> Result<String, LarkError> {
+    let resp = request.send().await.map_err(LarkError::transport)?;
+    let status = resp.status();
+    let text = resp.text().await.unwrap_or_default();
+    match errors::classify(status, &text) {
+        Some(err) => Err(err),
+        None => Ok(text),
+    }
+}
+
+pub fn parse<T: DeserializeOwned>(body: &str) -> Result<T, LarkError> {
+    serde_json::from_str(body).map_err(|e| LarkError {
+        code: None,
+        class: ErrorClass::Permanent,
+        explanation: "unexpected lark response".into(),
+        fix: None,
+        detail: format!("{e}: {body}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    #[test]
+    fn converts_webhook_messages() {
+        let card = serde_json::json!({
+            "msg_type": "interactive",
+            "card": { "header": { "template": "red" } },
+        });
+        let (msg_type, content) = bot_content(&card);
+        assert_eq!(msg_type, "interactive");
+        assert_eq!(content, r#"{"header":{"template":"red"}}"#);
+
+        let text = serde_json::json!({ "msg_type": "text", "content": { "text": "hi" } });
+        assert_eq!(bot_content(&text), ("text", r#"{"text":"hi"}"#.to_string()));
+    }
+
+    /// Hands out `t-1`, then `t-2`.
+    async fn token_server() -> MockServer {
+        let lark = MockServer::start().await;
+        for (token, priority) in [("t-1", 1), ("t-2", 2)] {
+            Mock::given(method("POST"))
+                .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "code": 0,
+                    "tenant_access_token": token,
+                    "expire": 7200,
+                })))
+                .up_to_n_times(1)
+                .with_priority(priority)
+                .mount(&lark)
+                .await;
+        }
+        lark
+    }
+
+    fn token_fetches(requests: &[wiremock::Request]) -> usize {
+        requests
+            .iter()
+            .filter(|r| r.url.path().starts_with("/open-apis/auth/"))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn rejected_tokens_are_replaced_and_the_call_retried() {
+        let lark = token_server().await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/im/v1/messages"))
+            .and(header("authorization", "Bearer t-1"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "code": 99991663,
+                "msg": "Invalid access token for authorization.",
+            })))
+            .mount(&lark)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/im/v1/messages"))
+            .and(header("authorization", "Bearer t-2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "code": 0,
+                "data": { "message_id": "om_1" },
+            })))
+            .mount(&lark)
+            .await;
+
+        let app = LarkApp::new(&lark.uri(), "cli_test".into(), "app-secret".into());
+        let http = Client::new();
+        let text = serde_json::json!({ "msg_type": "text", "content": { "text": "hi" } });
+        let id = app.send_message(&http, "oc_eng", &text).await.unwrap();
+        assert_eq!(id, "om_1");
+        assert_eq!(token_fetches(&lark.received_requests().await.unwrap()), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_token_fetch() {
+        let lark = token_server().await;
+        let app = LarkApp::new(&lark.uri(), "cli_test".into(), "app-secret".into());
+        let http = Client::new();
+        let (a, b) = tokio::join!(app.tenant_token(&http), app.tenant_token(&http));
+        assert_eq!((a.unwrap(), b.unwrap()), ("t-1".into(), "t-1".into()));
+        assert_eq!(token_fetches(&lark.received_requests().await.unwrap()), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_token_fetches_are_retried() {
+        let lark = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&lark)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "code": 0,
+                "tenant_access_token": "t-1",
+                "expire": 7200,
+            })))
+            .mount(&lark)
+            .await;
+
+        let app = LarkApp::new(&lark.uri(), "cli_test".into(), "app-secret".into());
+        assert_eq!(app.tenant_token(&Client::new()).await.unwrap(), "t-1");
+        assert_eq!(token_fetches(&lark.received_requests().await.unwrap()), 2);
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials_fail_fast() {
+        let lark = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "code": 10014,
+                "msg": "app secret invalid",
+            })))
+            .mount(&lark)
+            .await;
+
+        let app = LarkApp::new(&lark.uri(), "cli_test".into(), "wrong".into());
+        let http = Client::new();
+        for _ in 0..2 {
+            let err = app.tenant_token(&http).await.unwrap_err();
+            assert_eq!(
+                (err.code, err.class),
+                (Some(10014), ErrorClass::Configuration)
+            );
+        }
+        assert!(app.credentials_rejected());
+        assert_eq!(token_fetches(&lark.received_requests().await.unwrap()), 1);
+    }
+
+    #[test]
+    fn tells_chats_from_webhooks() {
+        assert!(is_chat_id("oc_5ad11d72b830411d72b836c20"));
+        assert!(!is_chat_id(
+            "https://open.larksuite.com/open-apis/bot/v2/hook/x"
+        ));
+    }
+}
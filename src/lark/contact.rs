@@ -0,0 +1,154 @@
+//! Linear users as Lark users, for `<at>` mentions on cards.
+//!
+//! Looks people up by email through the contact API of the [`LarkApp`],
+//! which needs the permission to get user IDs by email.
+//!
+//! Answers are cached for an hour, and "no such user" for five minutes so
+//! people who join Lark are mentioned soon.
+//! Failed lookups are not cached, and the card falls back to the plain
+//! name.
+//!
+//! The reverse, a Lark user's email, serves "Assign to me" on cards and
+//! additionally needs the permission to read user emails.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::lark::app::{LarkApp, parse};
+use crate::lark::errors::LarkError;
+
+/// How long a found open_id is reused. People rarely change emails.
+const OPEN_ID_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long an email without a Lark user is not looked up again.
+const MISSING_TTL: Duration = Duration::from_secs(5 * 60);
+
+pub struct Contacts {
+    app: Arc<LarkApp>,
+    /// By lowercased email, with when it was looked up.
+    open_ids: Mutex<HashMap<String, (Instant, Option<String>)>>,
+}
+
+fn is_fresh((at, open_id): &(Instant, Option<String>)) -> bool {
+    let ttl = if open_id.is_some() {
+        OPEN_ID_TTL
+    } else {
+        MISSING_TTL
+    };
+    at.elapsed() < ttl
+}
+
+#[derive(Deserialize)]
+struct BatchGetIdResponse {
+    data: BatchGetIdData,
+}
+
+#[derive(Deserialize)]
+struct BatchGetIdData {
+    #[serde(default)]
+    user_list: Vec<UserId>,
+}
+
+#[derive(Deserialize)]
+struct GetUserResponse {
+    data: GetUserData,
+}
+
+#[derive(Deserialize)]
+struct GetUserData {
+    user: LarkUser,
+}
+
+#[derive(Deserialize)]
+struct LarkUser {
+    /// Empty or absent without the email permission.
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserId {
+    email: Option<String>,
+    /// Absent when no user has the email.
+    user_id: Option<String>,
+}
+
+impl Contacts {
+    pub fn new(app: Arc<LarkApp>) -> Self {
+        Self {
+            app,
+            open_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The open_id of the Lark user with `email`, or `None` when there is
+    /// none or the lookup failed.
+    pub async fn open_id(&self, http: &Client, email: &str) -> Option<String> {
+        let email = email.trim().to_lowercase();
+        if let Some((_, open_id)) = self
+            .open_ids
+            .lock()
+            .unwrap()
+            .get(&email)
+            .filter(|entry| is_fresh(entry))
+        {
+            return open_id.clone();
+        }
+        match self.lookup(http, &email).await {
+            Ok(open_id) => {
+                let mut open_ids = self.open_ids.lock().unwrap();
+                // Expired entries are pruned on write, which keeps the map
+                // bounded by the people mentioned within one TTL.
+                open_ids.retain(|_, entry| is_fresh(entry));
+                open_ids.insert(email, (Instant::now(), open_id.clone()));
+                open_id
+            }
+            Err(e) => {
+                warn!("lark user lookup failed, mentioning by name: {e}");
+                None
+            }
+        }
+    }
+
+    async fn lookup(&self, http: &Client, email: &str) -> Result<Option<String>, LarkError> {
+        let url = self
+            .app
+            .url("/open-apis/contact/v3/users/batch_get_id?user_id_type=open_id");
+        let body = self
+            .app
+            .call(http, |token| {
+                http.post(&url)
+                    .bearer_auth(token)
+                    .json(&serde_json::json!({ "emails": [email] }))
+            })
+            .await?;
+        let response: BatchGetIdResponse = parse(&body)?;
+        Ok(response
+            .data
+            .user_list
+            .into_iter()
+            .find(|user| {
+                user.email
+                    .as_deref()
+                    .is_none_or(|e| e.eq_ignore_ascii_case(email))
+            })
+            .and_then(|user| user.user_id))
+    }
+
+    /// The email of the Lark user `open_id`, when the app may read it.
+    pub async fn email(&self, http: &Client, open_id: &str) -> Result<Option<String>, LarkError> {
+        let url = self.app.url(&format!(
+            "/open-apis/contact/v3/users/{open_id}?user_id_type=open_id"
+        ));
+        let body = self
+            .app
+            .call(http, |token| http.get(&url).bearer_auth(token))
+            .await?;
+        let response: GetUserResponse = parse(&body)?;
+        Ok(response.data.user.email.filter(|e| !e.is_empty()))
+    }
+}
@@ -0,0 +1,451 @@
+//! Queued delivery of cards.
+//!
+//! Linear expects a webhook answer within a few seconds and disables
+//! webhooks that keep failing, while Lark's bot webhooks rate-limit bursts
+//! (HTTP 429, code 9499). So the webhook handler only queues each card and
+//! a worker posts them, retrying rate limits and transport errors with
+//! jittered exponential backoff. The worker also does the card's lookups in
+//! Linear and Lark first, so the handler never waits on either. When the queue is full the handler answers
+//! 503 instead, and Linear sends the webhook again later.
+//!
+//! A card given up on is kept as a dead letter, as is one the full queue
+//! turned away. `GET /admin/dead-letters` lists them and
+//! `POST /admin/dead-letters/{id}/replay` sends one again.
+//!
+//! With a debounce window, issue updates wait that long before they are
+//! queued, and further updates to the same issue in the meantime fold into
+//! the waiting card instead of posting one each. Held cards are also kept
+//! in the store: shutdown does not wait for them, and the next start
+//! releases them on their original schedule. Queued cards are not stored;
+//! shutdown drains them instead.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::{Instrument, error, info, warn};
+
+use crate::lark::errors::ErrorClass;
+use crate::routes::Destination;
+use crate::storage::{DeadLetter, Event, HeldCard, Storage};
+use crate::{AppState, Notification};
+
+/// Longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    /// Cards waiting beyond this are dropped instead of queued.
+    pub capacity: usize,
+    /// Attempts per card, the first one included.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubles with each further one. Each
+    /// wait is randomly shortened by up to half, so cards that failed
+    /// together do not retry together.
+    pub backoff: Duration,
+    /// How long issue updates wait for further edits; zero sends each
+    /// right away.
+    pub debounce: Duration,
+    /// Cards posted at once. One keeps each destination's cards in order
+    /// and shares the bot's rate limit the least.
+    pub workers: usize,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            debounce: Duration::ZERO,
+            workers: 1,
+        }
+    }
+}
+
+/// One card for one destination, with the event to record once it is
+/// delivered or given up.
+pub struct Job {
+    pub destination: Destination,
+    pub notification: Notification,
+    pub event: Event,
+    /// The webhook's request span, so the worker's logs carry its id.
+    pub span: tracing::Span,
+}
+
+/// Issue updates in their debounce window and when each is released, by
+/// issue id and destination url. An async lock, since changes are written
+/// through to the store while it is held.
+type Held = Arc<tokio::sync::Mutex<HashMap<(String, String), (Job, DateTime<Utc>)>>>;
+
+pub struct Delivery {
+    config: DeliveryConfig,
+    queue: mpsc::Sender<Job>,
+    held: Held,
+    storage: Storage,
+    /// Queued or in flight.
+    pending: Arc<AtomicU64>,
+    /// In the debounce window.
+    holding: Arc<AtomicU64>,
+    sent: AtomicU64,
+    retried: AtomicU64,
+    dropped: AtomicU64,
+    /// Totals of earlier runs, restored from the last snapshot.
+    baseline: Mutex<DeliveryTotals>,
+}
+
+/// Counters across restarts, as far as they are persisted; see
+/// [`crate::jobs::counters`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryTotals {
+    pub sent: u64,
+    pub retried: u64,
+    pub dropped: u64,
+}
+
+/// Counters since startup, for `GET /health`.
+#[derive(Debug, Serialize)]
+pub struct DeliveryStats {
+    pub queued: u64,
+    pub held: u64,
+    pub sent: u64,
+    pub retried: u64,
+    pub dropped: u64,
+}
+
+impl Delivery {
+    /// The queue and the receiving end to hand to [`run`].
+    pub fn new(config: DeliveryConfig, storage: Storage) -> (Self, mpsc::Receiver<Job>) {
+        let (queue, jobs) = mpsc::channel(config.capacity.max(1));
+        let delivery = Self {
+            config,
+            queue,
+            held: Held::default(),
+            storage,
+            pending: Arc::default(),
+            holding: Arc::default(),
+            sent: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            baseline: Mutex::default(),
+        };
+        (delivery, jobs)
+    }
+
+    /// Queues `job`, or holds it for the debounce window if it is an issue
+    /// update. When the queue is full it counts as dropped and its event is
+    /// handed back for recording. When it folds into a held card for the
+    /// same issue, the event of the earlier update is handed back instead.
+    pub async fn enqueue(&self, job: Job) -> Result<Option<Box<Event>>, Box<Event>> {
+        if !self.config.debounce.is_zero() {
+            if let Some(issue_id) = job.notification.debounce_key() {
+                return Ok(self.hold(issue_id.to_string(), job).await);
+            }
+        }
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.queue.try_send(job).map_err(|e| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Box::new(e.into_inner().event)
+        })?;
+        Ok(None)
+    }
+
+    async fn hold(&self, issue_id: String, job: Job) -> Option<Box<Event>> {
+        let key = (issue_id, job.destination.url.clone());
+        let mut held = self.held.lock().await;
+        if let Some((earlier, release_at)) = held.get_mut(&key) {
+            earlier.notification.absorb(job.notification);
+            let event = std::mem::replace(&mut earlier.event, job.event);
+            store_held(&self.storage, &key, earlier, *release_at).await;
+            return Some(Box::new(event));
+        }
+
+        let release_at = Utc::now() + self.config.debounce;
+        store_held(&self.storage, &key, &job, release_at).await;
+        held.insert(key.clone(), (job, release_at));
+        drop(held);
+        self.holding.fetch_add(1, Ordering::Relaxed);
+        self.release_at(key, release_at);
+        None
+    }
+
+    /// Takes back the cards a previous run held, releasing those whose
+    /// window has passed right away, in order, and the rest on schedule.
+    pub async fn resume(&self) {
+        let cards = match self.storage.held_cards().list().await {
+            Ok(cards) => cards,
+            Err(e) => {
+                error!("failed to load held cards: {e}");
+                return;
+            }
+        };
+        if !cards.is_empty() {
+            info!("resuming {} held cards", cards.len());
+        }
+        for card in cards {
+            let Some(notification) = Notification::from_held(card.notification) else {
+                warn!("dropping unreadable held card for {}", card.issue_id);
+                continue;
+            };
+            let key = (card.issue_id, card.url.clone());
+            let job = Job {
+                destination: Destination {
+                    label: card.label,
+                    url: card.url,
+                },
+                notification,
+                event: card.event,
+                span: tracing::Span::none(),
+            };
+            self.held
+                .lock()
+                .await
+                .insert(key.clone(), (job, card.release_at));
+            self.holding.fetch_add(1, Ordering::Relaxed);
+            if card.release_at <= Utc::now() {
+                self.releaser().release(&key).await;
+            } else {
+                self.release_at(key, card.release_at);
+            }
+        }
+    }
+
+    fn release_at(&self, key: (String, String), at: DateTime<Utc>) {
+        let releaser = self.releaser();
+        tokio::spawn(async move {
+            let wait = (at - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            releaser.release(&key).await;
+        });
+    }
+
+    fn releaser(&self) -> Releaser {
+        Releaser {
+            held: self.held.clone(),
+            queue: self.queue.clone(),
+            storage: self.storage.clone(),
+            pending: self.pending.clone(),
+            holding: self.holding.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> DeliveryStats {
+        DeliveryStats {
+            queued: self.pending.load(Ordering::Relaxed),
+            held: self.holding.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn set_baseline(&self, totals: DeliveryTotals) {
+        *self.baseline.lock().unwrap() = totals;
+    }
+
+    /// The baseline plus the counters since startup.
+    pub fn totals(&self) -> DeliveryTotals {
+        let baseline = *self.baseline.lock().unwrap();
+        let stats = self.stats();
+        DeliveryTotals {
+            sent: baseline.sent + stats.sent,
+            retried: baseline.retried + stats.retried,
+            dropped: baseline.dropped + stats.dropped,
+        }
+    }
+
+    /// Waits until every queued card is delivered or given up, for at most
+    /// `timeout`. Returns whether the queue emptied.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.pending.load(Ordering::Relaxed) > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        true
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.config
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(MAX_BACKOFF)
+            .mul_f64(rand::random_range(0.5..=1.0))
+    }
+}
+
+/// What a debounce timer needs of [`Delivery`] to queue a held card.
+struct Releaser {
+    held: Held,
+    queue: mpsc::Sender<Job>,
+    storage: Storage,
+    pending: Arc<AtomicU64>,
+    holding: Arc<AtomicU64>,
+}
+
+impl Releaser {
+    async fn release(&self, key: &(String, String)) {
+        let mut held = self.held.lock().await;
+        let Some((job, _)) = held.remove(key) else {
+            return;
+        };
+        // Under the lock, so a new hold for the issue cannot be deleted.
+        let (issue_id, url) = key;
+        if let Err(e) = self.storage.held_cards().remove(url, issue_id).await {
+            error!("failed to unstore the held card for {issue_id}: {e}");
+        }
+        drop(held);
+
+        self.holding.fetch_sub(1, Ordering::Relaxed);
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        // Waits for room rather than dropping: the webhook was answered
+        // long ago.
+        if self.queue.send(job).await.is_err() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Writes the held `job` through to the store. Failures are only logged:
+/// the card is still sent, it just would not survive a restart.
+async fn store_held(
+    storage: &Storage,
+    (issue_id, url): &(String, String),
+    job: &Job,
+    release_at: DateTime<Utc>,
+) {
+    let card = HeldCard {
+        url: url.clone(),
+        issue_id: issue_id.clone(),
+        label: job.destination.label.clone(),
+        release_at,
+        notification: job.notification.to_held(),
+        event: job.event.clone(),
+    };
+    if let Err(e) = storage.held_cards().put(card).await {
+        error!("failed to store the held card for {issue_id}: {e}");
+    }
+}
+
+/// Keeps the card for `destination` that was given up on after `attempts`
+/// sends (none when it never got into the queue), for replay.
+pub async fn keep_dead_letter(
+    state: &AppState,
+    destination: &Destination,
+    notification: &Notification,
+    attempts: u32,
+    error: String,
+) {
+    let letter = DeadLetter {
+        id: 0,
+        failed_at: chrono::Utc::now(),
+        identifier: notification.identifier().to_string(),
+        label: destination.label.clone(),
+        url: destination.url.clone(),
+        message: notification.last_message(state),
+        attempts,
+        error,
+    };
+    if let Err(e) = state.storage.dead_letters().add(letter).await {
+        error!("failed to keep the dropped card as a dead letter: {e}");
+    }
+}
+
+/// The workers: each posts queued cards one at a time, which with the
+/// default single worker also keeps the bridge under Lark's per-bot rate
+/// limit in the common case.
+pub async fn run(state: Arc<AppState>, jobs: mpsc::Receiver<Job>) {
+    let jobs = Arc::new(tokio::sync::Mutex::new(jobs));
+    let mut workers = JoinSet::new();
+    for _ in 0..state.delivery.config.workers.max(1) {
+        workers.spawn(work(state.clone(), jobs.clone()));
+    }
+    while workers.join_next().await.is_some() {}
+}
+
+async fn work(state: Arc<AppState>, jobs: Arc<tokio::sync::Mutex<mpsc::Receiver<Job>>>) {
+    loop {
+        // Released before delivering, so the next worker can take a card.
+        let Some(job) = jobs.lock().await.recv().await else {
+            return;
+        };
+        let span = job.span.clone();
+        deliver(&state, job).instrument(span).await;
+        state.delivery.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+async fn deliver(state: &AppState, job: Job) {
+    let Job {
+        destination,
+        mut notification,
+        mut event,
+        span: _,
+    } = job;
+    let delivery = &state.delivery;
+    let max_attempts = delivery.config.max_attempts.max(1);
+    notification.look_up(state).await;
+
+    let mut attempt = 1;
+    loop {
+        match crate::send_notification(state, &destination.url, &notification).await {
+            Ok(()) => {
+                delivery.sent.fetch_add(1, Ordering::Relaxed);
+                crate::observe_delivery(state, &event);
+                event.disposition = "sent".into();
+                break;
+            }
+            Err(e) if e.class == ErrorClass::Retryable && attempt < max_attempts => {
+                let wait = delivery.backoff(attempt);
+                warn!(
+                    "lark delivery of {} to {} failed (attempt {attempt}/{max_attempts}), retrying in {wait:?}: {e}",
+                    notification.identifier(),
+                    destination.label
+                );
+                delivery.retried.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                crate::report_send_failure(state, &e).await;
+                delivery.dropped.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    "dropping card for {} to {} after {attempt} attempts",
+                    notification.identifier(),
+                    destination.label
+                );
+                keep_dead_letter(state, &destination, &notification, attempt, e.to_string()).await;
+                event.disposition = "failed".into();
+                event.outcome = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    crate::store_event(state, event).await;
+}
+
+/// Logs how shutdown left the queue.
+pub async fn drain_on_shutdown(delivery: &Delivery, timeout: Duration) {
+    let held = delivery.stats().held;
+    if held > 0 {
+        info!("keeping {held} held cards for the next start");
+    }
+    let queued = delivery.stats().queued;
+    if queued == 0 {
+        return;
+    }
+    info!("delivering {queued} queued cards before exiting");
+    if !delivery.drain(timeout).await {
+        let left = delivery.stats().queued;
+        error!("shutting down with {left} cards undelivered after {timeout:?}");
+    }
+}
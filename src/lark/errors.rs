@@ -0,0 +1,361 @@
+//! Known Lark error codes and how delivery should react to them.
+//!
+//! Lark reports most failures as HTTP 200 with a non-zero `code`, and the
+//! accompanying `msg` rarely says how to fix anything. The table below maps
+//! the codes we have actually run into to a class and a human explanation.
+
+use std::fmt;
+
+use reqwest::StatusCode;
+
+/// What the caller should do about a failed send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Transient: rate limits, expired tokens, Lark-side outages. Try again.
+    Retryable,
+    /// The message itself is bad. Retrying the same payload will not help.
+    Permanent,
+    /// The bridge or the Lark bot is misconfigured. Needs a human to fix.
+    Configuration,
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorClass::Retryable => "retryable",
+            ErrorClass::Permanent => "permanent",
+            ErrorClass::Configuration => "configuration",
+        })
+    }
+}
+
+struct KnownError {
+    code: i64,
+    class: ErrorClass,
+    explanation: &'static str,
+    fix: Option<&'static str>,
+}
+
+const KNOWN_ERRORS: &[KnownError] = &[
+    // Custom bot (incoming webhook) errors
+    KnownError {
+        code: 9499,
+        class: ErrorClass::Retryable,
+        explanation: "rate limited by the custom bot webhook",
+        fix: None,
+    },
+    KnownError {
+        code: 11232,
+        class: ErrorClass::Retryable,
+        explanation: "message frequency limit reached",
+        fix: None,
+    },
+    KnownError {
+        code: 19001,
+        class: ErrorClass::Configuration,
+        explanation: "webhook access token is invalid",
+        fix: Some(
+            "the custom bot was removed from the group or LARK_WEBHOOK_URL is wrong; \
+             add the bot back to the group and update LARK_WEBHOOK_URL",
+        ),
+    },
+    KnownError {
+        code: 19002,
+        class: ErrorClass::Permanent,
+        explanation: "request parameters are invalid",
+        fix: None,
+    },
+    KnownError {
+        code: 19021,
+        class: ErrorClass::Configuration,
+        explanation: "signature check failed",
+        fix: Some(
+            "the custom bot has \"signature verification\" enabled, which the bridge \
+             does not sign for; disable it in the bot's security settings",
+        ),
+    },
+    KnownError {
+        code: 19022,
+        class: ErrorClass::Configuration,
+        explanation: "request IP is not on the bot's allowlist",
+        fix: Some("add the bridge's egress IP to the custom bot's IP allowlist"),
+    },
+    KnownError {
+        code: 19024,
+        class: ErrorClass::Configuration,
+        explanation: "message does not contain the bot's required keyword",
+        fix: Some(
+            "the custom bot has a keyword security setting; add \"Linear\" as a keyword \
+             or remove the keyword restriction",
+        ),
+    },
+    // Open API (bot) errors
+    KnownError {
+        code: 10003,
+        class: ErrorClass::Configuration,
+        explanation: "the app id is not recognised",
+        fix: Some("check LARK_APP_ID against the app's credentials page"),
+    },
+    KnownError {
+        code: 10014,
+        class: ErrorClass::Configuration,
+        explanation: "the app secret is invalid",
+        fix: Some(
+            "check LARK_APP_SECRET against the app's credentials page; it changes when reset",
+        ),
+    },
+    KnownError {
+        code: 230001,
+        class: ErrorClass::Configuration,
+        explanation: "invalid request parameter, usually the receive_id",
+        fix: Some("check that the configured chat_id exists and is spelled correctly"),
+    },
+    KnownError {
+        code: 230002,
+        class: ErrorClass::Configuration,
+        explanation: "the bot is not a member of the chat",
+        fix: Some("invite the bot back into the group"),
+    },
+    KnownError {
+        code: 230006,
+        class: ErrorClass::Configuration,
+        explanation: "bot capability is not enabled for the app",
+        fix: Some("enable the Bot feature for the app in the Lark developer console"),
+    },
+    KnownError {
+        code: 230020,
+        class: ErrorClass::Retryable,
+        explanation: "message frequency limit reached",
+        fix: None,
+    },
+    KnownError {
+        code: 230025,
+        class: ErrorClass::Permanent,
+        explanation: "message content is too long",
+        fix: None,
+    },
+    KnownError {
+        code: 230099,
+        class: ErrorClass::Permanent,
+        explanation: "card content is invalid",
+        fix: None,
+    },
+    KnownError {
+        code: 99991400,
+        class: ErrorClass::Retryable,
+        explanation: "API request frequency limit reached",
+        fix: None,
+    },
+    KnownError {
+        code: 99991661,
+        class: ErrorClass::Retryable,
+        explanation: "access token is missing",
+        fix: None,
+    },
+    KnownError {
+        code: 99991663,
+        class: ErrorClass::Retryable,
+        explanation: "tenant access token is invalid or expired",
+        fix: None,
+    },
+    KnownError {
+        code: 99991668,
+        class: ErrorClass::Retryable,
+        explanation: "access token is invalid or expired",
+        fix: None,
+    },
+    KnownError {
+        code: 99991672,
+        class: ErrorClass::Configuration,
+        explanation: "the app lacks the required API scope",
+        fix: Some("grant the app the im:message scope and publish a new app version"),
+    },
+];
+
+/// A failed Lark send, classified.
+#[derive(Debug, Clone)]
+pub struct LarkError {
+    /// Lark's `code`, absent for transport errors and bare HTTP failures.
+    pub code: Option<i64>,
+    pub class: ErrorClass,
+    pub explanation: String,
+    /// Suggested fix for configuration errors.
+    pub fix: Option<&'static str>,
+    /// Raw status and body (or transport error) for the logs.
+    pub detail: String,
+}
+
+impl LarkError {
+    pub fn transport(err: reqwest::Error) -> Self {
+        Self {
+            code: None,
+            class: ErrorClass::Retryable,
+            explanation: "request to lark failed".into(),
+            fix: None,
+            detail: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.explanation, self.class, self.detail)
+    }
+}
+
+/// Classifies a Lark response. Returns `None` when the send succeeded.
+pub fn classify(status: StatusCode, body: &str) -> Option<LarkError> {
+    let parsed = serde_json::from_str::<serde_json::Value>(body).ok();
+    // Older webhook responses use StatusCode / StatusMessage instead.
+    let code = parsed.as_ref().and_then(|v| {
+        v.get("code")
+            .or_else(|| v.get("StatusCode"))
+            .and_then(|c| c.as_i64())
+    });
+    let detail = format!("{status}: {body}");
+
+    if let Some(code) = code.filter(|&c| c != 0) {
+        let msg = parsed
+            .as_ref()
+            .and_then(|v| v.get("msg").or_else(|| v.get("StatusMessage")))
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        let (class, explanation, fix) = match KNOWN_ERRORS.iter().find(|e| e.code == code) {
+            Some(known) => (known.class, known.explanation.to_string(), known.fix),
+            None => (
+                class_for_status(status),
+                format!("lark error {code}: {msg}"),
+                None,
+            ),
+        };
+        return Some(LarkError {
+            code: Some(code),
+            class,
+            explanation,
+            fix,
+            detail,
+        });
+    }
+
+    if status.is_success() {
+        return None;
+    }
+
+    Some(LarkError {
+        code: None,
+        class: class_for_status(status),
+        explanation: format!("lark returned {status}"),
+        fix: None,
+        detail,
+    })
+}
+
+fn class_for_status(status: StatusCode) -> ErrorClass {
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        ErrorClass::Retryable
+    } else {
+        ErrorClass::Permanent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify_ok(body: &str) -> Option<LarkError> {
+        classify(StatusCode::OK, body)
+    }
+
+    #[test]
+    fn success_bodies_are_not_errors() {
+        assert!(
+            classify_ok(
+                r#"{"StatusCode":0,"StatusMessage":"success","code":0,"data":{},"msg":"success"}"#
+            )
+            .is_none()
+        );
+        assert!(classify_ok(r#"{"code":0,"data":{},"msg":"success"}"#).is_none());
+        assert!(classify_ok("").is_none());
+    }
+
+    #[test]
+    fn webhook_rate_limit_is_retryable() {
+        let err = classify_ok(r#"{"code":9499,"data":{},"msg":"too many request"}"#).unwrap();
+        assert_eq!(err.code, Some(9499));
+        assert_eq!(err.class, ErrorClass::Retryable);
+    }
+
+    #[test]
+    fn invalid_webhook_token_is_configuration() {
+        let err = classify_ok(
+            r#"{"code":19001,"data":{},"msg":"param invalid: incoming webhook access token invalid"}"#,
+        )
+        .unwrap();
+        assert_eq!(err.class, ErrorClass::Configuration);
+        assert!(err.fix.unwrap().contains("LARK_WEBHOOK_URL"));
+    }
+
+    #[test]
+    fn signature_mismatch_is_configuration() {
+        let err = classify_ok(
+            r#"{"code":19021,"data":{},"msg":"sign match fail or timestamp is not within one hour from current time"}"#,
+        )
+        .unwrap();
+        assert_eq!(err.class, ErrorClass::Configuration);
+    }
+
+    #[test]
+    fn keyword_missing_is_configuration() {
+        let err = classify_ok(r#"{"code":19024,"data":{},"msg":"Key Words Not Found"}"#).unwrap();
+        assert_eq!(err.class, ErrorClass::Configuration);
+    }
+
+    #[test]
+    fn bot_outside_chat_suggests_invite() {
+        let err = classify(
+            StatusCode::BAD_REQUEST,
+            r#"{"code":230002,"msg":"Bot/User can NOT be out of the chat.","error":{"log_id":"20240101000000ABCDEF"}}"#,
+        )
+        .unwrap();
+        assert_eq!(err.class, ErrorClass::Configuration);
+        assert_eq!(err.fix, Some("invite the bot back into the group"));
+    }
+
+    #[test]
+    fn invalid_card_is_permanent() {
+        let err = classify(
+            StatusCode::BAD_REQUEST,
+            r#"{"code":230099,"msg":"Failed to create card content, ext=ErrCode: 200621; ErrMsg: parse card json err"}"#,
+        )
+        .unwrap();
+        assert_eq!(err.class, ErrorClass::Permanent);
+    }
+
+    #[test]
+    fn expired_token_is_retryable() {
+        let err = classify(
+            StatusCode::BAD_REQUEST,
+            r#"{"code":99991663,"msg":"Invalid access token for authorization. Please make a request with token attached."}"#,
+        )
+        .unwrap();
+        assert_eq!(err.class, ErrorClass::Retryable);
+    }
+
+    #[test]
+    fn unknown_code_keeps_lark_message() {
+        let err = classify_ok(r#"{"code":12345,"msg":"something new"}"#).unwrap();
+        assert_eq!(err.code, Some(12345));
+        assert_eq!(err.class, ErrorClass::Permanent);
+        assert!(err.explanation.contains("something new"));
+    }
+
+    #[test]
+    fn bare_http_failures_classify_by_status() {
+        let err = classify(StatusCode::TOO_MANY_REQUESTS, "").unwrap();
+        assert_eq!(err.class, ErrorClass::Retryable);
+        let err = classify(StatusCode::BAD_GATEWAY, "<html>bad gateway</html>").unwrap();
+        assert_eq!(err.class, ErrorClass::Retryable);
+        let err = classify(StatusCode::NOT_FOUND, "404 page not found").unwrap();
+        assert_eq!(err.class, ErrorClass::Permanent);
+    }
+}
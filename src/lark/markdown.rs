@@ -0,0 +1,177 @@
+//! Escaping for text interpolated into `lark_md`.
+//!
+//! Issue titles, names and states come from users and must show as typed:
+//! a title with `**` would otherwise turn the rest of the card bold, and
+//! `<at ...>` would mention someone. Descriptions and comment bodies keep
+//! their formatting, which is meant to render, but go through [`sanitize`]
+//! so they cannot carry tags.
+
+use std::borrow::Cow;
+
+/// Characters with a meaning in lark_md, written as HTML entities, which
+/// Lark resolves after parsing the markdown.
+const ENTITIES: &[(char, &str)] = &[
+    ('&', "&amp;"),
+    ('<', "&#60;"),
+    ('>', "&#62;"),
+    ('*', "&#42;"),
+    ('_', "&#95;"),
+    ('~', "&#126;"),
+    ('`', "&#96;"),
+    ('$', "&#36;"),
+    ('[', "&#91;"),
+    (']', "&#93;"),
+    ('(', "&#40;"),
+    (')', "&#41;"),
+    ('#', "&#35;"),
+    ('|', "&#124;"),
+    ('\\', "&#92;"),
+];
+
+/// Escapes `text` for a single-line lark_md context. Line breaks become
+/// spaces, since an entity cannot stand in for them.
+pub fn escape(text: &str) -> Cow<'_, str> {
+    let special = |c: char| c == '\n' || c == '\r' || ENTITIES.iter().any(|(e, _)| *e == c);
+    if !text.contains(special) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match ENTITIES.iter().find(|(e, _)| *e == c) {
+            Some((_, entity)) => escaped.push_str(entity),
+            None if c == '\n' || c == '\r' => escaped.push(' '),
+            None => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Neutralizes the tags in user-written markdown, such as `<at>` mentions,
+/// `<font>` and `<a>`, while keeping its formatting. Only `<` is escaped,
+/// which is enough that no tag can open.
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if text.contains('<') {
+        Cow::Owned(text.replace('<', "&#60;"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// What a Lark client shows for escaped text.
+    fn unescape(text: &str) -> String {
+        let mut shown = text.to_string();
+        // `&amp;` last, so `&amp;#42;` stays literal.
+        for (c, entity) in ENTITIES.iter().rev() {
+            shown = shown.replace(entity, &c.to_string());
+        }
+        shown
+    }
+
+    const HOSTILE: &[&str] = &[
+        "**",
+        "*",
+        "_",
+        "~~",
+        "`",
+        "```",
+        "$",
+        "$$",
+        "[",
+        "]",
+        "(",
+        ")",
+        "<at id=all></at>",
+        "<at user_id=\"ou_1\">",
+        "</font>",
+        "&amp;",
+        "&#42;",
+        "#",
+        "|",
+        "\\",
+        "\\*",
+        "-",
+        " ",
+        "a",
+        "Z",
+        "9",
+        "é",
+        "日本",
+        "🦀",
+        "\"",
+        "'",
+        "{",
+        "}",
+        ":",
+    ];
+
+    fn hostile(rng: &mut StdRng) -> String {
+        (0..rng.random_range(0..12))
+            .map(|_| HOSTILE[rng.random_range(0..HOSTILE.len())])
+            .collect()
+    }
+
+    #[test]
+    fn plain_text_is_borrowed() {
+        assert!(matches!(escape("Fix login on iOS 17"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn escapes_formatting_and_mentions() {
+        assert_eq!(escape("**bold**"), "&#42;&#42;bold&#42;&#42;");
+        assert_eq!(
+            escape("<at id=all></at>"),
+            "&#60;at id=all&#62;&#60;/at&#62;"
+        );
+        assert_eq!(escape("a\nb"), "a b");
+    }
+
+    #[test]
+    fn sanitize_removes_tags_but_keeps_formatting() {
+        assert_eq!(
+            sanitize("**ping** <at id=all></at> [docs](https://x.test)"),
+            "**ping** &#60;at id=all>&#60;/at> [docs](https://x.test)"
+        );
+        assert_eq!(
+            sanitize("<font color='red'>x</font>"),
+            "&#60;font color='red'>x&#60;/font>"
+        );
+        assert!(matches!(sanitize("- a\n- b"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn hostile_strings_round_trip_and_leave_no_markup() {
+        let mut rng = StdRng::seed_from_u64(250);
+        for _ in 0..2_000 {
+            let raw = hostile(&mut rng);
+            let escaped = escape(&raw);
+
+            let bare = ENTITIES
+                .iter()
+                .fold(escaped.to_string(), |text, (_, entity)| {
+                    text.replace(entity, "")
+                });
+            assert!(
+                !bare.contains(|c: char| ENTITIES.iter().any(|(e, _)| *e == c)),
+                "markup left in {escaped:?}"
+            );
+            assert_eq!(unescape(&escaped), raw);
+
+            // Still valid inside the card JSON, and shown verbatim there.
+            let element = serde_json::json!({
+                "tag": "lark_md",
+                "content": format!("**{escaped}**"),
+            });
+            let parsed: serde_json::Value = serde_json::from_str(&element.to_string()).unwrap();
+            let content = parsed["content"].as_str().unwrap();
+            assert_eq!(unescape(&content[2..content.len() - 2]), raw);
+        }
+    }
+}
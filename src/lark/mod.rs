@@ -0,0 +1,7 @@
+//! Lark Open Platform helpers shared by the delivery paths.
+
+pub mod app;
+pub mod contact;
+pub mod delivery;
+pub mod errors;
+pub mod markdown;
@@ -0,0 +1,101 @@
+//! Caps on payload content, applied while a payload is parsed so the logs,
+//! the event log and the card builder only ever see bounded values.
+//!
+//! A pasted log dump in a description once made a card Lark rejected and a
+//! log line the log pipeline choked on. Truncation here is a hard cut with
+//! a marker; cards still cut descriptions further for display.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tracing::warn;
+
+/// Descriptions and comment bodies.
+pub const MAX_TEXT_CHARS: usize = 10_000;
+
+/// Titles, names, identifiers and other single-line values.
+pub const MAX_FIELD_CHARS: usize = 500;
+
+/// Labels kept per issue; the rest are dropped unannounced.
+pub const MAX_LABELS: usize = 50;
+
+/// All text of one normalized event together. Past this the description,
+/// being the only large field, is cut further.
+pub const MAX_EVENT_BYTES: usize = 32 * 1024;
+
+const MARKER: &str = " …[truncated]";
+
+/// How often each cap fired since startup.
+pub struct CapCounters {
+    pub text: AtomicU64,
+    pub field: AtomicU64,
+    pub event: AtomicU64,
+}
+
+pub static CAPS: CapCounters = CapCounters {
+    text: AtomicU64::new(0),
+    field: AtomicU64::new(0),
+    event: AtomicU64::new(0),
+};
+
+pub fn cap_text(value: &mut String) {
+    cap(value, MAX_TEXT_CHARS, &CAPS.text, "text");
+}
+
+pub fn cap_field(value: &mut String) {
+    cap(value, MAX_FIELD_CHARS, &CAPS.field, "field");
+}
+
+/// Cuts `value` to at most `max_bytes` (marker included), on a char
+/// boundary. For the event size guard.
+pub fn cap_bytes(value: &mut String, max_bytes: usize) {
+    if value.len() <= max_bytes {
+        return;
+    }
+    let mut end = max_bytes.saturating_sub(MARKER.len());
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    value.push_str(MARKER);
+    record(&CAPS.event, "event size", max_bytes);
+}
+
+fn cap(value: &mut String, max_chars: usize, counter: &AtomicU64, kind: &str) {
+    if let Some((end, _)) = value.char_indices().nth(max_chars) {
+        value.truncate(end);
+        value.push_str(MARKER);
+        record(counter, kind, max_chars);
+    }
+}
+
+fn record(counter: &AtomicU64, kind: &str, max: usize) {
+    let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+    warn!("payload {kind} cap ({max}) hit, {count} times since startup");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_values_are_untouched() {
+        let mut value = "Fix login".to_string();
+        cap_field(&mut value);
+        assert_eq!(value, "Fix login");
+    }
+
+    #[test]
+    fn long_values_are_cut_with_a_marker() {
+        let mut value = "é".repeat(MAX_FIELD_CHARS + 1);
+        cap_field(&mut value);
+        assert_eq!(value, format!("{}{MARKER}", "é".repeat(MAX_FIELD_CHARS)));
+    }
+
+    #[test]
+    fn byte_cap_respects_char_boundaries() {
+        let mut value = "日本語".repeat(100);
+        cap_bytes(&mut value, 50);
+        assert!(value.len() <= 50);
+        assert!(value.ends_with(MARKER));
+    }
+}
@@ -0,0 +1,657 @@
+//! Linear GraphQL API client.
+//!
+//! Everything the bridge asks of Linear goes through [`LinearClient`]; no
+//! other module builds GraphQL strings. The client is optional at runtime:
+//! it only exists when Linear OAuth or `LINEAR_API_KEY` (or
+//! `LINEAR_API_KEY_FILE`) is configured.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode, header::HeaderMap};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tracing::warn;
+
+use super::oauth::OAuth;
+
+const GRAPHQL_URL: &str = "https://api.linear.app/graphql";
+
+/// Rate-limited requests are retried this many times before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 2;
+
+/// Never sleep longer than this for a rate-limit reset. Callers such as the
+/// card callback have to answer Lark within a few seconds.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum LinearError {
+    /// The API key is missing, invalid or revoked.
+    Auth(String),
+    /// The key is valid but not allowed to do this.
+    Forbidden(String),
+    /// Rate limits, timeouts, network errors and Linear-side outages.
+    Transient(String),
+    /// Linear rejected the request, e.g. invalid input.
+    Api(String),
+}
+
+impl fmt::Display for LinearError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinearError::Auth(msg) => write!(f, "linear authentication failed: {msg}"),
+            LinearError::Forbidden(msg) => write!(f, "linear denied the request: {msg}"),
+            LinearError::Transient(msg) => write!(f, "linear temporarily unavailable: {msg}"),
+            LinearError::Api(msg) => write!(f, "linear rejected the request: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for LinearError {}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkflowState {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub position: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Comment {
+    pub body: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    pub user: Option<User>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub name: String,
+}
+
+/// A workspace member, as found by [`LinearClient::user_by_email`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct Member {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: String,
+    pub url: Option<String>,
+    pub label: Option<String>,
+    pub enabled: bool,
+    pub resource_types: Vec<String>,
+}
+
+/// Settings for creating or updating a webhook. The secret is write-only in
+/// Linear, so it is always sent and can never be compared.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookInput<'a> {
+    pub url: &'a str,
+    pub label: &'a str,
+    pub secret: &'a str,
+    pub resource_types: &'a [String],
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphqlError {
+    message: String,
+    #[serde(default)]
+    extensions: GraphqlErrorExtensions,
+}
+
+#[derive(Default, Deserialize)]
+struct GraphqlErrorExtensions {
+    code: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TeamRef {
+    pub key: String,
+    pub name: String,
+}
+
+/// An open issue with a due date, as listed by [`LinearClient::issues_due_by`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DueIssue {
+    pub id: String,
+    pub identifier: String,
+    pub title: String,
+    pub url: String,
+    /// `YYYY-MM-DD`.
+    pub due_date: String,
+    pub assignee: Option<User>,
+    pub team: TeamRef,
+}
+
+/// An open issue under an SLA, as listed by [`LinearClient::open_issues_with_sla`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlaIssue {
+    pub id: String,
+    pub identifier: String,
+    pub title: String,
+    pub url: String,
+    /// Null when the issue has no SLA.
+    pub sla_breaches_at: Option<DateTime<Utc>>,
+    pub assignee: Option<User>,
+    pub team: TeamRef,
+}
+
+/// An issue sitting in a started state, as listed by
+/// [`LinearClient::started_issues_idle_since`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdleIssue {
+    pub identifier: String,
+    pub title: String,
+    pub url: String,
+    /// ISO-8601 UTC.
+    pub updated_at: String,
+    pub assignee: Option<User>,
+    pub team: TeamRef,
+}
+
+#[derive(Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    nodes: Vec<T>,
+    page_info: PageInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MutationResult {
+    success: bool,
+}
+
+/// How requests authenticate.
+pub enum LinearAuth {
+    /// A personal API key, acting as the employee who created it.
+    ApiKey(String),
+    /// OAuth application tokens, acting as the app.
+    OAuth(Arc<OAuth>),
+}
+
+pub struct LinearClient {
+    http: Client,
+    auth: LinearAuth,
+    endpoint: String,
+}
+
+impl LinearClient {
+    pub fn new(auth: LinearAuth, timeout: Duration) -> Self {
+        let http = Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("failed to build linear http client");
+        Self {
+            http,
+            auth,
+            endpoint: GRAPHQL_URL.into(),
+        }
+    }
+
+    /// Sends requests to `endpoint` instead of Linear's, e.g. a mock.
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    async fn authorization(&self) -> Result<String, LinearError> {
+        match &self.auth {
+            LinearAuth::ApiKey(key) => Ok(key.clone()),
+            LinearAuth::OAuth(oauth) => Ok(format!("Bearer {}", oauth.access_token().await?)),
+        }
+    }
+
+    /// Workflow states of a team, in no particular order.
+    pub async fn team_workflow_states(
+        &self,
+        team_id: &str,
+    ) -> Result<Vec<WorkflowState>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data {
+            team: Team,
+        }
+        #[derive(Deserialize)]
+        struct Team {
+            states: Nodes<WorkflowState>,
+        }
+
+        let data: Data = self
+            .request(
+                "query($id: String!) { team(id: $id) { states { nodes { id name type position } } } }",
+                serde_json::json!({ "id": team_id }),
+            )
+            .await?;
+        Ok(data.team.states.nodes)
+    }
+
+    /// The issue's markdown description, `None` when it has none.
+    pub async fn issue_description(&self, issue_id: &str) -> Result<Option<String>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data {
+            issue: Issue,
+        }
+        #[derive(Deserialize)]
+        struct Issue {
+            description: Option<String>,
+        }
+
+        let data: Data = self
+            .request(
+                "query($id: String!) { issue(id: $id) { description } }",
+                serde_json::json!({ "id": issue_id }),
+            )
+            .await?;
+        Ok(data.issue.description.filter(|d| !d.trim().is_empty()))
+    }
+
+    /// The most recently created comment on the issue.
+    pub async fn latest_comment(&self, issue_id: &str) -> Result<Option<Comment>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data {
+            issue: Issue,
+        }
+        #[derive(Deserialize)]
+        struct Issue {
+            comments: Nodes<Comment>,
+        }
+
+        // Linear lists newest first; pick the newest explicitly rather than
+        // rely on that. createdAt is ISO-8601 UTC and sorts lexically.
+        let data: Data = self
+            .request(
+                "query($id: String!) { issue(id: $id) { comments(first: 20, orderBy: createdAt) { nodes { body createdAt user { name } } } } }",
+                serde_json::json!({ "id": issue_id }),
+            )
+            .await?;
+        Ok(data
+            .issue
+            .comments
+            .nodes
+            .into_iter()
+            .max_by(|a, b| a.created_at.cmp(&b.created_at)))
+    }
+
+    pub async fn update_issue_state(
+        &self,
+        issue_id: &str,
+        state_id: &str,
+    ) -> Result<(), LinearError> {
+        self.update_issue(issue_id, serde_json::json!({ "stateId": state_id }))
+            .await
+    }
+
+    pub async fn assign_issue(&self, issue_id: &str, user_id: &str) -> Result<(), LinearError> {
+        self.update_issue(issue_id, serde_json::json!({ "assigneeId": user_id }))
+            .await
+    }
+
+    /// `priority` counts 1 (urgent) to 4 (low), with 0 meaning none.
+    pub async fn update_issue_priority(
+        &self,
+        issue_id: &str,
+        priority: u8,
+    ) -> Result<(), LinearError> {
+        self.update_issue(issue_id, serde_json::json!({ "priority": priority }))
+            .await
+    }
+
+    /// Applies an `IssueUpdateInput`.
+    async fn update_issue(
+        &self,
+        issue_id: &str,
+        input: serde_json::Value,
+    ) -> Result<(), LinearError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Data {
+            issue_update: MutationResult,
+        }
+
+        let data: Data = self
+            .request(
+                "mutation($id: String!, $input: IssueUpdateInput!) { issueUpdate(id: $id, input: $input) { success } }",
+                serde_json::json!({ "id": issue_id, "input": input }),
+            )
+            .await?;
+        check_success(data.issue_update, "issueUpdate")
+    }
+
+    /// The active member with `email`, compared case-insensitively.
+    pub async fn user_by_email(&self, email: &str) -> Result<Option<Member>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data {
+            users: Nodes<Member>,
+        }
+
+        let data: Data = self
+            .request(
+                "query($email: String!) { users(filter: { email: { eqIgnoreCase: $email }, active: { eq: true } }) { nodes { id name } } }",
+                serde_json::json!({ "email": email }),
+            )
+            .await?;
+        Ok(data.users.nodes.into_iter().next())
+    }
+
+    /// Open (not completed or canceled) issues due on or before `date`
+    /// (`YYYY-MM-DD`), optionally limited to some team keys.
+    pub async fn issues_due_by(
+        &self,
+        team_keys: &[String],
+        date: &str,
+    ) -> Result<Vec<DueIssue>, LinearError> {
+        let mut filter = serde_json::json!({
+            "dueDate": { "lte": date },
+            "state": { "type": { "nin": ["completed", "canceled"] } },
+        });
+        if !team_keys.is_empty() {
+            filter["team"] = serde_json::json!({ "key": { "in": team_keys } });
+        }
+
+        self.issues_paginated(
+            "query($filter: IssueFilter, $after: String) { issues(filter: $filter, first: 100, after: $after) { nodes { id identifier title url dueDate assignee { name } team { key name } } pageInfo { hasNextPage endCursor } } }",
+            filter,
+        )
+        .await
+    }
+
+    /// Open issues with an SLA, optionally limited to some team keys. The
+    /// SLA itself is not filtered on here; callers pick the breach window.
+    pub async fn open_issues_with_sla(
+        &self,
+        team_keys: &[String],
+    ) -> Result<Vec<SlaIssue>, LinearError> {
+        let mut filter = serde_json::json!({
+            "state": { "type": { "nin": ["completed", "canceled"] } },
+        });
+        if !team_keys.is_empty() {
+            filter["team"] = serde_json::json!({ "key": { "in": team_keys } });
+        }
+
+        let issues: Vec<SlaIssue> = self
+            .issues_paginated(
+                "query($filter: IssueFilter, $after: String) { issues(filter: $filter, first: 100, after: $after) { nodes { id identifier title url slaBreachesAt assignee { name } team { key name } } pageInfo { hasNextPage endCursor } } }",
+                filter,
+            )
+            .await?;
+        Ok(issues
+            .into_iter()
+            .filter(|i| i.sla_breaches_at.is_some())
+            .collect())
+    }
+
+    /// Issues in a started-type state not updated since `before` (ISO-8601),
+    /// optionally limited to some team keys.
+    pub async fn started_issues_idle_since(
+        &self,
+        team_keys: &[String],
+        before: &str,
+    ) -> Result<Vec<IdleIssue>, LinearError> {
+        let mut filter = serde_json::json!({
+            "updatedAt": { "lt": before },
+            "state": { "type": { "eq": "started" } },
+        });
+        if !team_keys.is_empty() {
+            filter["team"] = serde_json::json!({ "key": { "in": team_keys } });
+        }
+
+        self.issues_paginated(
+            "query($filter: IssueFilter, $after: String) { issues(filter: $filter, first: 100, after: $after) { nodes { identifier title url updatedAt assignee { name } team { key name } } pageInfo { hasNextPage endCursor } } }",
+            filter,
+        )
+        .await
+    }
+
+    /// Follows `issues` pagination until the last page.
+    async fn issues_paginated<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        filter: serde_json::Value,
+    ) -> Result<Vec<T>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data<T> {
+            issues: Page<T>,
+        }
+
+        let mut issues = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let data: Data<T> = self
+                .request(
+                    query,
+                    serde_json::json!({ "filter": filter, "after": after }),
+                )
+                .await?;
+            issues.extend(data.issues.nodes);
+            match data.issues.page_info {
+                PageInfo {
+                    has_next_page: true,
+                    end_cursor: Some(cursor),
+                } => after = Some(cursor),
+                _ => return Ok(issues),
+            }
+        }
+    }
+
+    /// All webhooks visible to the API key. Needs an admin-scoped key.
+    pub async fn webhooks(&self) -> Result<Vec<Webhook>, LinearError> {
+        #[derive(Deserialize)]
+        struct Data {
+            webhooks: Nodes<Webhook>,
+        }
+
+        let data: Data = self
+            .request(
+                "query { webhooks(first: 250) { nodes { id url label enabled resourceTypes } } }",
+                serde_json::json!({}),
+            )
+            .await?;
+        Ok(data.webhooks.nodes)
+    }
+
+    /// Creates a webhook for all public teams and returns its id.
+    pub async fn create_webhook(&self, input: &WebhookInput<'_>) -> Result<String, LinearError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Data {
+            webhook_create: WebhookPayload,
+        }
+        #[derive(Deserialize)]
+        struct WebhookPayload {
+            success: bool,
+            webhook: Option<Created>,
+        }
+        #[derive(Deserialize)]
+        struct Created {
+            id: String,
+        }
+
+        let mut input = serde_json::to_value(input).expect("webhook input serializes");
+        input["allPublicTeams"] = true.into();
+
+        let data: Data = self
+            .request(
+                "mutation($input: WebhookCreateInput!) { webhookCreate(input: $input) { success webhook { id } } }",
+                serde_json::json!({ "input": input }),
+            )
+            .await?;
+        let payload = data.webhook_create;
+        match payload.webhook {
+            Some(webhook) if payload.success => Ok(webhook.id),
+            _ => Err(LinearError::Api("webhookCreate was not successful".into())),
+        }
+    }
+
+    pub async fn update_webhook(
+        &self,
+        id: &str,
+        input: &WebhookInput<'_>,
+    ) -> Result<(), LinearError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Data {
+            webhook_update: MutationResult,
+        }
+
+        let data: Data = self
+            .request(
+                "mutation($id: String!, $input: WebhookUpdateInput!) { webhookUpdate(id: $id, input: $input) { success } }",
+                serde_json::json!({ "id": id, "input": input }),
+            )
+            .await?;
+        check_success(data.webhook_update, "webhookUpdate")
+    }
+
+    pub async fn delete_webhook(&self, id: &str) -> Result<(), LinearError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Data {
+            webhook_delete: MutationResult,
+        }
+
+        let data: Data = self
+            .request(
+                "mutation($id: String!) { webhookDelete(id: $id) { success } }",
+                serde_json::json!({ "id": id }),
+            )
+            .await?;
+        check_success(data.webhook_delete, "webhookDelete")
+    }
+
+    /// Sends a GraphQL request, waiting out rate limits when the reset is
+    /// near enough.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, LinearError> {
+        let body = serde_json::json!({ "query": query, "variables": variables });
+        let mut attempt = 0;
+
+        loop {
+            let authorization = self.authorization().await?;
+            let resp = self
+                .http
+                .post(&self.endpoint)
+                .header("Authorization", authorization)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| LinearError::Transient(e.to_string()))?;
+
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let text = resp
+                .text()
+                .await
+                .map_err(|e| LinearError::Transient(e.to_string()))?;
+            let parsed = serde_json::from_str::<GraphqlResponse<T>>(&text);
+
+            // Linear signals rate limiting both with 429 and with a
+            // RATELIMITED error code on a 400.
+            let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+                || parsed.as_ref().is_ok_and(|r| {
+                    r.errors
+                        .iter()
+                        .any(|e| e.extensions.code.as_deref() == Some("RATELIMITED"))
+                });
+
+            if rate_limited {
+                let wait = rate_limit_wait(&headers);
+                if attempt >= MAX_RATE_LIMIT_RETRIES || wait > MAX_RATE_LIMIT_WAIT {
+                    return Err(LinearError::Transient(format!(
+                        "rate limited, resets in {}s",
+                        wait.as_secs()
+                    )));
+                }
+                attempt += 1;
+                warn!("linear rate limited, retrying in {}ms", wait.as_millis());
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            return match parsed {
+                Ok(resp) => into_result(status, resp),
+                Err(_) if status == StatusCode::UNAUTHORIZED => Err(LinearError::Auth(text)),
+                Err(_) if status.is_server_error() => {
+                    Err(LinearError::Transient(format!("{status}: {text}")))
+                }
+                Err(e) => Err(LinearError::Api(format!(
+                    "unexpected response ({status}): {e}"
+                ))),
+            };
+        }
+    }
+}
+
+fn into_result<T>(status: StatusCode, resp: GraphqlResponse<T>) -> Result<T, LinearError> {
+    if let Some(err) = resp.errors.into_iter().next() {
+        return Err(match err.extensions.code.as_deref() {
+            Some("AUTHENTICATION_ERROR") => LinearError::Auth(err.message),
+            Some("FORBIDDEN") => LinearError::Forbidden(err.message),
+            _ if status == StatusCode::UNAUTHORIZED => LinearError::Auth(err.message),
+            _ if status.is_server_error() => LinearError::Transient(err.message),
+            _ => LinearError::Api(err.message),
+        });
+    }
+    resp.data
+        .ok_or_else(|| LinearError::Api(format!("response without data ({status})")))
+}
+
+fn check_success(result: MutationResult, mutation: &str) -> Result<(), LinearError> {
+    if result.success {
+        Ok(())
+    } else {
+        Err(LinearError::Api(format!("{mutation} was not successful")))
+    }
+}
+
+/// Time until the request quota resets. Linear sends the reset as epoch
+/// milliseconds; without it, back off for a second.
+fn rate_limit_wait(headers: &HeaderMap) -> Duration {
+    let reset_ms = headers
+        .get("x-ratelimit-requests-reset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    match reset_ms {
+        Some(reset) => Duration::from_millis(reset.saturating_sub(now_ms)),
+        None => Duration::from_secs(1),
+    }
+}
@@ -0,0 +1,5 @@
+//! Talking back to Linear.
+
+pub mod api;
+pub mod oauth;
+pub mod registration;
@@ -0,0 +1,225 @@
+//! Linear OAuth, as an alternative to a personal API key.
+//!
+//! An admin visits `/auth/linear` once to install the bridge as an OAuth
+//! application (`actor=app`, so write-backs are attributed to the app
+//! rather than to whoever installed it). The resulting tokens live in the
+//! local store and are refreshed automatically before they expire.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::api::LinearError;
+use crate::storage::Storage;
+
+const AUTHORIZE_URL: &str = "https://linear.app/oauth/authorize";
+const TOKEN_URL: &str = "https://api.linear.app/oauth/token";
+
+const STORE_KEY: &str = "linear_oauth_tokens";
+
+/// Tokens are refreshed this long before they actually expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// How long an authorization `state` stays valid. Plenty for one trip
+/// through the consent screen.
+const STATE_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub struct OAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Our `/auth/linear/callback` URL, as registered with the application.
+    pub redirect_uri: String,
+    pub scopes: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredTokens {
+    access_token: String,
+    refresh_token: Option<String>,
+    /// Unix seconds; `None` for tokens issued without an expiry.
+    expires_at: Option<u64>,
+}
+
+impl StoredTokens {
+    fn needs_refresh(&self) -> bool {
+        self.expires_at
+            .is_some_and(|at| unix_now() + REFRESH_MARGIN.as_secs() >= at)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+pub struct OAuth {
+    config: OAuthConfig,
+    http: Client,
+    storage: Storage,
+    /// Held across refreshes so concurrent requests share one refresh.
+    tokens: tokio::sync::Mutex<Option<StoredTokens>>,
+    pending_states: Mutex<HashMap<String, Instant>>,
+}
+
+impl OAuth {
+    pub async fn new(config: OAuthConfig, http: Client, storage: Storage) -> Self {
+        let tokens = storage
+            .kv()
+            .get::<StoredTokens>(STORE_KEY)
+            .await
+            .unwrap_or_else(|e| {
+                error!("failed to load linear oauth tokens: {e}");
+                None
+            });
+        if tokens.is_none() {
+            info!("linear oauth configured but not authorized yet – visit /auth/linear");
+        }
+        Self {
+            config,
+            http,
+            storage,
+            tokens: tokio::sync::Mutex::new(tokens),
+            pending_states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Linear's consent page URL, with a fresh single-use `state`.
+    pub fn authorize_url(&self) -> String {
+        let state = hex::encode(rand::random::<[u8; 16]>());
+        {
+            let mut pending = self.pending_states.lock().unwrap();
+            pending.retain(|_, at| at.elapsed() < STATE_TTL);
+            pending.insert(state.clone(), Instant::now());
+        }
+
+        Url::parse_with_params(
+            AUTHORIZE_URL,
+            [
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("response_type", "code"),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state.as_str()),
+                ("actor", "app"),
+            ],
+        )
+        .expect("authorize url is valid")
+        .to_string()
+    }
+
+    /// Exchanges the callback's code for tokens and stores them.
+    pub async fn complete(&self, code: &str, state: &str) -> Result<(), LinearError> {
+        let known_state = self
+            .pending_states
+            .lock()
+            .unwrap()
+            .remove(state)
+            .is_some_and(|at| at.elapsed() < STATE_TTL);
+        if !known_state {
+            return Err(LinearError::Auth("unknown or expired oauth state".into()));
+        }
+
+        let tokens = self
+            .token_request(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.config.redirect_uri),
+            ])
+            .await?;
+        self.save(&tokens).await;
+        *self.tokens.lock().await = Some(tokens);
+        info!("linear oauth authorized");
+        Ok(())
+    }
+
+    /// A valid access token, refreshing it first when it is about to expire.
+    pub async fn access_token(&self) -> Result<String, LinearError> {
+        let mut guard = self.tokens.lock().await;
+        let tokens = guard.as_ref().ok_or_else(|| {
+            LinearError::Auth("linear oauth not authorized yet – visit /auth/linear".into())
+        })?;
+
+        if !tokens.needs_refresh() {
+            return Ok(tokens.access_token.clone());
+        }
+
+        let refresh_token = tokens.refresh_token.clone().ok_or_else(|| {
+            LinearError::Auth("linear oauth token expired – visit /auth/linear again".into())
+        })?;
+        let mut refreshed = self
+            .token_request(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &refresh_token),
+            ])
+            .await?;
+        // Linear may keep the refresh token unchanged and omit it.
+        refreshed.refresh_token = refreshed.refresh_token.or(Some(refresh_token));
+
+        self.save(&refreshed).await;
+        info!("linear oauth token refreshed");
+        let access_token = refreshed.access_token.clone();
+        *guard = Some(refreshed);
+        Ok(access_token)
+    }
+
+    async fn token_request(&self, params: &[(&str, &str)]) -> Result<StoredTokens, LinearError> {
+        let mut form = vec![
+            ("client_id", self.config.client_id.as_str()),
+            ("client_secret", self.config.client_secret.as_str()),
+        ];
+        form.extend_from_slice(params);
+
+        let resp = self
+            .http
+            .post(TOKEN_URL)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| LinearError::Transient(e.to_string()))?;
+
+        let status = resp.status();
+        if status.is_server_error() {
+            return Err(LinearError::Transient(format!(
+                "token endpoint returned {status}"
+            )));
+        }
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(LinearError::Auth(format!(
+                "token request rejected ({status}): {text}"
+            )));
+        }
+
+        let token: TokenResponse = resp
+            .json()
+            .await
+            .map_err(|e| LinearError::Api(format!("unexpected token response: {e}")))?;
+        Ok(StoredTokens {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token.expires_in.map(|secs| unix_now() + secs),
+        })
+    }
+
+    async fn save(&self, tokens: &StoredTokens) {
+        // Still usable for this process; only a restart would lose them.
+        if let Err(e) = self.storage.kv().set(STORE_KEY, tokens).await {
+            error!("failed to persist linear oauth tokens: {e}");
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
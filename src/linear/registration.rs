@@ -0,0 +1,144 @@
+//! Keeps the bridge's Linear webhook in sync with its configuration.
+//!
+//! With `AUTO_REGISTER_WEBHOOK=true` the bridge registers itself on startup
+//! instead of someone copying the URL and secret into Linear's settings by
+//! hand. The webhook is identified by its URL, so restarts update the same
+//! webhook rather than piling up new ones.
+
+use tracing::info;
+
+use super::api::{LinearClient, LinearError, WebhookInput};
+
+/// Label shown in Linear's webhook settings.
+const WEBHOOK_LABEL: &str = "linear-lark-bridge";
+
+/// Every payload type the bridge makes cards for.
+const DEFAULT_RESOURCE_TYPES: &[&str] = &["Issue", "Comment", "Project", "ProjectUpdate"];
+
+/// `WEBHOOK_RESOURCE_TYPES`, or [`DEFAULT_RESOURCE_TYPES`] when unset.
+pub fn resource_types(configured: Vec<String>) -> Vec<String> {
+    if configured.is_empty() {
+        DEFAULT_RESOURCE_TYPES
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    } else {
+        configured
+    }
+}
+
+pub struct Registration {
+    /// Full URL of our `/webhook` endpoint.
+    pub url: String,
+    pub secret: String,
+    pub resource_types: Vec<String>,
+}
+
+impl Registration {
+    fn input(&self) -> WebhookInput<'_> {
+        WebhookInput {
+            url: &self.url,
+            label: WEBHOOK_LABEL,
+            secret: &self.secret,
+            resource_types: &self.resource_types,
+            enabled: true,
+        }
+    }
+}
+
+/// Creates the webhook, or brings an existing one for our URL up to date.
+pub async fn register(
+    linear: &LinearClient,
+    registration: &Registration,
+) -> Result<(), LinearError> {
+    let existing = linear
+        .webhooks()
+        .await?
+        .into_iter()
+        .find(|w| w.url.as_deref() == Some(registration.url.as_str()));
+
+    let Some(webhook) = existing else {
+        let id = linear.create_webhook(&registration.input()).await?;
+        info!(
+            "created linear webhook {id} for {} (resource types: {})",
+            registration.url,
+            registration.resource_types.join(", ")
+        );
+        return Ok(());
+    };
+
+    let mut changes = Vec::new();
+    let mut wanted = registration.resource_types.clone();
+    let mut current = webhook.resource_types.clone();
+    wanted.sort();
+    current.sort();
+    if wanted != current {
+        changes.push(format!(
+            "resource types [{}] -> [{}]",
+            current.join(", "),
+            wanted.join(", ")
+        ));
+    }
+    if !webhook.enabled {
+        changes.push("re-enabled".to_string());
+    }
+    if webhook.label.as_deref() != Some(WEBHOOK_LABEL) {
+        changes.push(format!(
+            "label {:?} -> {WEBHOOK_LABEL:?}",
+            webhook.label.as_deref().unwrap_or_default()
+        ));
+    }
+
+    // The secret cannot be read back, so it is always re-applied.
+    linear
+        .update_webhook(&webhook.id, &registration.input())
+        .await?;
+
+    if changes.is_empty() {
+        info!(
+            "linear webhook {} for {} already up to date (secret re-applied)",
+            webhook.id, registration.url
+        );
+    } else {
+        info!(
+            "updated linear webhook {} for {}: {} (secret re-applied)",
+            webhook.id,
+            registration.url,
+            changes.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Deletes every webhook pointing at our URL.
+pub async fn deregister(linear: &LinearClient, url: &str) -> Result<(), LinearError> {
+    let webhooks: Vec<_> = linear
+        .webhooks()
+        .await?
+        .into_iter()
+        .filter(|w| w.url.as_deref() == Some(url))
+        .collect();
+
+    if webhooks.is_empty() {
+        info!("no linear webhook registered for {url}");
+    }
+    for webhook in webhooks {
+        linear.delete_webhook(&webhook.id).await?;
+        info!("deleted linear webhook {} for {url}", webhook.id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_cover_every_card_kind() {
+        assert_eq!(
+            resource_types(Vec::new()),
+            ["Issue", "Comment", "Project", "ProjectUpdate"]
+        );
+        assert_eq!(resource_types(vec!["Issue".into()]), ["Issue"]);
+    }
+}
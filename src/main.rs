@@ -1,17 +1,66 @@
-use std::{env, sync::Arc};
+mod drift;
+mod enrich;
+mod export;
+mod filters;
+mod jobs;
+mod lark;
+mod limits;
+mod linear;
+mod metrics;
+mod reports;
+mod routes;
+mod storage;
+#[cfg(test)]
+mod webhook_tests;
 
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
 use axum::{
-    Router,
-    body::Bytes,
-    extract::State,
-    http::{HeaderMap, StatusCode},
-    routing::post,
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{MatchedPath, Path, Query, Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
 };
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono_tz::Tz;
+use cron::Schedule;
 use hmac::{Hmac, Mac};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha1::{Digest, Sha1};
 use sha2::Sha256;
-use tracing::{error, info, warn};
+use tracing::{Instrument, error, info, info_span, warn};
+
+use crate::drift::ParseMode;
+use crate::enrich::Enricher;
+use crate::filters::IgnoreRules;
+use crate::jobs::due_dates::DueDateConfig;
+use crate::jobs::sla::SlaConfig;
+use crate::jobs::stale::StaleConfig;
+use crate::lark::app::{LarkApp, is_chat_id};
+use crate::lark::contact::Contacts;
+use crate::lark::delivery::{Delivery, DeliveryConfig, Job};
+use crate::lark::errors::{ErrorClass, LarkError};
+use crate::lark::markdown::{escape as escape_md, sanitize as sanitize_md};
+use crate::linear::api::{LinearAuth, LinearClient, LinearError, WorkflowState};
+use crate::linear::oauth::{OAuth, OAuthConfig};
+use crate::linear::registration::{self, Registration};
+use crate::metrics::Metrics;
+use crate::routes::{Destination, RouteKeys, Routes};
+use crate::storage::{Event, HistoryFilter, MappedMessage, Retention, Storage};
 
 // ---------------------------------------------------------------------------
 // Config & shared state
@@ -19,42 +68,594 @@ use tracing::{error, info, warn};
 
 struct AppState {
     webhook_secret: String,
+    /// Default destination: a webhook URL, or a chat id with
+    /// `LARK_DELIVERY=bot`; empty when unset.
     lark_webhook_url: String,
+    /// Per-team and per-project destinations, tried before the default.
+    routes: Routes,
+    /// States, labels and actors that never get a card.
+    ignore: IgnoreRules,
+    lark_verification_token: Option<String>,
+    lark_encrypt_key: Option<String>,
+    /// Receives one-time alerts for configuration-class send failures.
+    lark_ops_webhook_url: Option<String>,
+    /// Lark error codes already alerted on, so ops hear about each once.
+    alerted_error_codes: Mutex<HashSet<i64>>,
+    linear: Option<LinearClient>,
+    /// URL slug of the Linear workspace, for links payloads do not carry.
+    linear_workspace: Option<String>,
+    card_template: Option<CardTemplate>,
+    card_language: CardLanguage,
+    card_include_description: bool,
+    card_include_latest_comment: bool,
+    /// Bot cards older than this are left as they are, and the next change
+    /// posts a new card, so an old issue's card does not change out of
+    /// sight far up the chat.
+    card_update_max_age: Duration,
+    /// Delivery counters whose totals survive restarts; see
+    /// [`jobs::counters`].
+    persisted_counters: Vec<String>,
+    enricher: Enricher,
+    /// Workflow states per Linear team id, resolved lazily for the
+    /// state-transition buttons.
+    workflow_states: Mutex<HashMap<String, Vec<WorkflowState>>>,
+    /// Present when the bridge authenticates to Linear as an OAuth app.
+    oauth: Option<Arc<OAuth>>,
+    /// Guards admin-only endpoints; they refuse everything when unset.
+    admin_token: Option<String>,
+    storage: Storage,
+    retention: Retention,
+    /// Leave issue text (titles) out of history and export responses.
+    redact_content: bool,
+    /// Queue in front of Lark for webhook-triggered cards.
+    delivery: Delivery,
+    metrics: Metrics,
+    /// The Lark app, which posts to chat ids as its bot; present with
+    /// `LARK_APP_ID` and `LARK_APP_SECRET`.
+    lark_app: Option<Arc<LarkApp>>,
+    /// Resolves Linear users to Lark users for @mentions; names are shown
+    /// as text without it.
+    contacts: Option<Contacts>,
+    /// Strict additionally logs payload keys outside the known shape.
+    parse_mode: ParseMode,
+    /// Serves the unsigned `POST /simulate`; see [`DEV_MODE_CONFIRMATION`].
+    dev_mode: bool,
+    /// Timezone for scheduled jobs and "today" semantics.
+    timezone: Tz,
     http: Client,
 }
 
+impl AppState {
+    /// `LARK_WEBHOOK_URL`, unless a routes-only setup left it empty.
+    fn default_webhook(&self) -> Option<&str> {
+        Some(self.lark_webhook_url.as_str()).filter(|url| !url.is_empty())
+    }
+
+    fn card_options(&self) -> CardOptions {
+        let callbacks = self.lark_verification_token.is_some();
+        CardOptions {
+            language: self.card_language,
+            callbacks,
+            transitions: callbacks && self.linear.is_some(),
+            assign: callbacks && self.linear.is_some() && self.contacts.is_some(),
+            updatable: self.lark_app.is_some(),
+        }
+    }
+}
+
+/// Reads a secret from `NAME`, or from the file named by `NAME_FILE` so it
+/// can be mounted instead of passed in the environment.
+fn env_secret(name: &str) -> Option<String> {
+    if let Ok(path) = env::var(format!("{name}_FILE")) {
+        let secret = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read {name}_FILE ({path}): {e}"));
+        return Some(secret.trim().to_string());
+    }
+    env::var(name).ok()
+}
+
+/// Comma-separated list, empty when unset.
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Cron schedule in the `cron` crate's format, which includes seconds:
+/// `0 0 9 * * Mon-Fri` is 09:00 on weekdays.
+fn env_schedule(name: &str) -> Option<Schedule> {
+    let expr = env::var(name).ok()?;
+    Some(
+        expr.parse()
+            .unwrap_or_else(|e| panic!("invalid {name} {expr:?}: {e}")),
+    )
+}
+
 // ---------------------------------------------------------------------------
 // Linear webhook models
 // ---------------------------------------------------------------------------
 
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "RawPayload")]
 struct LinearPayload {
+    action: String,
+    kind: String,
+    data: PayloadData,
+    /// Missing on some payloads, e.g. removals.
+    url: Option<String>,
+    /// Previous values of the fields an `update` changed.
+    updated_from: Option<UpdatedFrom>,
+    /// Who made the change; missing on some automated events.
+    actor: Option<Actor>,
+}
+
+/// First parsing stage: `data` stays raw until `type` says what it is.
+#[derive(Deserialize)]
+struct RawPayload {
     action: String,
     #[serde(rename = "type")]
     kind: String,
-    data: Issue,
-    url: String,
+    data: serde_json::Value,
+    url: Option<String>,
+    #[serde(rename = "updatedFrom")]
+    updated_from: Option<UpdatedFrom>,
+    actor: Option<Actor>,
+}
+
+impl TryFrom<RawPayload> for LinearPayload {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawPayload) -> Result<Self, Self::Error> {
+        let mut data = match raw.kind.as_str() {
+            "Issue" => PayloadData::Issue(serde_json::from_value(raw.data)?),
+            "Comment" => PayloadData::Comment(serde_json::from_value(raw.data)?),
+            "Project" => PayloadData::Project(serde_json::from_value(raw.data)?),
+            "ProjectUpdate" => PayloadData::ProjectUpdate(serde_json::from_value(raw.data)?),
+            "Cycle" => PayloadData::Cycle(serde_json::from_value(raw.data)?),
+            _ => PayloadData::Unknown(raw.data),
+        };
+        let mut url = raw.url;
+        if let Some(url) = &mut url {
+            limits::cap_field(url);
+        }
+        let mut updated_from = raw.updated_from;
+        if let Some(from) = &mut updated_from {
+            let fields = from
+                .title
+                .iter_mut()
+                .chain(from.state.as_mut())
+                .chain(from.name.as_mut())
+                .chain(from.target_date.as_mut().and_then(Option::as_mut));
+            for field in fields {
+                limits::cap_field(field);
+            }
+        }
+        let mut actor = raw.actor;
+        if let Some(actor) = &mut actor {
+            for field in [&mut actor.id, &mut actor.name, &mut actor.email]
+                .into_iter()
+                .flatten()
+            {
+                limits::cap_field(field);
+            }
+        }
+        data.apply_limits();
+        Ok(Self {
+            action: raw.action,
+            kind: raw.kind,
+            data,
+            url,
+            updated_from,
+            actor,
+        })
+    }
+}
+
+/// The payload's `data`, by its `type`. Kinds the bridge does not know
+/// parse as `Unknown` so they can be acknowledged and ignored.
+#[derive(Debug)]
+enum PayloadData {
+    Issue(Box<Issue>),
+    Comment(Box<CommentData>),
+    Project(Box<ProjectData>),
+    /// A status post on a project, not an edit of the project.
+    ProjectUpdate(Box<ProjectUpdateData>),
+    Cycle(CycleData),
+    Unknown(serde_json::Value),
+}
+
+impl PayloadData {
+    /// Caps every text field, see [`limits`]. Done while parsing so no
+    /// later step sees more.
+    fn apply_limits(&mut self) {
+        match self {
+            Self::Issue(issue) => issue.apply_limits(),
+            Self::Comment(comment) => comment.apply_limits(),
+            Self::Project(project) => project.apply_limits(),
+            Self::ProjectUpdate(update) => update.apply_limits(),
+            Self::Cycle(cycle) => {
+                if let Some(name) = &mut cycle.name {
+                    limits::cap_field(name);
+                }
+            }
+            // Never read beyond `describe`.
+            Self::Unknown(_) => {}
+        }
+    }
+
+    /// Short description for logs.
+    fn describe(&self) -> String {
+        match self {
+            Self::Issue(issue) => issue.identifier.clone(),
+            Self::Comment(comment) => match &comment.issue_id {
+                Some(issue_id) => format!("comment {} on issue {issue_id}", comment.id),
+                None => format!("comment {}", comment.id),
+            },
+            Self::Project(project) => format!("project {}", project.name),
+            Self::ProjectUpdate(update) => match &update.project {
+                Some(project) => format!("update {} on project {}", update.id, project.name),
+                None => format!("project update {}", update.id),
+            },
+            Self::Cycle(cycle) => match &cycle.name {
+                Some(name) => format!("cycle {} ({name})", cycle.number),
+                None => format!("cycle {}", cycle.number),
+            },
+            Self::Unknown(data) => data
+                .get("id")
+                .and_then(|id| id.as_str())
+                .unwrap_or("?")
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentData {
+    id: String,
+    #[serde(default)]
+    body: String,
+    #[serde(rename = "issueId")]
+    issue_id: Option<String>,
+    /// Missing on comments that are not on an issue, e.g. project updates.
+    issue: Option<CommentIssue>,
+    user: Option<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentIssue {
+    id: String,
+    identifier: String,
+    title: String,
+    team: Option<Team>,
+    url: Option<String>,
+}
+
+impl CommentData {
+    fn apply_limits(&mut self) {
+        limits::cap_field(&mut self.id);
+        let mut size = self.id.len();
+        if let Some(issue_id) = &mut self.issue_id {
+            limits::cap_field(issue_id);
+            size += issue_id.len();
+        }
+        if let Some(issue) = &mut self.issue {
+            let team = issue
+                .team
+                .iter_mut()
+                .flat_map(|t| std::iter::once(&mut t.key).chain(t.name.as_mut()));
+            let fields = [&mut issue.id, &mut issue.identifier, &mut issue.title]
+                .into_iter()
+                .chain(issue.url.as_mut())
+                .chain(team);
+            for field in fields {
+                limits::cap_field(field);
+                size += field.len();
+            }
+        }
+        if let Some(user) = &mut self.user {
+            user.apply_limits();
+            size += user.name.len();
+        }
+        limits::cap_text(&mut self.body);
+        limits::cap_bytes(&mut self.body, limits::MAX_EVENT_BYTES.saturating_sub(size));
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectData {
+    id: String,
+    name: String,
+    /// `backlog`, `planned`, `started`, `paused`, `completed` or `canceled`.
+    state: Option<String>,
+    /// `YYYY-MM-DD`.
+    #[serde(rename = "targetDate")]
+    target_date: Option<String>,
+    /// 0 to 1.
+    progress: Option<f64>,
+    lead: Option<User>,
+}
+
+impl ProjectData {
+    fn apply_limits(&mut self) {
+        for field in [&mut self.id, &mut self.name]
+            .into_iter()
+            .chain(self.state.as_mut())
+            .chain(self.target_date.as_mut())
+        {
+            limits::cap_field(field);
+        }
+        if let Some(lead) = &mut self.lead {
+            lead.apply_limits();
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectUpdateData {
+    id: String,
+    #[serde(default)]
+    body: String,
+    /// `onTrack`, `atRisk` or `offTrack`.
+    health: Option<String>,
+    project: Option<ProjectRef>,
+    user: Option<User>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectRef {
+    id: String,
+    name: String,
+    url: Option<String>,
+}
+
+impl ProjectUpdateData {
+    fn apply_limits(&mut self) {
+        let project = self
+            .project
+            .iter_mut()
+            .flat_map(|p| [&mut p.id, &mut p.name].into_iter().chain(p.url.as_mut()));
+        let mut size = 0;
+        for field in [&mut self.id]
+            .into_iter()
+            .chain(self.health.as_mut())
+            .chain(self.url.as_mut())
+            .chain(project)
+        {
+            limits::cap_field(field);
+            size += field.len();
+        }
+        if let Some(user) = &mut self.user {
+            user.apply_limits();
+            size += user.name.len();
+        }
+        limits::cap_text(&mut self.body);
+        limits::cap_bytes(&mut self.body, limits::MAX_EVENT_BYTES.saturating_sub(size));
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleData {
+    number: f64,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdatedFrom {
+    #[serde(rename = "stateId")]
+    state_id: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_priority")]
+    priority: Option<u8>,
+    title: Option<String>,
+    /// `Some(None)` when the issue was unassigned before.
+    #[serde(
+        default,
+        rename = "assigneeId",
+        deserialize_with = "deserialize_present"
+    )]
+    assignee_id: Option<Option<String>>,
+    /// Projects: the previous state, by name.
+    state: Option<String>,
+    /// Projects: `Some(None)` when there was no target date.
+    #[serde(
+        default,
+        rename = "targetDate",
+        deserialize_with = "deserialize_present"
+    )]
+    target_date: Option<Option<String>>,
+    /// Projects: the previous name.
+    name: Option<String>,
+}
+
+impl UpdatedFrom {
+    /// The changed fields a card is sent for; edits to anything else
+    /// (description, labels, estimates, ...) stay quiet.
+    fn notable_fields(&self) -> Vec<&'static str> {
+        [
+            ("state", self.state_id.is_some()),
+            ("priority", self.priority.is_some()),
+            ("assignee", self.assignee_id.is_some()),
+            ("title", self.title.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(field, changed)| changed.then_some(field))
+        .collect()
+    }
+}
+
+/// Tells a field sent as `null` (`Some(None)`) from one left out (`None`).
+fn deserialize_present<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<String>>, D::Error> {
+    Ok(Some(Option::deserialize(deserializer)?))
 }
 
+/// `remove` payloads can be as small as id, identifier and title, so
+/// everything else is optional.
 #[derive(Debug, Deserialize)]
 struct Issue {
-    #[allow(dead_code)]
     id: String,
     title: String,
-    priority: u8,
-    state: IssueState,
-    assignee: Option<Assignee>,
+    #[serde(default, deserialize_with = "deserialize_optional_priority")]
+    priority: Option<u8>,
+    state: Option<IssueState>,
+    assignee: Option<User>,
     identifier: String,
+    #[serde(rename = "teamId")]
+    team_id: Option<String>,
+    team: Option<Team>,
+    project: Option<Project>,
+    #[serde(rename = "projectId")]
+    project_id: Option<String>,
+    #[serde(default)]
+    labels: Vec<IssueLabel>,
+    /// `YYYY-MM-DD`.
+    #[serde(rename = "dueDate")]
+    due_date: Option<String>,
+    /// In the team's estimate scale, e.g. points.
+    estimate: Option<f64>,
+    description: Option<String>,
+    #[serde(rename = "createdAt")]
+    created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "completedAt")]
+    completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "archivedAt")]
+    archived_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Set on `remove` when the issue went to the trash rather than the archive.
+    trashed: Option<bool>,
+}
+
+impl Issue {
+    fn apply_limits(&mut self) {
+        let (team_key, team_name) = match &mut self.team {
+            Some(team) => (Some(&mut team.key), team.name.as_mut()),
+            None => (None, None),
+        };
+        let (project_name, project_id) = match &mut self.project {
+            Some(project) => (Some(&mut project.name), project.id.as_mut()),
+            None => (None, None),
+        };
+        let fields = [
+            Some(&mut self.id),
+            Some(&mut self.identifier),
+            Some(&mut self.title),
+            self.team_id.as_mut(),
+            self.state.as_mut().map(|s| &mut s.name),
+            team_key,
+            team_name,
+            project_name,
+            project_id,
+            self.project_id.as_mut(),
+            self.due_date.as_mut(),
+        ];
+        let mut size = 0;
+        for field in fields.into_iter().flatten() {
+            limits::cap_field(field);
+            size += field.len();
+        }
+        if let Some(assignee) = &mut self.assignee {
+            assignee.apply_limits();
+            size += assignee.name.len();
+        }
+        self.labels.truncate(limits::MAX_LABELS);
+        for label in &mut self.labels {
+            limits::cap_field(&mut label.name);
+            size += label.name.len();
+        }
+        if let Some(description) = &mut self.description {
+            limits::cap_text(description);
+            limits::cap_bytes(description, limits::MAX_EVENT_BYTES.saturating_sub(size));
+        }
+    }
+
+    /// The card action for a `remove` payload, which Linear sends for both
+    /// deleting and archiving.
+    fn removal(&self) -> &'static str {
+        if self.archived_at.is_some() && self.trashed != Some(true) {
+            "archive"
+        } else {
+            "delete"
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Team {
+    key: String,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    id: Option<String>,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssueLabel {
+    name: String,
+}
+
+/// Linear sends priority as an integer, or as a float (`2.0`) in some
+/// payloads, and leaves it null or out in others. Normalized to 0 (no
+/// priority) through 4 (low).
+fn normalize_priority(priority: f64) -> u8 {
+    if priority.is_finite() {
+        priority.round().clamp(0.0, 4.0) as u8
+    } else {
+        0
+    }
+}
+
+fn deserialize_optional_priority<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u8>, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.map(normalize_priority))
 }
 
 #[derive(Debug, Deserialize)]
 struct IssueState {
     name: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-struct Assignee {
+struct User {
     name: String,
+    /// For the Lark @mention, see [`lark::contact`].
+    email: Option<String>,
+}
+
+/// The user, integration or OAuth app behind a webhook.
+#[derive(Debug, Deserialize)]
+struct Actor {
+    id: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+impl Actor {
+    /// Everything `IGNORE_ACTORS` can name it by.
+    fn names(&self) -> Vec<&str> {
+        [&self.name, &self.email, &self.id]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+impl User {
+    fn apply_limits(&mut self) {
+        limits::cap_field(&mut self.name);
+        if let Some(email) = &mut self.email {
+            limits::cap_field(email);
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -69,8 +670,19 @@ struct LarkMessage {
 
 #[derive(Serialize)]
 struct LarkCard {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<LarkCardConfig>,
     header: LarkHeader,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     elements: Vec<serde_json::Value>,
+    /// Per-locale bodies, used instead of `elements` for i18n cards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    i18n_elements: Option<BTreeMap<&'static str, Vec<serde_json::Value>>>,
+}
+
+#[derive(Serialize)]
+struct LarkCardConfig {
+    update_multi: bool,
 }
 
 #[derive(Serialize)]
@@ -83,19 +695,66 @@ struct LarkHeader {
 struct LarkTitle {
     content: String,
     tag: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    i18n: Option<BTreeMap<&'static str, String>>,
 }
 
 // ---------------------------------------------------------------------------
 // Signature verification
 // ---------------------------------------------------------------------------
 
+/// The `linear-signature` header as proxies may pass it on: values are
+/// trimmed, and repeats (as separate headers or folded into one comma
+/// list) are fine as long as they agree. Errors mean a header that cannot
+/// be a signature at all, as opposed to one that does not verify.
+fn linear_signature(headers: &HeaderMap) -> Result<Option<&str>, &'static str> {
+    let mut signature = None;
+    for value in headers.get_all("linear-signature") {
+        let value = value.to_str().map_err(|_| "not valid ascii")?;
+        for candidate in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match signature {
+                None => signature = Some(candidate),
+                Some(seen) if seen == candidate => {}
+                Some(_) => return Err("conflicting values"),
+            }
+        }
+    }
+    Ok(signature)
+}
+
 fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
     let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
         return false;
     };
     mac.update(body);
     let expected = hex::encode(mac.finalize().into_bytes());
-    expected == signature
+    constant_time_eq(expected.as_bytes(), signature.as_bytes())
+}
+
+/// Compares secrets without returning at the first differing byte, so the
+/// time taken does not tell how much of a guess was right. Only the length
+/// leaks.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Lark signs card callbacks as `sha1(timestamp + nonce + token + body)`.
+fn verify_lark_signature(
+    token: &str,
+    timestamp: &str,
+    nonce: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let mut hasher = Sha1::new();
+    hasher.update(timestamp.as_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.update(token.as_bytes());
+    hasher.update(body);
+    constant_time_eq(
+        hex::encode(hasher.finalize()).as_bytes(),
+        signature.as_bytes(),
+    )
 }
 
 // ---------------------------------------------------------------------------
@@ -111,182 +770,3689 @@ fn priority_color(priority: u8) -> &'static str {
     }
 }
 
-fn priority_label(priority: u8) -> &'static str {
-    match priority {
-        1 => "Urgent",
-        2 => "High",
-        3 => "Medium",
-        4 => "Low",
-        _ => "None",
-    }
-}
-
 // ---------------------------------------------------------------------------
 // Build the Lark interactive card
 // ---------------------------------------------------------------------------
 
-fn build_lark_card(payload: &LinearPayload) -> LarkMessage {
-    let color = priority_color(payload.data.priority);
-    let action_label = match payload.action.as_str() {
-        "create" => "Created",
-        "update" => "Updated",
-        _ => &payload.action,
-    };
+/// Everything the card shows about an issue. Kept in the store for cards
+/// with buttons, so a card callback can re-render the card without looking
+/// the issue up again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IssueSummary {
+    id: String,
+    action: String,
+    identifier: String,
+    title: String,
+    /// Missing on deletion and archive cards.
+    state: Option<String>,
+    state_type: Option<String>,
+    priority: Option<u8>,
+    assignee: Option<String>,
+    /// Mentions the assignee instead of naming them when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assignee_open_id: Option<String>,
+    url: Option<String>,
+    team_id: Option<String>,
+    /// Already truncated to [`MAX_DESCRIPTION_CHARS`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_comment: Option<CommentSnippet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    acknowledged_by: Option<String>,
+    /// What an `update` changed; empty for every other action.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
+    /// `YYYY-MM-DD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due_date: Option<String>,
+    /// As of when the card was built, see [`IssueSummary::is_overdue_on`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    overdue: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate: Option<f64>,
+    /// What the delivery worker still has to look up before sending.
+    #[serde(default, skip_serializing_if = "IssueLookups::is_empty")]
+    lookups: IssueLookups,
+}
 
-    let assignee = payload
-        .data
-        .assignee
-        .as_ref()
-        .map(|a| a.name.as_str())
-        .unwrap_or("Unassigned");
+/// What an issue card still needs from Linear and Lark. The webhook handler
+/// only notes it, and the delivery worker looks it up right before the card
+/// is sent, so Linear's webhook is answered without waiting on either.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct IssueLookups {
+    /// Of a status change, to name the state it came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous_state_id: Option<String>,
+    /// Of the assignee, to mention them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assignee_email: Option<String>,
+    /// The current description, which the payload's copy may cut short.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    description: bool,
+    /// The latest comment, for state transitions.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    latest_comment: bool,
+}
 
-    let title_element = serde_json::json!({
-        "tag": "div",
-        "text": {
-            "tag": "lark_md",
-            "content": format!("**{}**", payload.data.title),
-        }
-    });
+impl IssueLookups {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
 
-    let fields_element = serde_json::json!({
-        "tag": "div",
-        "fields": [
-            {
-                "is_short": true,
-                "text": {
-                    "tag": "lark_md",
-                    "content": format!("**Status:** {}", payload.data.state.name),
-                }
-            },
-            {
-                "is_short": true,
-                "text": {
-                    "tag": "lark_md",
-                    "content": format!("**Priority:** {}", priority_label(payload.data.priority)),
-                }
-            },
-            {
-                "is_short": true,
-                "text": {
-                    "tag": "lark_md",
-                    "content": format!("**Assignee:** {}", assignee),
-                }
-            }
-        ]
-    });
+/// One changed field, shown as "previous → current". The current value is
+/// the summary's own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+enum Change {
+    /// The name is only known once looked up from the previous state id.
+    Status {
+        from: Option<String>,
+    },
+    Priority {
+        from: u8,
+    },
+    /// Payloads only carry the previous assignee's id.
+    Assignee {
+        previously_unassigned: bool,
+    },
+    Title {
+        from: String,
+    },
+}
 
-    let action_element = serde_json::json!({
-        "tag": "action",
-        "actions": [
-            {
-                "tag": "button",
-                "text": {
-                    "tag": "plain_text",
-                    "content": "View in Linear"
-                },
-                "type": "primary",
-                "url": payload.url,
+impl Change {
+    fn render(&self, issue: &IssueSummary, labels: &Labels) -> String {
+        let (label, from, to) = match self {
+            Change::Status { from } => (
+                labels.status,
+                from.as_deref().map(escape_md),
+                escape_md(issue.state.as_deref().unwrap_or_default()),
+            ),
+            Change::Priority { from } => (
+                labels.priority,
+                Some(labels.priority_label(*from).into()),
+                labels.priority_label(issue.priority.unwrap_or(0)).into(),
+            ),
+            Change::Assignee {
+                previously_unassigned,
+            } => (
+                labels.assignee,
+                previously_unassigned.then(|| labels.unassigned.into()),
+                escape_md(issue.assignee.as_deref().unwrap_or(labels.unassigned)),
+            ),
+            Change::Title { from } => {
+                (labels.title, Some(escape_md(from)), escape_md(&issue.title))
             }
-        ]
-    });
-
-    LarkMessage {
-        msg_type: "interactive",
-        card: LarkCard {
-            header: LarkHeader {
-                template: color,
-                title: LarkTitle {
-                    content: format!(
-                        "[Linear] {}: {}",
-                        action_label, payload.data.identifier
-                    ),
-                    tag: "plain_text",
-                },
-            },
-            elements: vec![title_element, fields_element, action_element],
-        },
+        };
+        match from {
+            Some(from) => format!("{label}: {from} → {to}"),
+            None => format!("{label} {} → {to}", labels.changed),
+        }
     }
 }
 
-// ---------------------------------------------------------------------------
-// Webhook handler
-// ---------------------------------------------------------------------------
-
-async fn webhook_handler(
-    State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    body: Bytes,
-) -> StatusCode {
-    // 1. Signature verification
-    let signature = match headers.get("linear-signature").and_then(|v| v.to_str().ok()) {
-        Some(s) => s,
-        None => {
-            warn!("missing linear-signature header");
-            return StatusCode::UNAUTHORIZED;
-        }
-    };
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CommentSnippet {
+    author: Option<String>,
+    body: String,
+}
 
-    if !verify_signature(&state.webhook_secret, &body, signature) {
-        warn!("invalid webhook signature");
-        return StatusCode::UNAUTHORIZED;
+fn changes(from: &UpdatedFrom, issue: &Issue) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if from.state_id.is_some() {
+        changes.push(Change::Status { from: None });
+    }
+    if let Some(priority) = from.priority {
+        changes.push(Change::Priority { from: priority });
+    }
+    if let Some(previous) = &from.assignee_id {
+        changes.push(Change::Assignee {
+            previously_unassigned: previous.is_none(),
+        });
+    }
+    if let Some(title) = from.title.as_ref().filter(|t| **t != issue.title) {
+        changes.push(Change::Title {
+            from: title.clone(),
+        });
     }
+    changes
+}
 
-    // 2. Deserialize payload
-    let payload: LinearPayload = match serde_json::from_slice(&body) {
-        Ok(p) => p,
-        Err(e) => {
-            error!("failed to parse payload: {e}");
-            return StatusCode::BAD_REQUEST;
+impl IssueSummary {
+    /// `workspace` is the Linear URL slug, used to link the issue when the
+    /// payload has no `url`.
+    fn from_payload(payload: &LinearPayload, issue: &Issue, workspace: Option<&str>) -> Self {
+        Self {
+            id: issue.id.clone(),
+            action: match payload.action.as_str() {
+                "remove" => issue.removal().to_string(),
+                action => action.to_string(),
+            },
+            identifier: issue.identifier.clone(),
+            title: issue.title.clone(),
+            state: issue.state.as_ref().map(|s| s.name.clone()),
+            state_type: issue.state.as_ref().and_then(|s| s.kind.clone()),
+            priority: issue.priority,
+            assignee: issue.assignee.as_ref().map(|a| a.name.clone()),
+            assignee_open_id: None,
+            url: payload.url.clone().or_else(|| {
+                workspace.map(|w| format!("https://linear.app/{w}/issue/{}", issue.identifier))
+            }),
+            team_id: issue.team_id.clone(),
+            description: None,
+            latest_comment: None,
+            acknowledged_by: None,
+            changes: match (payload.action.as_str(), &payload.updated_from) {
+                ("update", Some(from)) => changes(from, issue),
+                _ => Vec::new(),
+            },
+            labels: issue.labels.iter().map(|l| l.name.clone()).collect(),
+            due_date: issue.due_date.clone(),
+            overdue: false,
+            estimate: issue.estimate,
+            lookups: IssueLookups {
+                previous_state_id: payload
+                    .updated_from
+                    .as_ref()
+                    .and_then(|from| from.state_id.clone()),
+                assignee_email: issue.assignee.as_ref().and_then(|a| a.email.clone()),
+                description: false,
+                latest_comment: false,
+            },
         }
-    };
-
-    // 3. Filter: only Issue create / update
-    if payload.kind != "Issue" || !matches!(payload.action.as_str(), "create" | "update") {
-        info!(
-            "ignoring event: type={}, action={}",
-            payload.kind, payload.action
-        );
-        return StatusCode::OK;
     }
 
-    info!(
-        "processing {} {} – {}",
-        payload.action, payload.data.identifier, payload.data.title
-    );
-
-    // 4. Build & send Lark card
-    let card = build_lark_card(&payload);
+    /// Whether the due date is before `today` while the issue is still
+    /// open. Removed issues and unparsable dates are never overdue.
+    fn is_overdue_on(&self, today: chrono::NaiveDate) -> bool {
+        let open = !self.is_removed()
+            && !matches!(self.state_type.as_deref(), Some("completed" | "canceled"));
+        let due = self
+            .due_date
+            .as_deref()
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+        open && due.is_some_and(|due| due < today)
+    }
 
-    match state
-        .http
-        .post(&state.lark_webhook_url)
-        .json(&card)
-        .send()
-        .await
-    {
-        Ok(resp) => {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            if status.is_success() {
-                info!("lark notification sent: {text}");
-            } else {
-                error!("lark returned {status}: {text}");
+    /// Takes over `later`, a subsequent update of the same issue, keeping
+    /// for each changed field the value from before the first update.
+    fn absorb(&mut self, later: IssueSummary) {
+        let mut changes = std::mem::take(&mut self.changes);
+        for change in later.changes.iter() {
+            let field = std::mem::discriminant(change);
+            if !changes.iter().any(|c| std::mem::discriminant(c) == field) {
+                changes.push(change.clone());
             }
         }
-        Err(e) => {
-            error!("failed to send lark notification: {e}");
+        // A status change kept from this update is named from its own
+        // previous state.
+        let previous_state_id = self.lookups.previous_state_id.take();
+        let latest_comment = self.lookups.latest_comment;
+        *self = later;
+        self.changes = changes;
+        if previous_state_id.is_some() {
+            self.lookups.previous_state_id = previous_state_id;
         }
+        self.lookups.latest_comment |= latest_comment;
     }
 
-    StatusCode::OK
-}
+    fn set_description(&mut self, description: &str) {
+        self.description = Some(truncate_chars(description.trim(), MAX_DESCRIPTION_CHARS));
+    }
 
+    fn action_label<'a>(&'a self, labels: &'a Labels) -> &'a str {
+        match self.action.as_str() {
+            "create" => labels.created,
+            "update" => labels.updated,
+            "delete" => labels.deleted,
+            "archive" => labels.archived,
+            _ => &self.action,
+        }
+    }
+
+    /// Deleted or archived: the card is a notice, with nothing left to act on.
+    fn is_removed(&self) -> bool {
+        matches!(self.action.as_str(), "delete" | "archive")
+    }
+
+    fn is_completed(&self) -> bool {
+        self.state_type.as_deref() == Some("completed")
+    }
+}
+
+/// Value attached to card buttons and posted back to `/lark/card-callback`.
+/// Only the issue id: the rest is in the store, and a whole summary per
+/// button and locale would multiply the card's size.
+#[derive(Debug, Serialize, Deserialize)]
+struct CardAction {
+    action: String,
+    issue_id: String,
+}
+
+/// How cards are rendered: language and which callback buttons they carry.
+#[derive(Debug, Clone, Copy, Default)]
+struct CardOptions {
+    language: CardLanguage,
+    /// "Ack", needs the card callback endpoint to be configured.
+    callbacks: bool,
+    /// "Start" / "Done" / "Set Urgent", additionally need a Linear API key.
+    transitions: bool,
+    /// "Assign to me", additionally needs the Lark app to look up who
+    /// clicked.
+    assign: bool,
+    /// Bot messages in chats are edited when the issue changes.
+    updatable: bool,
+}
+
+/// Descriptions are cut to this many characters; the card links to the rest.
+const MAX_DESCRIPTION_CHARS: usize = 500;
+
+/// Length of the latest-comment excerpt on state-transition cards.
+const MAX_COMMENT_CHARS: usize = 200;
+
+/// Comment bodies on comment cards are cut to this many characters.
+const MAX_COMMENT_BODY_CHARS: usize = 500;
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn build_lark_card(issue: &IssueSummary, options: CardOptions) -> LarkMessage {
+    LarkMessage {
+        msg_type: "interactive",
+        card: render_issue_card(issue, options, None),
+    }
+}
+
+fn callback_button(
+    label: &str,
+    kind: &str,
+    action: &str,
+    issue: &IssueSummary,
+) -> serde_json::Value {
+    let value = CardAction {
+        action: action.into(),
+        issue_id: issue.id.clone(),
+    };
+    serde_json::json!({
+        "tag": "button",
+        "text": {
+            "tag": "plain_text",
+            "content": label
+        },
+        "type": kind,
+        "value": value,
+    })
+}
+
+/// Lark rejects cards whose JSON is larger than 30KB. Kept a little under
+/// that to leave room for the message around the card.
+const MAX_CARD_BYTES: usize = 28 * 1024;
+
+/// Renders the issue card. `action_error` is why the card action that
+/// triggered the render failed, shown above the buttons.
+///
+/// A card over [`MAX_CARD_BYTES`] loses its labels first, then its
+/// description and comment excerpt are halved until it fits. An i18n card
+/// carries every language, so it is measured as a whole.
+fn render_issue_card(
+    issue: &IssueSummary,
+    options: CardOptions,
+    action_error: Option<&str>,
+) -> LarkCard {
+    let card = issue_card(issue, options, action_error);
+    if card_bytes(&card) <= MAX_CARD_BYTES {
+        return card;
+    }
+    let mut issue = issue.clone();
+    issue.labels.clear();
+    loop {
+        let card = issue_card(&issue, options, action_error);
+        if card_bytes(&card) <= MAX_CARD_BYTES || !shorten(&mut issue) {
+            return card;
+        }
+    }
+}
+
+fn card_bytes(card: &LarkCard) -> usize {
+    serde_json::to_vec(card).map_or(0, |json| json.len())
+}
+
+/// Halves the description, or once it is gone the comment excerpt, then
+/// drops the changes. False when nothing is left to cut.
+fn shorten(issue: &mut IssueSummary) -> bool {
+    fn halve(text: &mut Option<String>) -> bool {
+        let Some(value) = text else { return false };
+        let chars = value.chars().count();
+        if chars < 20 {
+            *text = None;
+        } else {
+            *value = truncate_chars(value, chars / 2);
+        }
+        true
+    }
+
+    if halve(&mut issue.description) {
+        return true;
+    }
+    if let Some(comment) = &mut issue.latest_comment {
+        let mut body = Some(std::mem::take(&mut comment.body));
+        halve(&mut body);
+        match body {
+            Some(body) => comment.body = body,
+            None => issue.latest_comment = None,
+        }
+        return true;
+    }
+    !std::mem::take(&mut issue.changes).is_empty()
+}
+
+fn issue_card(issue: &IssueSummary, options: CardOptions, action_error: Option<&str>) -> LarkCard {
+    // Completed issues turn green and removed ones grey, regardless of
+    // priority.
+    let color = if issue.is_removed() {
+        "grey"
+    } else if issue.is_completed() {
+        "green"
+    } else {
+        priority_color(issue.priority.unwrap_or(0))
+    };
+
+    localized_card(
+        options.language,
+        color,
+        // Shared cards so a callback update is visible to the whole group,
+        // not just the person who clicked. Lark only lets the bot edit
+        // shared cards, too.
+        options.callbacks || options.updatable,
+        |labels| {
+            format!(
+                "[Linear] {}: {}",
+                issue.action_label(labels),
+                issue.identifier
+            )
+        },
+        |labels| render_elements(issue, options, labels, action_error),
+    )
+}
+
+/// A card in `language`, with the header and body rendered per language by
+/// `header_title` and `body`. `shared` cards update for everyone in the
+/// chat when a callback changes them.
+fn localized_card(
+    language: CardLanguage,
+    color: &'static str,
+    shared: bool,
+    header_title: impl Fn(&Labels) -> String,
+    body: impl Fn(&Labels) -> Vec<serde_json::Value>,
+) -> LarkCard {
+    let (title, elements, i18n_elements) = match language {
+        CardLanguage::Single(lang) => {
+            let labels = lang.labels();
+            let title = LarkTitle {
+                content: header_title(labels),
+                tag: "plain_text",
+                i18n: None,
+            };
+            (title, body(labels), None)
+        }
+        CardLanguage::I18n => {
+            // English doubles as the fallback for clients in other languages.
+            let title = LarkTitle {
+                content: header_title(Lang::En.labels()),
+                tag: "plain_text",
+                i18n: Some(
+                    Lang::ALL
+                        .iter()
+                        .map(|lang| (lang.code(), header_title(lang.labels())))
+                        .collect(),
+                ),
+            };
+            let i18n_elements = Lang::ALL
+                .iter()
+                .map(|lang| (lang.code(), body(lang.labels())))
+                .collect();
+            (title, Vec::new(), Some(i18n_elements))
+        }
+    };
+
+    LarkCard {
+        config: shared.then_some(LarkCardConfig { update_multi: true }),
+        header: LarkHeader {
+            template: color,
+            title,
+        },
+        elements,
+        i18n_elements,
+    }
+}
+
+/// Card body in one language. Only labels are translated, issue content is
+/// shown as-is.
+fn render_elements(
+    issue: &IssueSummary,
+    options: CardOptions,
+    labels: &Labels,
+    action_error: Option<&str>,
+) -> Vec<serde_json::Value> {
+    let assignee = mention_or_name(
+        issue.assignee_open_id.as_deref(),
+        issue.assignee.as_deref().unwrap_or(labels.unassigned),
+    );
+
+    let title_element = serde_json::json!({
+        "tag": "div",
+        "text": {
+            "tag": "lark_md",
+            "content": format!("**{}**", escape_md(&issue.title)),
+        }
+    });
+
+    let description_element = issue.description.as_ref().map(|description| {
+        serde_json::json!({
+            "tag": "div",
+            "text": {
+                "tag": "lark_md",
+                "content": sanitize_md(description),
+            }
+        })
+    });
+
+    // Removal payloads may lack state and priority; those fields are left
+    // out instead of showing a placeholder.
+    let fields: Vec<_> = [
+        issue
+            .state
+            .as_ref()
+            .map(|state| format!("**{}:** {}", labels.status, escape_md(state))),
+        // An issue that still exists without a priority shows "None".
+        issue
+            .priority
+            .or((!issue.is_removed()).then_some(0))
+            .map(|priority| {
+                format!(
+                    "**{}:** {}",
+                    labels.priority,
+                    labels.priority_label(priority)
+                )
+            }),
+        Some(format!("**{}:** {}", labels.assignee, assignee)),
+        issue.due_date.as_ref().map(|due| {
+            let due = escape_md(due);
+            if issue.overdue {
+                format!("**{}:** <font color='red'>{due}</font>", labels.due_date)
+            } else {
+                format!("**{}:** {due}", labels.due_date)
+            }
+        }),
+        issue
+            .estimate
+            .map(|estimate| format!("**{}:** {estimate}", labels.estimate)),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|content| {
+        serde_json::json!({
+            "is_short": true,
+            "text": {
+                "tag": "lark_md",
+                "content": content,
+            }
+        })
+    })
+    .collect();
+    let fields_element = serde_json::json!({
+        "tag": "div",
+        "fields": fields,
+    });
+
+    let changes_element = (!issue.changes.is_empty()).then(|| {
+        let lines: Vec<String> = issue
+            .changes
+            .iter()
+            .map(|change| change.render(issue, labels))
+            .collect();
+        serde_json::json!({
+            "tag": "div",
+            "text": {
+                "tag": "lark_md",
+                "content": format!("**{}**\n{}", labels.changes, lines.join("\n")),
+            }
+        })
+    });
+
+    let labels_element = (!issue.labels.is_empty()).then(|| {
+        let chips: Vec<String> = issue.labels.iter().map(|l| format!("🏷 {l}")).collect();
+        serde_json::json!({
+            "tag": "note",
+            "elements": [
+                {
+                    "tag": "plain_text",
+                    "content": chips.join("  "),
+                }
+            ]
+        })
+    });
+
+    let mut elements = vec![title_element];
+    elements.extend(description_element);
+    elements.push(fields_element);
+    elements.extend(labels_element);
+    elements.extend(changes_element);
+
+    if let Some(comment) = &issue.latest_comment {
+        let author = comment.author.as_deref().unwrap_or("Someone");
+        elements.push(serde_json::json!({
+            "tag": "note",
+            "elements": [
+                {
+                    "tag": "plain_text",
+                    "content": format!("💬 {author}: {}", comment.body),
+                }
+            ]
+        }));
+    }
+
+    if let Some(open_id) = &issue.acknowledged_by {
+        elements.push(serde_json::json!({
+            "tag": "div",
+            "text": {
+                "tag": "lark_md",
+                "content": format!("✅ {} <at id={open_id}></at>", labels.acknowledged_by),
+            }
+        }));
+    }
+
+    if let Some(error) = action_error {
+        elements.push(serde_json::json!({
+            "tag": "note",
+            "elements": [
+                {
+                    "tag": "plain_text",
+                    "content": format!("⚠️ {} {error}", labels.could_not_update),
+                }
+            ]
+        }));
+    }
+
+    let mut actions = Vec::new();
+    if let Some(url) = &issue.url {
+        actions.push(serde_json::json!({
+            "tag": "button",
+            "text": {
+                "tag": "plain_text",
+                "content": labels.view_in_linear
+            },
+            "type": "primary",
+            "url": url,
+        }));
+    }
+
+    if options.callbacks && issue.acknowledged_by.is_none() && !issue.is_removed() {
+        actions.push(callback_button(labels.ack, "default", "ack", issue));
+    }
+
+    // Transitions need the team to resolve its workflow states.
+    if options.transitions && issue.team_id.is_some() && !issue.is_removed() {
+        let started = matches!(issue.state_type.as_deref(), Some("started"));
+        if !started && !issue.is_completed() {
+            actions.push(callback_button(labels.start, "default", "start", issue));
+        }
+        if !issue.is_completed() {
+            actions.push(callback_button(labels.done, "default", "done", issue));
+        }
+    }
+
+    // Once the card mentions an assignee, it was assigned from a card or
+    // the assignee is known to Lark; either way the button is dropped.
+    let open = !issue.is_removed() && !issue.is_completed();
+    if options.assign && open && issue.assignee_open_id.is_none() {
+        actions.push(callback_button(
+            labels.assign_to_me,
+            "default",
+            "assign",
+            issue,
+        ));
+    }
+    if options.transitions && open && issue.priority != Some(1) {
+        actions.push(callback_button(
+            labels.set_urgent,
+            "danger",
+            "urgent",
+            issue,
+        ));
+    }
+
+    if !actions.is_empty() {
+        elements.push(serde_json::json!({
+            "tag": "action",
+            "actions": actions,
+        }));
+    }
+
+    elements
+}
+
+/// A person on a card: an @mention when their Lark user is known, their
+/// name otherwise.
+fn mention_or_name(open_id: Option<&str>, name: &str) -> String {
+    match open_id {
+        Some(open_id) => format!("<at id={open_id}></at>"),
+        None => escape_md(name).into_owned(),
+    }
+}
+
+/// What a comment card shows: the comment and the issue it is on.
+#[derive(Debug, Clone)]
+struct CommentSummary {
+    identifier: String,
+    title: String,
+    /// Already truncated to [`MAX_COMMENT_BODY_CHARS`].
+    body: String,
+    author: Option<String>,
+    /// Looked up by the delivery worker to mention the author.
+    author_email: Option<String>,
+    author_open_id: Option<String>,
+    /// The comment itself when the payload links it, else its issue.
+    url: Option<String>,
+}
+
+impl CommentSummary {
+    fn from_payload(
+        payload: &LinearPayload,
+        comment: &CommentData,
+        issue: &CommentIssue,
+        workspace: Option<&str>,
+    ) -> Self {
+        Self {
+            identifier: issue.identifier.clone(),
+            title: issue.title.clone(),
+            body: truncate_chars(comment.body.trim(), MAX_COMMENT_BODY_CHARS),
+            author: comment.user.as_ref().map(|u| u.name.clone()),
+            author_email: comment.user.as_ref().and_then(|u| u.email.clone()),
+            author_open_id: None,
+            url: payload
+                .url
+                .clone()
+                .or_else(|| issue.url.clone())
+                .or_else(|| {
+                    workspace.map(|w| format!("https://linear.app/{w}/issue/{}", issue.identifier))
+                }),
+        }
+    }
+}
+
+fn build_comment_card(comment: &CommentSummary, language: CardLanguage) -> LarkMessage {
+    LarkMessage {
+        msg_type: "interactive",
+        card: localized_card(
+            language,
+            "wathet",
+            false,
+            |labels| format!("[Linear] {} {}", labels.comment_on, comment.identifier),
+            |labels| render_comment_elements(comment, labels),
+        ),
+    }
+}
+
+/// Comment card body. The comment is markdown like a description and
+/// renders as such.
+fn render_comment_elements(comment: &CommentSummary, labels: &Labels) -> Vec<serde_json::Value> {
+    let mut elements = vec![
+        md_element(format!("**{}**", escape_md(&comment.title))),
+        md_element(sanitize_md(&comment.body).into_owned()),
+    ];
+    // Integrations comment without a user; those show no author.
+    if let Some(author) = &comment.author {
+        let author = mention_or_name(comment.author_open_id.as_deref(), author);
+        elements.extend(fields_element(vec![format!(
+            "**{}:** {author}",
+            labels.author
+        )]));
+    }
+    elements.extend(link_element(comment.url.as_deref(), labels));
+    elements
+}
+
+fn md_element(content: String) -> serde_json::Value {
+    serde_json::json!({
+        "tag": "div",
+        "text": {
+            "tag": "lark_md",
+            "content": content,
+        }
+    })
+}
+
+/// Short side-by-side fields; `None` when there are none.
+fn fields_element(contents: Vec<String>) -> Option<serde_json::Value> {
+    if contents.is_empty() {
+        return None;
+    }
+    let fields: Vec<_> = contents
+        .into_iter()
+        .map(|content| {
+            serde_json::json!({
+                "is_short": true,
+                "text": {
+                    "tag": "lark_md",
+                    "content": content,
+                }
+            })
+        })
+        .collect();
+    Some(serde_json::json!({
+        "tag": "div",
+        "fields": fields,
+    }))
+}
+
+/// The "View in Linear" button, alone in its action row.
+fn link_element(url: Option<&str>, labels: &Labels) -> Option<serde_json::Value> {
+    let url = url?;
+    Some(serde_json::json!({
+        "tag": "action",
+        "actions": [
+            {
+                "tag": "button",
+                "text": {
+                    "tag": "plain_text",
+                    "content": labels.view_in_linear
+                },
+                "type": "primary",
+                "url": url,
+            }
+        ],
+    }))
+}
+
+/// What a project card shows.
+#[derive(Debug, Clone)]
+struct ProjectSummary {
+    action: String,
+    name: String,
+    state: Option<String>,
+    target_date: Option<String>,
+    /// 0 to 1.
+    progress: Option<f64>,
+    lead: Option<String>,
+    /// Looked up by the delivery worker to mention the lead.
+    lead_email: Option<String>,
+    lead_open_id: Option<String>,
+    url: Option<String>,
+    /// What an `update` changed; empty for every other action.
+    changes: Vec<ProjectChange>,
+}
+
+/// One changed project field, shown as "previous → current".
+#[derive(Debug, Clone, PartialEq)]
+enum ProjectChange {
+    State { from: String },
+    TargetDate { from: Option<String> },
+    Name { from: String },
+}
+
+impl ProjectChange {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::State { .. } => "state",
+            Self::TargetDate { .. } => "target_date",
+            Self::Name { .. } => "name",
+        }
+    }
+
+    fn render(&self, project: &ProjectSummary, labels: &Labels) -> String {
+        let state = |state: Option<&str>| match state {
+            Some(state) => labels.project_state_label(state).into_owned(),
+            None => labels.none.to_string(),
+        };
+        let date = |date: Option<&str>| match date {
+            Some(date) => escape_md(date).into_owned(),
+            None => labels.none.to_string(),
+        };
+        let (label, from, to) = match self {
+            Self::State { from } => (
+                labels.status,
+                state(Some(from)),
+                state(project.state.as_deref()),
+            ),
+            Self::TargetDate { from } => (
+                labels.target_date,
+                date(from.as_deref()),
+                date(project.target_date.as_deref()),
+            ),
+            Self::Name { from } => (
+                labels.name,
+                escape_md(from).into_owned(),
+                escape_md(&project.name).into_owned(),
+            ),
+        };
+        format!("{label}: {from} → {to}")
+    }
+}
+
+/// The changes a project update card is sent for; progress and other
+/// edits stay quiet.
+fn project_changes(from: &UpdatedFrom, project: &ProjectData) -> Vec<ProjectChange> {
+    let mut changes = Vec::new();
+    if let Some(state) = from
+        .state
+        .as_ref()
+        .filter(|s| Some(*s) != project.state.as_ref())
+    {
+        changes.push(ProjectChange::State {
+            from: state.clone(),
+        });
+    }
+    if let Some(date) = from
+        .target_date
+        .as_ref()
+        .filter(|d| **d != project.target_date)
+    {
+        changes.push(ProjectChange::TargetDate { from: date.clone() });
+    }
+    if let Some(name) = from.name.as_ref().filter(|n| **n != project.name) {
+        changes.push(ProjectChange::Name { from: name.clone() });
+    }
+    changes
+}
+
+impl ProjectSummary {
+    fn from_payload(payload: &LinearPayload, project: &ProjectData) -> Self {
+        Self {
+            action: payload.action.clone(),
+            name: project.name.clone(),
+            state: project.state.clone(),
+            target_date: project.target_date.clone(),
+            progress: project.progress,
+            lead: project.lead.as_ref().map(|l| l.name.clone()),
+            lead_email: project.lead.as_ref().and_then(|l| l.email.clone()),
+            lead_open_id: None,
+            url: payload.url.clone(),
+            changes: match (payload.action.as_str(), &payload.updated_from) {
+                ("update", Some(from)) => project_changes(from, project),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    fn action_label<'a>(&'a self, labels: &'a Labels) -> &'a str {
+        match self.action.as_str() {
+            "create" => labels.created,
+            "update" => labels.updated,
+            "remove" => labels.deleted,
+            _ => &self.action,
+        }
+    }
+}
+
+fn build_project_card(project: &ProjectSummary, language: CardLanguage) -> LarkMessage {
+    let color = match project.state.as_deref() {
+        _ if project.action == "remove" => "grey",
+        Some("completed") => "green",
+        Some("canceled") => "grey",
+        _ => "purple",
+    };
+    LarkMessage {
+        msg_type: "interactive",
+        card: localized_card(
+            language,
+            color,
+            false,
+            |labels| {
+                format!(
+                    "[Linear] {}: {} {}",
+                    project.action_label(labels),
+                    labels.project,
+                    project.name
+                )
+            },
+            |labels| render_project_elements(project, labels),
+        ),
+    }
+}
+
+fn render_project_elements(project: &ProjectSummary, labels: &Labels) -> Vec<serde_json::Value> {
+    let fields = [
+        project.state.as_deref().map(|state| {
+            format!(
+                "**{}:** {}",
+                labels.status,
+                labels.project_state_label(state)
+            )
+        }),
+        project
+            .target_date
+            .as_deref()
+            .map(|date| format!("**{}:** {}", labels.target_date, escape_md(date))),
+        project
+            .progress
+            .map(|progress| format!("**{}:** {:.0}%", labels.progress, progress * 100.0)),
+        project.lead.as_deref().map(|lead| {
+            let lead = mention_or_name(project.lead_open_id.as_deref(), lead);
+            format!("**{}:** {lead}", labels.lead)
+        }),
+    ];
+
+    let mut elements: Vec<_> = fields_element(fields.into_iter().flatten().collect())
+        .into_iter()
+        .collect();
+    if !project.changes.is_empty() {
+        let lines: Vec<String> = project
+            .changes
+            .iter()
+            .map(|change| change.render(project, labels))
+            .collect();
+        elements.push(md_element(format!(
+            "**{}**\n{}",
+            labels.changes,
+            lines.join("\n")
+        )));
+    }
+    elements.extend(link_element(project.url.as_deref(), labels));
+    elements
+}
+
+/// What a project update (status post) card shows.
+#[derive(Debug, Clone)]
+struct ProjectUpdateSummary {
+    project: String,
+    /// Already truncated to [`MAX_COMMENT_BODY_CHARS`].
+    body: String,
+    health: Option<String>,
+    author: Option<String>,
+    /// Looked up by the delivery worker to mention the author.
+    author_email: Option<String>,
+    author_open_id: Option<String>,
+    url: Option<String>,
+}
+
+impl ProjectUpdateSummary {
+    fn from_payload(
+        payload: &LinearPayload,
+        update: &ProjectUpdateData,
+        project: &ProjectRef,
+    ) -> Self {
+        Self {
+            project: project.name.clone(),
+            body: truncate_chars(update.body.trim(), MAX_COMMENT_BODY_CHARS),
+            health: update.health.clone(),
+            author: update.user.as_ref().map(|u| u.name.clone()),
+            author_email: update.user.as_ref().and_then(|u| u.email.clone()),
+            author_open_id: None,
+            url: payload
+                .url
+                .clone()
+                .or_else(|| update.url.clone())
+                .or_else(|| project.url.clone()),
+        }
+    }
+}
+
+fn build_project_update_card(update: &ProjectUpdateSummary, language: CardLanguage) -> LarkMessage {
+    let color = match update.health.as_deref() {
+        Some("onTrack") => "green",
+        Some("atRisk") => "orange",
+        Some("offTrack") => "red",
+        _ => "purple",
+    };
+    LarkMessage {
+        msg_type: "interactive",
+        card: localized_card(
+            language,
+            color,
+            false,
+            |labels| format!("[Linear] {}: {}", labels.project_update, update.project),
+            |labels| render_project_update_elements(update, labels),
+        ),
+    }
+}
+
+/// The post is markdown like a comment and renders as such.
+fn render_project_update_elements(
+    update: &ProjectUpdateSummary,
+    labels: &Labels,
+) -> Vec<serde_json::Value> {
+    let fields = [
+        update
+            .health
+            .as_deref()
+            .map(|health| format!("**{}:** {}", labels.health, labels.health_label(health))),
+        update.author.as_deref().map(|author| {
+            let author = mention_or_name(update.author_open_id.as_deref(), author);
+            format!("**{}:** {author}", labels.author)
+        }),
+    ];
+
+    let mut elements = vec![md_element(sanitize_md(&update.body).into_owned())];
+    elements.extend(fields_element(fields.into_iter().flatten().collect()));
+    elements.extend(link_element(update.url.as_deref(), labels));
+    elements
+}
+
+// ---------------------------------------------------------------------------
+// Card translations
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+enum Lang {
+    En,
+    Zh,
+}
+
+impl Lang {
+    const ALL: [Lang; 2] = [Lang::En, Lang::Zh];
+
+    /// Locale key used by Lark's `i18n` / `i18n_elements`.
+    fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en_us",
+            Lang::Zh => "zh_cn",
+        }
+    }
+
+    fn labels(self) -> &'static Labels {
+        match self {
+            Lang::En => &EN_LABELS,
+            Lang::Zh => &ZH_LABELS,
+        }
+    }
+}
+
+/// `CARD_LANGUAGE`: `en` (default), `zh`, or `i18n` to send every language
+/// and let each Lark client pick its own.
+#[derive(Debug, Clone, Copy)]
+enum CardLanguage {
+    Single(Lang),
+    I18n,
+}
+
+impl CardLanguage {
+    /// Labels for single-language output such as template variables. i18n
+    /// falls back to English there.
+    fn labels(self) -> &'static Labels {
+        match self {
+            CardLanguage::Single(lang) => lang.labels(),
+            CardLanguage::I18n => Lang::En.labels(),
+        }
+    }
+}
+
+impl Default for CardLanguage {
+    fn default() -> Self {
+        CardLanguage::Single(Lang::En)
+    }
+}
+
+impl std::str::FromStr for CardLanguage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(CardLanguage::Single(Lang::En)),
+            "zh" => Ok(CardLanguage::Single(Lang::Zh)),
+            "i18n" => Ok(CardLanguage::I18n),
+            other => Err(format!("expected en, zh or i18n, got {other:?}")),
+        }
+    }
+}
+
+struct Labels {
+    created: &'static str,
+    updated: &'static str,
+    deleted: &'static str,
+    archived: &'static str,
+    title: &'static str,
+    status: &'static str,
+    priority: &'static str,
+    assignee: &'static str,
+    changes: &'static str,
+    /// For a change whose previous value is unknown: "Assignee changed".
+    changed: &'static str,
+    unassigned: &'static str,
+    /// Comment card header: "Comment on ENG-123".
+    comment_on: &'static str,
+    author: &'static str,
+    project: &'static str,
+    /// Project status posts, as opposed to edits of the project.
+    project_update: &'static str,
+    name: &'static str,
+    target_date: &'static str,
+    due_date: &'static str,
+    estimate: &'static str,
+    progress: &'static str,
+    lead: &'static str,
+    health: &'static str,
+    /// A field without a value, e.g. no previous target date.
+    none: &'static str,
+    /// Indexed like [`PROJECT_STATES`].
+    project_states: [&'static str; 6],
+    /// Indexed like [`PROJECT_HEALTHS`].
+    healths: [&'static str; 3],
+    acknowledged_by: &'static str,
+    view_in_linear: &'static str,
+    ack: &'static str,
+    start: &'static str,
+    done: &'static str,
+    assign_to_me: &'static str,
+    set_urgent: &'static str,
+    /// Shown before the error when a card action fails.
+    could_not_update: &'static str,
+    /// Indexed by Linear priority 0 (None) to 4 (Low).
+    priorities: [&'static str; 5],
+}
+
+/// Linear's project states, in `project_states` order.
+const PROJECT_STATES: [&str; 6] = [
+    "backlog",
+    "planned",
+    "started",
+    "paused",
+    "completed",
+    "canceled",
+];
+
+/// Linear's project update healths, in `healths` order.
+const PROJECT_HEALTHS: [&str; 3] = ["onTrack", "atRisk", "offTrack"];
+
+impl Labels {
+    fn priority_label(&self, priority: u8) -> &'static str {
+        self.priorities
+            .get(priority as usize)
+            .copied()
+            .unwrap_or(self.priorities[0])
+    }
+
+    /// States Linear adds later show as sent.
+    fn project_state_label<'a>(&self, state: &'a str) -> Cow<'a, str> {
+        match PROJECT_STATES.iter().position(|s| *s == state) {
+            Some(i) => Cow::Borrowed(self.project_states[i]),
+            None => escape_md(state),
+        }
+    }
+
+    fn health_label<'a>(&self, health: &'a str) -> Cow<'a, str> {
+        match PROJECT_HEALTHS.iter().position(|h| *h == health) {
+            Some(i) => Cow::Borrowed(self.healths[i]),
+            None => escape_md(health),
+        }
+    }
+}
+
+const EN_LABELS: Labels = Labels {
+    created: "Created",
+    updated: "Updated",
+    deleted: "Deleted",
+    archived: "Archived",
+    title: "Title",
+    status: "Status",
+    priority: "Priority",
+    assignee: "Assignee",
+    changes: "Changes",
+    changed: "changed",
+    unassigned: "Unassigned",
+    comment_on: "Comment on",
+    author: "Author",
+    project: "Project",
+    project_update: "Project update",
+    name: "Name",
+    target_date: "Target date",
+    due_date: "Due",
+    estimate: "Estimate",
+    progress: "Progress",
+    lead: "Lead",
+    health: "Health",
+    none: "None",
+    project_states: [
+        "Backlog",
+        "Planned",
+        "Started",
+        "Paused",
+        "Completed",
+        "Canceled",
+    ],
+    healths: ["On track", "At risk", "Off track"],
+    acknowledged_by: "Acknowledged by",
+    view_in_linear: "View in Linear",
+    ack: "Ack",
+    start: "Start",
+    done: "Done",
+    assign_to_me: "Assign to me",
+    set_urgent: "Set Urgent",
+    could_not_update: "Could not update Linear:",
+    priorities: ["None", "Urgent", "High", "Medium", "Low"],
+};
+
+const ZH_LABELS: Labels = Labels {
+    created: "新建",
+    updated: "更新",
+    deleted: "已删除",
+    archived: "已归档",
+    title: "标题",
+    status: "状态",
+    priority: "优先级",
+    assignee: "负责人",
+    changes: "变更",
+    changed: "已变更",
+    unassigned: "未分配",
+    comment_on: "评论",
+    author: "作者",
+    project: "项目",
+    project_update: "项目进展",
+    name: "名称",
+    target_date: "目标日期",
+    due_date: "截止日期",
+    estimate: "估算",
+    progress: "进度",
+    lead: "项目负责人",
+    health: "健康状况",
+    none: "无",
+    project_states: ["待办", "已计划", "进行中", "已暂停", "已完成", "已取消"],
+    healths: ["正常", "有风险", "偏离"],
+    acknowledged_by: "已确认：",
+    view_in_linear: "在 Linear 中查看",
+    ack: "确认",
+    start: "开始",
+    done: "完成",
+    assign_to_me: "指派给我",
+    set_urgent: "设为紧急",
+    could_not_update: "无法更新 Linear：",
+    priorities: ["无", "紧急", "高", "中", "低"],
+};
+
+// ---------------------------------------------------------------------------
+// Lark card templates
+// ---------------------------------------------------------------------------
+
+/// Event fields that can be mapped onto template variables.
+const TEMPLATE_FIELDS: &[&str] = &[
+    "action",
+    "identifier",
+    "title",
+    "state",
+    "priority",
+    "assignee",
+    "url",
+    "labels",
+    "due_date",
+    "estimate",
+];
+
+/// A card built in Lark's card builder, sent by id instead of our own JSON.
+#[derive(Debug)]
+struct CardTemplate {
+    id: String,
+    version: Option<String>,
+    /// (template variable, event field) pairs.
+    variables: Vec<(String, String)>,
+}
+
+impl CardTemplate {
+    /// `variables` is a comma-separated `variable=field` list. When unset,
+    /// every event field is passed under its own name.
+    fn new(id: String, version: Option<String>, variables: Option<&str>) -> Result<Self, String> {
+        let variables = match variables {
+            Some(spec) => spec
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (variable, field) = pair
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected variable=field, got {pair:?}"))?;
+                    let (variable, field) = (variable.trim(), field.trim());
+                    if !TEMPLATE_FIELDS.contains(&field) {
+                        return Err(format!(
+                            "unknown event field {field:?} for variable {variable:?} (available: {})",
+                            TEMPLATE_FIELDS.join(", ")
+                        ));
+                    }
+                    Ok((variable.to_string(), field.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => TEMPLATE_FIELDS
+                .iter()
+                .map(|f| (f.to_string(), f.to_string()))
+                .collect(),
+        };
+
+        Ok(Self {
+            id,
+            version,
+            variables,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct LarkTemplateMessage {
+    msg_type: &'static str,
+    card: LarkTemplateCard,
+}
+
+#[derive(Serialize)]
+struct LarkTemplateCard {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    data: LarkTemplateData,
+}
+
+#[derive(Serialize)]
+struct LarkTemplateData {
+    template_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template_version_name: Option<String>,
+    template_variable: serde_json::Map<String, serde_json::Value>,
+}
+
+fn template_field(issue: &IssueSummary, field: &str, labels: &Labels) -> String {
+    match field {
+        "action" => issue.action_label(labels).to_string(),
+        "identifier" => issue.identifier.clone(),
+        "title" => issue.title.clone(),
+        "state" => issue.state.clone().unwrap_or_default(),
+        "priority" => labels
+            .priority_label(issue.priority.unwrap_or(0))
+            .to_string(),
+        "assignee" => issue
+            .assignee
+            .clone()
+            .unwrap_or_else(|| labels.unassigned.into()),
+        "url" => issue.url.clone().unwrap_or_default(),
+        "labels" => issue.labels.join(", "),
+        "due_date" => issue.due_date.clone().unwrap_or_default(),
+        "estimate" => issue.estimate.map(|e| e.to_string()).unwrap_or_default(),
+        // Field names are validated against TEMPLATE_FIELDS at startup.
+        _ => unreachable!("unknown template field {field}"),
+    }
+}
+
+fn build_template_message(
+    template: &CardTemplate,
+    issue: &IssueSummary,
+    labels: &Labels,
+) -> LarkTemplateMessage {
+    let template_variable = template
+        .variables
+        .iter()
+        .map(|(variable, field)| {
+            (
+                variable.clone(),
+                template_field(issue, field, labels).into(),
+            )
+        })
+        .collect();
+
+    LarkTemplateMessage {
+        msg_type: "interactive",
+        card: LarkTemplateCard {
+            kind: "template",
+            data: LarkTemplateData {
+                template_id: template.id.clone(),
+                template_version_name: template.version.clone(),
+                template_variable,
+            },
+        },
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Linear API
+// ---------------------------------------------------------------------------
+
+/// Returns the team's workflow states, querying Linear only on first use.
+async fn team_workflow_states(
+    state: &AppState,
+    linear: &LinearClient,
+    team_id: &str,
+) -> Result<Vec<WorkflowState>, LinearError> {
+    if let Some(states) = state.workflow_states.lock().unwrap().get(team_id) {
+        return Ok(states.clone());
+    }
+
+    let states = linear.team_workflow_states(team_id).await?;
+
+    state
+        .workflow_states
+        .lock()
+        .unwrap()
+        .insert(team_id.to_string(), states.clone());
+    Ok(states)
+}
+
+/// Names the previous state of a status change, from the team's workflow
+/// states. Without Linear access, or when it does not answer within the
+/// enrichment budget, the change shows only the new state.
+async fn previous_state_name(
+    state: &AppState,
+    issue: &IssueSummary,
+    state_id: Option<&str>,
+) -> Option<String> {
+    let (Some(linear), Some(team_id), Some(state_id)) = (&state.linear, &issue.team_id, state_id)
+    else {
+        return None;
+    };
+    if !issue
+        .changes
+        .iter()
+        .any(|c| matches!(c, Change::Status { .. }))
+    {
+        return None;
+    }
+    let budget = state.enricher.budget();
+    match tokio::time::timeout(budget, team_workflow_states(state, linear, team_id)).await {
+        Ok(Ok(states)) => states
+            .into_iter()
+            .find(|s| s.id == state_id)
+            .map(|s| s.name),
+        Ok(Err(e)) => {
+            warn!(
+                "could not resolve the previous state of {}: {e}",
+                issue.identifier
+            );
+            None
+        }
+        Err(_) => {
+            warn!(
+                "resolving the previous state of {} timed out after {budget:?}",
+                issue.identifier
+            );
+            None
+        }
+    }
+}
+
+/// Does the lookups the webhook handler left in `issue.lookups`, at once
+/// and each within its own budget.
+async fn look_up_issue(state: &AppState, issue: &mut IssueSummary) {
+    let lookups = std::mem::take(&mut issue.lookups);
+    let linear = state.linear.as_ref();
+    let description = async {
+        match linear {
+            Some(linear) if lookups.description => {
+                state.enricher.description(linear, &issue.id).await
+            }
+            _ => None,
+        }
+    };
+    let latest_comment = async {
+        match linear {
+            Some(linear) if lookups.latest_comment => {
+                state.enricher.latest_comment(linear, &issue.id).await
+            }
+            _ => None,
+        }
+    };
+    let (previous_state, open_id, description, latest_comment) = tokio::join!(
+        previous_state_name(state, issue, lookups.previous_state_id.as_deref()),
+        lark_open_id(state, lookups.assignee_email.as_deref()),
+        description,
+        latest_comment,
+    );
+
+    if let Some(Change::Status { from }) = issue
+        .changes
+        .iter_mut()
+        .find(|c| matches!(c, Change::Status { .. }))
+    {
+        *from = from.take().or(previous_state);
+    }
+    issue.assignee_open_id = issue.assignee_open_id.take().or(open_id);
+    // The payload copy may be truncated, so it is only the fallback.
+    if let Some(description) = description {
+        issue.set_description(&description);
+    }
+    if let Some(comment) = latest_comment {
+        issue.latest_comment = Some(CommentSnippet {
+            author: comment.user.map(|u| u.name),
+            body: truncate_chars(comment.body.trim(), MAX_COMMENT_CHARS),
+        });
+    }
+}
+
+/// Moves the issue to the team's first workflow state of `state_type`
+/// ("started" or "completed") and updates the summary to match.
+async fn transition_issue(
+    state: &AppState,
+    issue: &mut IssueSummary,
+    state_type: &str,
+) -> Result<(), String> {
+    // A double-click renders the second card from the already-updated one.
+    if issue.state_type.as_deref() == Some(state_type) {
+        return Ok(());
+    }
+
+    let linear = state
+        .linear
+        .as_ref()
+        .ok_or("LINEAR_API_KEY not configured")?;
+    let team_id = issue.team_id.as_deref().ok_or("issue has no team")?;
+
+    let target = team_workflow_states(state, linear, team_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|s| s.kind == state_type)
+        .min_by(|a, b| a.position.total_cmp(&b.position))
+        .ok_or_else(|| format!("team has no {state_type} workflow state"))?;
+
+    linear
+        .update_issue_state(&issue.id, &target.id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    issue.state = Some(target.name);
+    issue.state_type = Some(target.kind);
+    Ok(())
+}
+
+/// Assigns the issue to the Linear user with the same email as the Lark
+/// user `open_id`, and mentions them on the card from then on.
+async fn assign_to_clicker(
+    state: &AppState,
+    issue: &mut IssueSummary,
+    open_id: &str,
+) -> Result<(), String> {
+    let linear = state
+        .linear
+        .as_ref()
+        .ok_or("LINEAR_API_KEY not configured")?;
+    let contacts = state
+        .contacts
+        .as_ref()
+        .ok_or("LARK_APP_ID not configured")?;
+
+    let email = contacts
+        .email(&state.http, open_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("your Lark account has no email the bridge may read")?;
+    let user = linear
+        .user_by_email(&email)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("no Linear user with the email {email}"))?;
+    linear
+        .assign_issue(&issue.id, &user.id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    issue.assignee = Some(user.name);
+    issue.assignee_open_id = Some(open_id.to_string());
+    Ok(())
+}
+
+async fn set_urgent(state: &AppState, issue: &mut IssueSummary) -> Result<(), String> {
+    const URGENT: u8 = 1;
+    if issue.priority == Some(URGENT) {
+        return Ok(());
+    }
+
+    let linear = state
+        .linear
+        .as_ref()
+        .ok_or("LINEAR_API_KEY not configured")?;
+    linear
+        .update_issue_priority(&issue.id, URGENT)
+        .await
+        .map_err(|e| e.to_string())?;
+    issue.priority = Some(URGENT);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Webhook handler
+// ---------------------------------------------------------------------------
+
+async fn webhook_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    // 1. Signature verification
+    let signature = match linear_signature(&headers) {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            warn!("missing linear-signature header");
+            state.metrics.inc(metrics::SIGNATURE_FAILURES, &[]);
+            return StatusCode::UNAUTHORIZED;
+        }
+        Err(e) => {
+            warn!("malformed linear-signature header: {e}");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    if !verify_signature(&state.webhook_secret, &body, signature) {
+        warn!("invalid webhook signature");
+        state.metrics.inc(metrics::SIGNATURE_FAILURES, &[]);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    // Linear retries deliveries it thinks failed; each one keeps its id.
+    if let Some(delivery) = headers.get("linear-delivery").and_then(|v| v.to_str().ok()) {
+        match state.storage.dedup().first_seen(delivery).await {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("ignoring duplicate delivery {delivery}");
+                return StatusCode::OK;
+            }
+            Err(e) => warn!("dedup check failed, processing anyway: {e}"),
+        }
+    }
+
+    // 2. Deserialize payload
+    if state.parse_mode == ParseMode::Strict {
+        log_drift(&state, &body);
+    }
+    let payload: LinearPayload = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(e) => {
+            // Serde quotes offending values, which may be huge.
+            let e = truncate_chars(&e.to_string(), limits::MAX_FIELD_CHARS);
+            error!("failed to parse payload: {e}");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    state.metrics.inc(
+        metrics::WEBHOOKS,
+        &[("type", &payload.kind), ("action", &payload.action)],
+    );
+
+    let disposition = process_payload(&state, &payload, Delivering::Queued).await;
+    if disposition.queue_full {
+        // Linear retries failed deliveries with backoff, likely once the
+        // queue has drained; the retry must not count as a duplicate.
+        if let Some(delivery) = headers.get("linear-delivery").and_then(|v| v.to_str().ok()) {
+            if let Err(e) = state.storage.dedup().forget(delivery).await {
+                warn!("failed to forget delivery {delivery}, its retry will be ignored: {e}");
+            }
+        }
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    StatusCode::OK
+}
+
+/// Unknown keys named per drift log line; the rest are only counted.
+const MAX_DRIFT_KEYS: usize = 20;
+
+/// Logs and counts the keys of `body` that Linear's known payload shape
+/// lacks. Only diagnostic: the payload is processed the same either way.
+fn log_drift(state: &AppState, body: &[u8]) {
+    let Ok(payload) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return;
+    };
+    if let Some(summary) = drift_summary(&payload) {
+        warn!(target: "payload_drift", "{summary}");
+        let kind = payload["type"].as_str().unwrap_or("?");
+        let kind = truncate_chars(kind, limits::MAX_FIELD_CHARS);
+        state
+            .metrics
+            .inc(metrics::PAYLOAD_DRIFT, &[("type", &kind)]);
+    }
+}
+
+/// One line naming the first [`MAX_DRIFT_KEYS`] unknown keys of `payload`,
+/// capped like any other field, since keys and type come from the payload.
+fn drift_summary(payload: &serde_json::Value) -> Option<String> {
+    let mut drift = drift::unknown_keys(payload);
+    let total: usize = drift.iter().map(|d| d.keys.len()).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut left = MAX_DRIFT_KEYS;
+    for d in &mut drift {
+        d.keys.truncate(left);
+        left -= d.keys.len();
+    }
+    drift.retain(|d| !d.keys.is_empty());
+
+    let objects: Vec<String> = drift.iter().map(ToString::to_string).collect();
+    let mut summary = format!(
+        "{} {} payload has unknown keys: {}",
+        payload["type"].as_str().unwrap_or("?"),
+        payload["action"].as_str().unwrap_or("?"),
+        objects.join("; ")
+    );
+    if total > MAX_DRIFT_KEYS {
+        summary.push_str(&format!(" (+{} more)", total - MAX_DRIFT_KEYS));
+    }
+    limits::cap_field(&mut summary);
+    Some(summary)
+}
+
+/// What the pipeline decided for one payload. `POST /simulate` returns it;
+/// real webhooks only act on it.
+#[derive(Debug, Default, Serialize)]
+struct Disposition {
+    /// `ignored`, `rendered` (simulated without sending), `queued`, `sent`
+    /// or `failed`.
+    outcome: &'static str,
+    /// Every filter evaluated, in order.
+    filters: Vec<FilterCheck>,
+    /// Webhooks the card goes to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    routes: Vec<Destination>,
+    /// The message as first posted to Lark.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// No card could be queued for lack of room.
+    #[serde(skip)]
+    queue_full: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct FilterCheck {
+    filter: &'static str,
+    value: String,
+    passed: bool,
+}
+
+impl Disposition {
+    fn check(&mut self, filter: &'static str, value: &str, passed: bool) -> bool {
+        self.filters.push(FilterCheck {
+            filter,
+            value: value.to_string(),
+            passed,
+        });
+        passed
+    }
+}
+
+/// What [`process_payload`] does with a rendered card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivering {
+    /// Nothing; the caller only wants the disposition.
+    No,
+    /// Post it before returning, once per destination.
+    Now,
+    /// Hand it to the delivery queue.
+    Queued,
+}
+
+/// Runs a parsed payload through filtering and routing, and delivers the
+/// card as `delivering` says. Queued cards do their lookups in the
+/// delivery worker, see [`IssueLookups`]. Payloads that are only rendered
+/// stay out of the event log.
+async fn process_payload(
+    state: &AppState,
+    payload: &LinearPayload,
+    delivering: Delivering,
+) -> Disposition {
+    let send = delivering != Delivering::No;
+    let mut disposition = Disposition {
+        outcome: "ignored",
+        ..Default::default()
+    };
+
+    // 3. Filter: Issue create / update / remove, plus the kinds with cards
+    // of their own
+    let known = disposition.check(
+        "type is Issue, Comment, Project or ProjectUpdate",
+        &payload.kind,
+        matches!(
+            payload.data,
+            PayloadData::Issue(_)
+                | PayloadData::Comment(_)
+                | PayloadData::Project(_)
+                | PayloadData::ProjectUpdate(_)
+        ),
+    );
+    let data = match &payload.data {
+        PayloadData::Issue(data) => Some(data.as_ref()),
+        PayloadData::Comment(comment) => {
+            return process_comment(state, payload, comment, delivering, disposition).await;
+        }
+        PayloadData::Project(project) => {
+            return process_project(state, payload, project, delivering, disposition).await;
+        }
+        PayloadData::ProjectUpdate(update) => {
+            return process_project_update(state, payload, update, delivering, disposition).await;
+        }
+        _ => None,
+    };
+    let relevant = disposition.check(
+        "action is create, update or remove",
+        &payload.action,
+        matches!(payload.action.as_str(), "create" | "update" | "remove"),
+    );
+    let data = match data {
+        Some(data) if known && relevant => data,
+        _ => {
+            info!(
+                "ignoring event: type={}, action={} ({})",
+                payload.kind,
+                payload.action,
+                payload.data.describe()
+            );
+            if let (Some(data), true) = (data, send) {
+                record_event(state, payload, data, "ignored", None, None).await;
+            }
+            return disposition;
+        }
+    };
+
+    // An update without updatedFrom cannot be judged, so it is sent.
+    if let (Some(from), "update") = (&payload.updated_from, payload.action.as_str()) {
+        let notable = from.notable_fields();
+        let changed = disposition.check(
+            "update changes state, priority, assignee or title",
+            &notable.join(","),
+            !notable.is_empty(),
+        );
+        if !changed {
+            info!(
+                "ignoring {} update: no notable field changed",
+                data.identifier
+            );
+            if send {
+                record_event(state, payload, data, "ignored", None, None).await;
+            }
+            return disposition;
+        }
+    }
+
+    if !check_ignore_rules(state, payload, Some(data), &mut disposition) {
+        info!(
+            "ignoring {} {}: ignore rule matched",
+            payload.action, data.identifier
+        );
+        if send {
+            record_event(state, payload, data, "ignored", None, None).await;
+        }
+        return disposition;
+    }
+
+    info!(
+        "processing {} {} – {}",
+        payload.action, data.identifier, data.title
+    );
+
+    let mut issue = IssueSummary::from_payload(payload, data, state.linear_workspace.as_deref());
+    issue.overdue = issue.is_overdue_on(
+        chrono::Utc::now()
+            .with_timezone(&state.timezone)
+            .date_naive(),
+    );
+
+    // 4. Optional enrichment, left to the delivery worker
+    // Removed issues can no longer be fetched, so they skip it.
+    if state.card_include_description && !issue.is_removed() {
+        if let Some(description) = &data.description {
+            issue.set_description(description);
+        }
+        issue.lookups.description = true;
+    }
+
+    // Only state transitions get the comment, since that is usually where
+    // the "why" (blocked on what, ready for whom) ends up.
+    let state_changed = payload
+        .updated_from
+        .as_ref()
+        .is_some_and(|from| from.state_id.is_some());
+    issue.lookups.latest_comment = state.card_include_latest_comment && state_changed;
+
+    // 5. Route, build & send Lark card
+    let keys = RouteKeys {
+        teams: team_names(data.team.as_ref()),
+        projects: data
+            .project
+            .iter()
+            .flat_map(|p| std::iter::once(p.name.as_str()).chain(p.id.as_deref()))
+            .chain(data.project_id.as_deref())
+            .collect(),
+        labels: data.labels.iter().map(|l| l.name.as_str()).collect(),
+    };
+    route_and_deliver(
+        state,
+        disposition,
+        delivering,
+        &keys,
+        Notification::Issue(Box::new(issue)),
+        new_event(payload, data),
+    )
+    .await
+}
+
+/// Applies the configured [`IgnoreRules`], each as a filter of its own.
+/// State and label rules need `issue`; actor rules apply to every kind.
+fn check_ignore_rules(
+    state: &AppState,
+    payload: &LinearPayload,
+    issue: Option<&Issue>,
+    disposition: &mut Disposition,
+) -> bool {
+    let rules = &state.ignore;
+    let mut wanted = true;
+    if rules.has_actors() {
+        let names = payload.actor.as_ref().map(Actor::names).unwrap_or_default();
+        wanted &= disposition.check(
+            "actor is not ignored",
+            names.first().copied().unwrap_or_default(),
+            !rules.actor(&names),
+        );
+    }
+    let Some(issue) = issue else {
+        return wanted;
+    };
+    if rules.has_states() {
+        let names: Vec<&str> = issue
+            .state
+            .iter()
+            .flat_map(|s| std::iter::once(s.name.as_str()).chain(s.kind.as_deref()))
+            .collect();
+        wanted &= disposition.check(
+            "state is not ignored",
+            &names.join("/"),
+            !rules.state(&names),
+        );
+    }
+    if rules.has_labels() {
+        let labels: Vec<&str> = issue.labels.iter().map(|l| l.name.as_str()).collect();
+        wanted &= disposition.check(
+            "no label is ignored",
+            &labels.join(","),
+            rules.label(&labels).is_none(),
+        );
+    }
+    wanted
+}
+
+/// Key and name of `team`, for routing.
+fn team_names(team: Option<&Team>) -> Vec<&str> {
+    team.iter()
+        .flat_map(|t| std::iter::once(t.key.as_str()).chain(t.name.as_deref()))
+        .collect()
+}
+
+/// The end of the pipeline shared by every kind: picks the destinations
+/// for `keys`, renders the card and delivers it as `delivering` says,
+/// recording `event` once per destination.
+async fn route_and_deliver(
+    state: &AppState,
+    mut disposition: Disposition,
+    delivering: Delivering,
+    keys: &RouteKeys<'_>,
+    mut notification: Notification,
+    mut event: Event,
+) -> Disposition {
+    disposition.routes = state.routes.resolve(keys, state.default_webhook());
+    let routed = disposition.check(
+        "a route matches",
+        &format!(
+            "team={} project={} labels={}",
+            keys.teams.join("/"),
+            keys.projects.join("/"),
+            keys.labels.join(",")
+        ),
+        !disposition.routes.is_empty(),
+    );
+    if !routed {
+        info!(
+            "no route for {} and no LARK_WEBHOOK_URL, not sending",
+            notification.identifier()
+        );
+        if delivering != Delivering::No {
+            event.disposition = "ignored".into();
+            store_event(state, event).await;
+        }
+        return disposition;
+    }
+
+    if delivering == Delivering::Queued {
+        deliver_card(state, &mut disposition, delivering, &notification, event).await;
+        return disposition;
+    }
+
+    // Simulated right here; real webhooks leave this to the delivery worker.
+    notification.look_up(state).await;
+    disposition.card = Some(notification.render(state));
+    if delivering == Delivering::No {
+        disposition.outcome = "rendered";
+        return disposition;
+    }
+
+    deliver_card(state, &mut disposition, delivering, &notification, event).await;
+    disposition
+}
+
+/// The comment branch of [`process_payload`]: new comments on issues get a
+/// card of their own, routed by the issue's team.
+async fn process_comment(
+    state: &AppState,
+    payload: &LinearPayload,
+    comment: &CommentData,
+    delivering: Delivering,
+    mut disposition: Disposition,
+) -> Disposition {
+    let send = delivering != Delivering::No;
+    let created = disposition.check(
+        "comment action is create",
+        &payload.action,
+        payload.action == "create",
+    );
+    let on_issue = disposition.check(
+        "comment is on an issue",
+        comment.issue_id.as_deref().unwrap_or_default(),
+        comment.issue.is_some(),
+    );
+    let wanted = check_ignore_rules(state, payload, None, &mut disposition);
+    let issue = match &comment.issue {
+        Some(issue) if created && on_issue && wanted => issue,
+        _ => {
+            info!(
+                "ignoring event: type={}, action={} ({})",
+                payload.kind,
+                payload.action,
+                payload.data.describe()
+            );
+            if let (Some(issue), true) = (&comment.issue, send) {
+                let mut event = new_comment_event(payload, issue);
+                event.disposition = "ignored".into();
+                store_event(state, event).await;
+            }
+            return disposition;
+        }
+    };
+
+    info!("processing comment on {}", issue.identifier);
+    let summary =
+        CommentSummary::from_payload(payload, comment, issue, state.linear_workspace.as_deref());
+
+    let keys = RouteKeys {
+        teams: team_names(issue.team.as_ref()),
+        ..Default::default()
+    };
+    route_and_deliver(
+        state,
+        disposition,
+        delivering,
+        &keys,
+        Notification::Comment(summary),
+        new_comment_event(payload, issue),
+    )
+    .await
+}
+
+/// The project branch of [`process_payload`]. Updates are only sent when
+/// the state, target date or name changed, see [`project_changes`].
+async fn process_project(
+    state: &AppState,
+    payload: &LinearPayload,
+    project: &ProjectData,
+    delivering: Delivering,
+    mut disposition: Disposition,
+) -> Disposition {
+    let send = delivering != Delivering::No;
+    let summary = ProjectSummary::from_payload(payload, project);
+    let relevant = disposition.check(
+        "action is create, update or remove",
+        &payload.action,
+        matches!(payload.action.as_str(), "create" | "update" | "remove"),
+    );
+    // As for issues, an update without updatedFrom cannot be judged and is
+    // sent.
+    let notable = payload.action != "update"
+        || payload.updated_from.is_none()
+        || disposition.check(
+            "update changes state, target date or name",
+            &summary
+                .changes
+                .iter()
+                .map(ProjectChange::field)
+                .collect::<Vec<_>>()
+                .join(","),
+            !summary.changes.is_empty(),
+        );
+    let mut event = new_project_event(payload, &project.id, &project.name);
+    event.state = project.state.clone().unwrap_or_default();
+    let wanted = check_ignore_rules(state, payload, None, &mut disposition);
+    if !relevant || !notable || !wanted {
+        info!(
+            "ignoring event: type={}, action={} ({})",
+            payload.kind,
+            payload.action,
+            payload.data.describe()
+        );
+        if send {
+            event.disposition = "ignored".into();
+            store_event(state, event).await;
+        }
+        return disposition;
+    }
+
+    info!("processing {} project {}", payload.action, project.name);
+    let keys = RouteKeys {
+        projects: vec![&project.name, &project.id],
+        ..Default::default()
+    };
+    route_and_deliver(
+        state,
+        disposition,
+        delivering,
+        &keys,
+        Notification::Project(summary),
+        event,
+    )
+    .await
+}
+
+/// The project update branch of [`process_payload`]: new status posts.
+async fn process_project_update(
+    state: &AppState,
+    payload: &LinearPayload,
+    update: &ProjectUpdateData,
+    delivering: Delivering,
+    mut disposition: Disposition,
+) -> Disposition {
+    let send = delivering != Delivering::No;
+    let created = disposition.check(
+        "project update action is create",
+        &payload.action,
+        payload.action == "create",
+    );
+    let wanted = check_ignore_rules(state, payload, None, &mut disposition);
+    let project = match &update.project {
+        Some(project) if created && wanted => project,
+        _ => {
+            info!(
+                "ignoring event: type={}, action={} ({})",
+                payload.kind,
+                payload.action,
+                payload.data.describe()
+            );
+            if let (Some(project), true) = (&update.project, send) {
+                let mut event = new_project_event(payload, &project.id, &project.name);
+                event.disposition = "ignored".into();
+                store_event(state, event).await;
+            }
+            return disposition;
+        }
+    };
+
+    info!("processing update on project {}", project.name);
+    let summary = ProjectUpdateSummary::from_payload(payload, update, project);
+    let mut event = new_project_event(payload, &project.id, &project.name);
+    event.state = update.health.clone().unwrap_or_default();
+    let keys = RouteKeys {
+        projects: vec![&project.name, &project.id],
+        ..Default::default()
+    };
+    route_and_deliver(
+        state,
+        disposition,
+        delivering,
+        &keys,
+        Notification::ProjectUpdate(summary),
+        event,
+    )
+    .await
+}
+
+/// Event-log entry for a project or project update. The project stands in
+/// for the issue, by id and by name.
+fn new_project_event(payload: &LinearPayload, id: &str, name: &str) -> Event {
+    Event {
+        received_at: chrono::Utc::now(),
+        kind: payload.kind.clone(),
+        action: payload.action.clone(),
+        issue_id: id.to_string(),
+        identifier: name.to_string(),
+        team_key: None,
+        title: name.to_string(),
+        state: String::new(),
+        state_type: None,
+        priority: 0,
+        previous_priority: None,
+        state_changed: false,
+        assignee: None,
+        issue_created_at: None,
+        completed_at: None,
+        disposition: String::new(),
+        target: None,
+        outcome: None,
+    }
+}
+
+/// Event-log entry for a comment, filed under its issue.
+fn new_comment_event(payload: &LinearPayload, issue: &CommentIssue) -> Event {
+    Event {
+        received_at: chrono::Utc::now(),
+        kind: payload.kind.clone(),
+        action: payload.action.clone(),
+        issue_id: issue.id.clone(),
+        identifier: issue.identifier.clone(),
+        team_key: issue.team.as_ref().map(|t| t.key.clone()),
+        title: issue.title.clone(),
+        state: String::new(),
+        state_type: None,
+        priority: 0,
+        previous_priority: None,
+        state_changed: false,
+        assignee: None,
+        issue_created_at: None,
+        completed_at: None,
+        disposition: String::new(),
+        target: None,
+        outcome: None,
+    }
+}
+
+/// A card on its way to Lark.
+#[derive(Debug, Clone)]
+enum Notification {
+    Issue(Box<IssueSummary>),
+    Comment(CommentSummary),
+    Project(ProjectSummary),
+    ProjectUpdate(ProjectUpdateSummary),
+}
+
+impl Notification {
+    /// The issue or project it is about, for logs.
+    fn identifier(&self) -> &str {
+        match self {
+            Self::Issue(issue) => &issue.identifier,
+            Self::Comment(comment) => &comment.identifier,
+            Self::Project(project) => &project.name,
+            Self::ProjectUpdate(update) => &update.project,
+        }
+    }
+
+    /// Does the lookups left for delivery: mentions, and for issues also
+    /// the enrichment, see [`IssueLookups`].
+    async fn look_up(&mut self, state: &AppState) {
+        match self {
+            Self::Issue(issue) => look_up_issue(state, issue).await,
+            Self::Comment(comment) => {
+                comment.author_open_id =
+                    lark_open_id(state, comment.author_email.take().as_deref()).await;
+            }
+            Self::Project(project) => {
+                project.lead_open_id =
+                    lark_open_id(state, project.lead_email.take().as_deref()).await;
+            }
+            Self::ProjectUpdate(update) => {
+                update.author_open_id =
+                    lark_open_id(state, update.author_email.take().as_deref()).await;
+            }
+        }
+    }
+
+    /// The message as first posted to Lark. Templates are issue-shaped, so
+    /// only issues use them.
+    fn render(&self, state: &AppState) -> serde_json::Value {
+        let language = state.card_language;
+        match self {
+            Self::Issue(issue) => match &state.card_template {
+                Some(template) => {
+                    serde_json::to_value(build_template_message(template, issue, language.labels()))
+                }
+                None => serde_json::to_value(build_lark_card(issue, state.card_options())),
+            },
+            Self::Comment(comment) => serde_json::to_value(build_comment_card(comment, language)),
+            Self::Project(project) => serde_json::to_value(build_project_card(project, language)),
+            Self::ProjectUpdate(update) => {
+                serde_json::to_value(build_project_update_card(update, language))
+            }
+        }
+        .expect("cards serialize to json")
+    }
+
+    /// The issue id to collapse rapid updates by; `None` for cards that are
+    /// never debounced.
+    fn debounce_key(&self) -> Option<&str> {
+        match self {
+            Self::Issue(issue) if issue.action == "update" => Some(&issue.id),
+            _ => None,
+        }
+    }
+
+    /// The card as the store keeps it while held. Only issue updates are
+    /// held, see [`Self::debounce_key`].
+    fn to_held(&self) -> serde_json::Value {
+        match self {
+            Self::Issue(issue) => serde_json::to_value(issue).expect("summaries serialize to json"),
+            _ => serde_json::Value::Null,
+        }
+    }
+
+    fn from_held(value: serde_json::Value) -> Option<Self> {
+        let issue = serde_json::from_value(value).ok()?;
+        Some(Self::Issue(Box::new(issue)))
+    }
+
+    /// Folds `later`, a card with the same [`Self::debounce_key`], into
+    /// this one.
+    fn absorb(&mut self, later: Notification) {
+        match (self, later) {
+            (Self::Issue(issue), Self::Issue(later)) => issue.absorb(*later),
+            (this, later) => *this = later,
+        }
+    }
+
+    /// The message a failed send posted last: issues fall back to the
+    /// built-in card whenever the template does not go through.
+    fn last_message(&self, state: &AppState) -> serde_json::Value {
+        match self {
+            Self::Issue(issue) => {
+                serde_json::to_value(build_lark_card(issue, state.card_options()))
+                    .expect("cards serialize to json")
+            }
+            other => other.render(state),
+        }
+    }
+}
+
+/// Posts or queues `notification` for every route in `disposition`, with
+/// one copy of `event` per destination so each delivery shows in the
+/// history.
+async fn deliver_card(
+    state: &AppState,
+    disposition: &mut Disposition,
+    delivering: Delivering,
+    notification: &Notification,
+    event: Event,
+) {
+    let mut errors = Vec::new();
+    let mut queued = 0;
+    for destination in &disposition.routes {
+        let mut event = event.clone();
+        event.target = Some(destination.label.clone());
+
+        if delivering == Delivering::Queued {
+            let job = Job {
+                destination: destination.clone(),
+                notification: notification.clone(),
+                event,
+                span: tracing::Span::current(),
+            };
+            match state.delivery.enqueue(job).await {
+                Ok(None) => queued += 1,
+                Ok(Some(mut earlier)) => {
+                    queued += 1;
+                    info!(
+                        "collapsed an earlier {} update into the waiting card",
+                        notification.identifier()
+                    );
+                    earlier.disposition = "collapsed".into();
+                    store_event(state, *earlier).await;
+                }
+                Err(mut event) => {
+                    error!(
+                        "delivery queue full, dropping card for {} to {}",
+                        notification.identifier(),
+                        destination.label
+                    );
+                    event.disposition = "failed".into();
+                    event.outcome = Some("delivery queue full".into());
+                    store_event(state, *event).await;
+                    let error = "delivery queue full".to_string();
+                    lark::delivery::keep_dead_letter(state, destination, notification, 0, error)
+                        .await;
+                    errors.push(format!("{}: delivery queue full", destination.label));
+                }
+            }
+            continue;
+        }
+
+        match send_notification(state, &destination.url, notification).await {
+            Ok(()) => {
+                observe_delivery(state, &event);
+                event.disposition = "sent".into();
+            }
+            Err(e) => {
+                report_send_failure(state, &e).await;
+                event.disposition = "failed".into();
+                event.outcome = Some(e.to_string());
+                errors.push(format!("{}: {e}", destination.label));
+            }
+        }
+        store_event(state, event).await;
+    }
+
+    // Only when nothing went out, so a retry from Linear does not send
+    // the cards that were queued twice.
+    disposition.queue_full = delivering == Delivering::Queued && queued == 0 && !errors.is_empty();
+    if !errors.is_empty() {
+        disposition.outcome = "failed";
+        disposition.error = Some(errors.join("; "));
+    } else if delivering == Delivering::Queued {
+        disposition.outcome = "queued";
+    } else {
+        disposition.outcome = "sent";
+    }
+}
+
+/// How long a card waits for the Lark user lookup behind a mention.
+const MENTION_BUDGET: Duration = Duration::from_secs(2);
+
+/// The open_id to mention the user with `email` by, when Lark lookups are
+/// configured and answer within [`MENTION_BUDGET`].
+async fn lark_open_id(state: &AppState, email: Option<&str>) -> Option<String> {
+    let contacts = state.contacts.as_ref()?;
+    let email = email?;
+    match tokio::time::timeout(MENTION_BUDGET, contacts.open_id(&state.http, email)).await {
+        Ok(open_id) => open_id,
+        Err(_) => {
+            warn!("lark user lookup timed out, mentioning by name");
+            None
+        }
+    }
+}
+
+/// Required in `DEV_MODE_CONFIRM` next to `DEV_MODE=true`, so a single
+/// stray variable cannot open an unsigned endpoint.
+const DEV_MODE_CONFIRMATION: &str = "skip-signature-verification";
+
+#[derive(Debug, Deserialize)]
+struct SimulateQuery {
+    #[serde(default)]
+    send: bool,
+}
+
+/// `POST /simulate` (dev mode only): the webhook pipeline for an unsigned
+/// Linear-shaped body, reporting what it would do. Nothing is posted to
+/// Lark unless `?send=true`.
+async fn simulate_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SimulateQuery>,
+    body: Bytes,
+) -> Response {
+    if state.parse_mode == ParseMode::Strict {
+        log_drift(&state, &body);
+    }
+    let payload: LinearPayload = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "failed to parse payload: {}",
+                    truncate_chars(&e.to_string(), limits::MAX_FIELD_CHARS)
+                ),
+            )
+                .into_response();
+        }
+    };
+    let delivering = if query.send {
+        Delivering::Now
+    } else {
+        Delivering::No
+    };
+    Json(process_payload(&state, &payload, delivering).await).into_response()
+}
+
+/// Sends the issue card to `url`, preferring the configured template and
+/// falling back to the built-in card when Lark rejects it.
+async fn send_issue(state: &AppState, url: &str, issue: &IssueSummary) -> Result<(), LarkError> {
+    if state.card_options().callbacks {
+        keep_issue_card(state, issue).await;
+    }
+    if let Some(template) = &state.card_template {
+        let message = build_template_message(template, issue, state.card_language.labels());
+        match send_issue_card(state, url, issue, &message).await {
+            Ok(text) => {
+                info!("lark template notification sent: {text}");
+                return Ok(());
+            }
+            Err(e) => warn!("template card rejected, falling back to built-in card: {e}"),
+        }
+    }
+
+    let card = build_lark_card(issue, state.card_options());
+    let text = send_issue_card(state, url, issue, &card).await?;
+    info!("lark notification sent: {text}");
+    Ok(())
+}
+
+/// Stores `issue` for the callbacks of its card's buttons. Failures are
+/// only logged; the buttons then leave the card as it is.
+async fn keep_issue_card(state: &AppState, issue: &IssueSummary) {
+    let summary = serde_json::to_value(issue).expect("summaries serialize to json");
+    if let Err(e) = state.storage.issue_cards().set(&issue.id, &summary).await {
+        error!("failed to keep the card of {}: {e}", issue.identifier);
+    }
+}
+
+/// Sends a card about `issue` to `url`. In a chat, the bot edits the card
+/// it last sent about the issue instead, falling back to a new message
+/// when there is none or it can no longer be edited. Other failed edits are
+/// returned, so the queue retries the edit rather than posting a duplicate.
+///
+/// Cards past [`AppState::card_update_max_age`] and completions get a new
+/// message, leaving the old card as it was.
+async fn send_issue_card(
+    state: &AppState,
+    url: &str,
+    issue: &IssueSummary,
+    message: &impl Serialize,
+) -> Result<String, LarkError> {
+    let Some(app) = state.lark_app.as_ref().filter(|_| is_chat_id(url)) else {
+        return send_to_lark_at(state, url, message).await;
+    };
+    let message = serde_json::to_value(message).expect("cards serialize to json");
+    let messages = state.storage.lark_messages();
+    let gone = match messages.get(&issue.id, url).await {
+        Ok(Some(mapped)) if needs_new_card(state, issue, mapped.sent_at) => Some(mapped.message_id),
+        Ok(Some(MappedMessage { message_id, .. })) => {
+            let result = app.update_message(&state.http, &message_id, &message).await;
+            count_lark_send(state, "api", &result);
+            match result {
+                Ok(()) => {
+                    if let Err(e) = messages.touch(&issue.id, url).await {
+                        error!(
+                            "failed to mark the lark message of {} used: {e}",
+                            issue.identifier
+                        );
+                    }
+                    return Ok(format!("updated {message_id}"));
+                }
+                Err(e) if lark::app::message_gone(&e) => {
+                    warn!(
+                        "lark message {message_id} can no longer be edited, sending a new one: {e}"
+                    );
+                    Some(message_id)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!(
+                "failed to look up the lark message for {}: {e}",
+                issue.identifier
+            );
+            None
+        }
+    };
+
+    let message_id = send_to_chat(state, url, &message).await?;
+    let stored = match &gone {
+        Some(old) => messages.replace(&issue.id, url, old, &message_id).await,
+        None => match messages.claim(&issue.id, url, &message_id).await {
+            Ok(kept) if kept != message_id => {
+                warn!(
+                    "{} got two first cards in {url}, later changes edit {kept}",
+                    issue.identifier
+                );
+                Ok(())
+            }
+            claimed => claimed.map(drop),
+        },
+    };
+    if let Err(e) = stored {
+        error!(
+            "failed to store the lark message for {}: {e}",
+            issue.identifier
+        );
+    }
+    Ok(format!("sent {message_id}"))
+}
+
+/// Whether the change to `issue` deserves a new card rather than an edit
+/// of the one sent at `sent_at`.
+fn needs_new_card(
+    state: &AppState,
+    issue: &IssueSummary,
+    sent_at: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    let completed = issue.is_completed()
+        && issue
+            .changes
+            .iter()
+            .any(|change| matches!(change, Change::Status { .. }));
+    let age = (chrono::Utc::now() - sent_at).to_std().unwrap_or_default();
+    completed || age > state.card_update_max_age
+}
+
+/// Posts `notification` to `url`.
+async fn send_notification(
+    state: &AppState,
+    url: &str,
+    notification: &Notification,
+) -> Result<(), LarkError> {
+    match notification {
+        Notification::Issue(issue) => send_issue(state, url, issue).await,
+        other => {
+            let text = send_to_lark_at(state, url, &other.render(state)).await?;
+            info!("lark notification sent: {text}");
+            Ok(())
+        }
+    }
+}
+
+/// Adds the webhook to the event log. Failures are only logged; the log
+/// must never stand in the way of a notification.
+async fn record_event(
+    state: &AppState,
+    payload: &LinearPayload,
+    issue: &Issue,
+    disposition: &str,
+    target: Option<&str>,
+    outcome: Option<String>,
+) {
+    let mut event = new_event(payload, issue);
+    event.disposition = disposition.to_string();
+    event.target = target.map(str::to_string);
+    event.outcome = outcome;
+    store_event(state, event).await;
+}
+
+/// The event-log entry for `issue`, not yet given a disposition.
+fn new_event(payload: &LinearPayload, issue: &Issue) -> Event {
+    Event {
+        received_at: chrono::Utc::now(),
+        kind: payload.kind.clone(),
+        action: payload.action.clone(),
+        issue_id: issue.id.clone(),
+        identifier: issue.identifier.clone(),
+        team_key: issue.team.as_ref().map(|t| t.key.clone()),
+        title: issue.title.clone(),
+        state: issue
+            .state
+            .as_ref()
+            .map(|s| s.name.clone())
+            .unwrap_or_default(),
+        state_type: issue.state.as_ref().and_then(|s| s.kind.clone()),
+        priority: issue.priority.unwrap_or(0),
+        previous_priority: payload.updated_from.as_ref().and_then(|from| from.priority),
+        state_changed: payload
+            .updated_from
+            .as_ref()
+            .is_some_and(|from| from.state_id.is_some()),
+        assignee: issue.assignee.as_ref().map(|a| a.name.clone()),
+        issue_created_at: issue.created_at,
+        completed_at: issue.completed_at,
+        disposition: String::new(),
+        target: None,
+        outcome: None,
+    }
+}
+
+async fn store_event(state: &AppState, event: Event) {
+    let identifier = event.identifier.clone();
+    if let Err(e) = state.storage.events().record(event).await {
+        error!("failed to record {identifier} in the event log: {e}");
+    }
+}
+
+/// Posts a message to the Lark webhook `url`. Lark reports most failures
+/// with a 200 and a non-zero `code` in the body, so both are checked. Chat
+/// ids are posted to as the bot.
+async fn send_to_lark_at(
+    state: &AppState,
+    url: &str,
+    message: &impl Serialize,
+) -> Result<String, LarkError> {
+    if is_chat_id(url) {
+        let message = serde_json::to_value(message).expect("cards serialize to json");
+        return send_to_chat(state, url, &message).await;
+    }
+
+    let resp = match state.http.post(url).json(message).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            let labels = [("status", "none"), ("code", "none")];
+            state.metrics.inc(metrics::LARK_SENDS, &labels);
+            return Err(LarkError::transport(e));
+        }
+    };
+
+    let status = resp.status();
+    let text = resp.text().await.unwrap_or_default();
+
+    let result = match lark::errors::classify(status, &text) {
+        Some(err) => Err(err),
+        None => Ok(text),
+    };
+    count_lark_send(state, status.as_str(), &result);
+    result
+}
+
+/// Posts `message` to `chat_id` as the bot and returns the message id.
+async fn send_to_chat(
+    state: &AppState,
+    chat_id: &str,
+    message: &serde_json::Value,
+) -> Result<String, LarkError> {
+    let Some(app) = &state.lark_app else {
+        return Err(LarkError {
+            code: None,
+            class: ErrorClass::Configuration,
+            explanation: "a destination is a chat id but no lark app is configured".into(),
+            fix: Some("set LARK_APP_ID and LARK_APP_SECRET, or route to webhook urls"),
+            detail: chat_id.to_string(),
+        });
+    };
+    let result = app.send_message(&state.http, chat_id, message).await;
+    count_lark_send(state, "api", &result);
+    result
+}
+
+fn count_lark_send<T>(state: &AppState, status: &str, result: &Result<T, LarkError>) {
+    let code = match result {
+        Ok(_) => "0".to_string(),
+        Err(e) => e.code.map_or("none".into(), |code| code.to_string()),
+    };
+    state
+        .metrics
+        .inc(metrics::LARK_SENDS, &[("status", status), ("code", &code)]);
+}
+
+/// Records how long `event` took from receipt to reaching Lark.
+fn observe_delivery(state: &AppState, event: &Event) {
+    let elapsed = chrono::Utc::now() - event.received_at;
+    let seconds = elapsed.to_std().unwrap_or_default().as_secs_f64();
+    state
+        .metrics
+        .observe(metrics::DELIVERY_LATENCY, &[], seconds);
+}
+
+/// Logs a failed send with its explanation. Configuration errors also get a
+/// one-time alert to the ops webhook, since they will not fix themselves.
+async fn report_send_failure(state: &AppState, err: &LarkError) {
+    error!("lark notification failed: {err}");
+
+    if err.class != ErrorClass::Configuration {
+        return;
+    }
+    let fix = err.fix.unwrap_or("check the Lark bot configuration");
+    warn!("lark configuration problem: {fix}");
+
+    let Some(ops_url) = &state.lark_ops_webhook_url else {
+        return;
+    };
+    let key = err.code.unwrap_or_default();
+    if !state.alerted_error_codes.lock().unwrap().insert(key) {
+        return;
+    }
+
+    let alert = serde_json::json!({
+        "msg_type": "text",
+        "content": {
+            "text": format!(
+                "linear-lark-bridge cannot deliver notifications: {}. Suggested fix: {fix}",
+                err.explanation
+            ),
+        }
+    });
+    if let Err(e) = state.http.post(ops_url).json(&alert).send().await {
+        error!("failed to send ops alert: {e}");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Lark card callback
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize)]
+struct CardChallenge {
+    challenge: String,
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CardCallback {
+    open_id: String,
+    action: CardCallbackAction,
+}
+
+#[derive(Debug, Deserialize)]
+struct CardCallbackAction {
+    value: CardAction,
+}
+
+async fn card_callback_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(token) = state.lark_verification_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // 1. URL verification challenge, sent once when the callback URL is saved
+    //    in the Lark developer console. It carries the token but no signature.
+    if let Ok(challenge) = serde_json::from_slice::<CardChallenge>(&body) {
+        if challenge.token != token {
+            warn!("card callback challenge with invalid token");
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        info!("answering lark card callback challenge");
+        return Json(serde_json::json!({ "challenge": challenge.challenge })).into_response();
+    }
+
+    // 2. Signature verification
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(timestamp), Some(nonce), Some(signature)) = (
+        header("x-lark-request-timestamp"),
+        header("x-lark-request-nonce"),
+        header("x-lark-signature"),
+    ) else {
+        warn!("missing lark signature headers");
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    if !verify_lark_signature(token, timestamp, nonce, &body, signature) {
+        warn!("invalid lark card callback signature");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    // 3. Deserialize the action value we embedded in the button
+    let callback: CardCallback = match serde_json::from_slice(&body) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("failed to parse card callback: {e}");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    let CardAction { action, issue_id } = callback.action.value;
+    let stored = state
+        .storage
+        .issue_cards()
+        .get(&issue_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("failed to look up the card of {issue_id}: {e}");
+            None
+        });
+    let Some(mut issue) =
+        stored.and_then(|summary| serde_json::from_value::<IssueSummary>(summary).ok())
+    else {
+        warn!("card action {action} for unknown issue {issue_id}, leaving the card as is");
+        return Json(serde_json::json!({})).into_response();
+    };
+
+    // 4. Apply the action and respond with the updated card
+    let result = match action.as_str() {
+        "ack" => {
+            info!("{} acknowledged by {}", issue.identifier, callback.open_id);
+            issue.acknowledged_by = Some(callback.open_id);
+            Ok(())
+        }
+        "start" | "done" => {
+            let state_type = if action == "start" {
+                "started"
+            } else {
+                "completed"
+            };
+            let result = transition_issue(&state, &mut issue, state_type).await;
+            if result.is_ok() {
+                info!(
+                    "{} moved to {} by {}",
+                    issue.identifier,
+                    issue.state.as_deref().unwrap_or(state_type),
+                    callback.open_id
+                );
+            }
+            result
+        }
+        "assign" => {
+            let result = assign_to_clicker(&state, &mut issue, &callback.open_id).await;
+            if result.is_ok() {
+                info!("{} assigned to {}", issue.identifier, callback.open_id);
+            }
+            result
+        }
+        "urgent" => {
+            let result = set_urgent(&state, &mut issue).await;
+            if result.is_ok() {
+                info!("{} set to urgent by {}", issue.identifier, callback.open_id);
+            }
+            result
+        }
+        other => {
+            warn!("ignoring unknown card action: {other}");
+            Ok(())
+        }
+    };
+
+    let action_error = match result {
+        Ok(()) => {
+            keep_issue_card(&state, &issue).await;
+            None
+        }
+        Err(e) => {
+            error!("card action {action} on {} failed: {e}", issue.identifier);
+            Some(e.to_string())
+        }
+    };
+
+    Json(render_issue_card(
+        &issue,
+        state.card_options(),
+        action_error.as_deref(),
+    ))
+    .into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Lark event subscription
+// ---------------------------------------------------------------------------
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+#[derive(Debug, Deserialize)]
+struct EncryptedEvent {
+    encrypt: String,
+}
+
+/// Decrypts an `{"encrypt": "..."}` event body. Lark uses AES-256-CBC with
+/// `sha256(encrypt_key)` as the key and the first 16 bytes as the IV.
+fn decrypt_lark_event(encrypt_key: &str, encrypted: &str) -> Option<Vec<u8>> {
+    let data = BASE64.decode(encrypted).ok()?;
+    if data.len() <= 16 {
+        return None;
+    }
+    let (iv, ciphertext) = data.split_at(16);
+    let key = Sha256::digest(encrypt_key.as_bytes());
+    Aes256CbcDec::new_from_slices(&key, iv)
+        .ok()?
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .ok()
+}
+
+/// Lark signs event deliveries as `sha256(timestamp + nonce + encrypt_key + body)`.
+fn verify_lark_event_signature(
+    encrypt_key: &str,
+    timestamp: &str,
+    nonce: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(timestamp.as_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.update(encrypt_key.as_bytes());
+    hasher.update(body);
+    constant_time_eq(
+        hex::encode(hasher.finalize()).as_bytes(),
+        signature.as_bytes(),
+    )
+}
+
+async fn lark_events_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(token) = state.lark_verification_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // 1. Decrypt when an encrypt key is configured
+    let plain = match &state.lark_encrypt_key {
+        Some(key) => {
+            let encrypted: EncryptedEvent = match serde_json::from_slice(&body) {
+                Ok(e) => e,
+                Err(e) => {
+                    warn!("expected encrypted lark event: {e}");
+                    return StatusCode::BAD_REQUEST.into_response();
+                }
+            };
+            match decrypt_lark_event(key, &encrypted.encrypt) {
+                Some(plain) => plain,
+                None => {
+                    warn!("failed to decrypt lark event");
+                    return StatusCode::BAD_REQUEST.into_response();
+                }
+            }
+        }
+        None => body.to_vec(),
+    };
+
+    let event: serde_json::Value = match serde_json::from_slice(&plain) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to parse lark event: {e}");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    // 2. Token verification. Schema 1.0 events and the challenge carry the
+    //    token at the top level, schema 2.0 events inside `header`.
+    let event_token = event
+        .pointer("/header/token")
+        .or_else(|| event.get("token"))
+        .and_then(|t| t.as_str());
+    if event_token != Some(token) {
+        warn!("lark event with invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    // 3. URL verification challenge
+    if event.get("type").and_then(|t| t.as_str()) == Some("url_verification") {
+        info!("answering lark url verification challenge");
+        let challenge = event.get("challenge").cloned().unwrap_or_default();
+        return Json(serde_json::json!({ "challenge": challenge })).into_response();
+    }
+
+    // 4. Signature verification, only possible with an encrypt key
+    if let Some(key) = &state.lark_encrypt_key {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let (Some(timestamp), Some(nonce), Some(signature)) = (
+            header("x-lark-request-timestamp"),
+            header("x-lark-request-nonce"),
+            header("x-lark-signature"),
+        ) else {
+            warn!("missing lark signature headers");
+            return StatusCode::UNAUTHORIZED.into_response();
+        };
+
+        if !verify_lark_event_signature(key, timestamp, nonce, &body, signature) {
+            warn!("invalid lark event signature");
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    let event_type = event
+        .pointer("/header/event_type")
+        .or_else(|| event.pointer("/event/type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("unknown");
+    info!("ignoring lark event: type={event_type}");
+
+    StatusCode::OK.into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Linear OAuth
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize)]
+struct OAuthStartQuery {
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthCallbackQuery {
+    code: String,
+    state: String,
+}
+
+/// Starts the install flow. Opened in a browser, so the admin token comes
+/// as `?token=` rather than a header.
+async fn oauth_start_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OAuthStartQuery>,
+) -> Response {
+    let Some(oauth) = &state.oauth else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !is_admin(&state, query.token.as_deref()) {
+        warn!("rejected linear oauth start without a valid admin token");
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    Redirect::to(&oauth.authorize_url()).into_response()
+}
+
+async fn oauth_callback_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> Response {
+    let Some(oauth) = &state.oauth else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match oauth.complete(&query.code, &query.state).await {
+        Ok(()) => "Linear authorized. You can close this tab.".into_response(),
+        Err(e) => {
+            error!("linear oauth callback failed: {e}");
+            (
+                StatusCode::BAD_REQUEST,
+                "Linear authorization failed, see logs.",
+            )
+                .into_response()
+        }
+    }
+}
+
+fn is_admin(state: &AppState, token: Option<&str>) -> bool {
+    match (&state.admin_token, token) {
+        (Some(expected), Some(token)) => constant_time_eq(expected.as_bytes(), token.as_bytes()),
+        _ => false,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Admin api
+// ---------------------------------------------------------------------------
+
+/// Admin calls come from scripts, so the token is a bearer header.
+fn is_admin_request(state: &AppState, headers: &HeaderMap) -> bool {
+    let token = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    is_admin(state, token)
+}
+
+#[derive(Deserialize)]
+struct MuteRequest {
+    /// Issue identifier, e.g. `ENG-42`.
+    issue: String,
+    days: u64,
+}
+
+/// Snoozes an issue on the stale issues card for `days`.
+async fn mute_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<MuteRequest>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let today = chrono::Utc::now()
+        .with_timezone(&state.timezone)
+        .date_naive();
+    let until = today + chrono::Days::new(request.days);
+    match state.storage.reminders().mute(&request.issue, until).await {
+        Ok(()) => {
+            info!("muted {} until {until}", request.issue);
+            Json(serde_json::json!({ "issue": request.issue, "until": until })).into_response()
+        }
+        Err(e) => {
+            error!("failed to mute {}: {e}", request.issue);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn unmute_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(issue): Path<String>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match state.storage.reminders().unmute(&issue).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("failed to unmute {issue}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Runs store maintenance now instead of waiting for `MAINTENANCE_CRON`.
+async fn maintenance_run_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match jobs::maintenance::run(&state, &state.retention).await {
+        Some(report) => Json(report).into_response(),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Sends today's digest now instead of waiting for `DIGEST_CRON`.
+async fn digest_run_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let cards = jobs::digest::run(&state).await;
+    Json(serde_json::json!({ "cards": cards })).into_response()
+}
+
+/// Cards the delivery worker gave up on, oldest first.
+async fn dead_letters_handler(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match state.storage.dead_letters().list().await {
+        Ok(letters) => Json(serde_json::json!({ "dead_letters": letters })).into_response(),
+        Err(e) => {
+            error!("failed to list dead letters: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Posts a dead letter again, once and right away, and forgets it if
+/// Lark takes it this time.
+async fn replay_dead_letter_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let letter = match state.storage.dead_letters().get(id).await {
+        Ok(Some(letter)) => letter,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("failed to load dead letter {id}: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Err(e) = send_to_lark_at(&state, &letter.url, &letter.message).await {
+        warn!(
+            "replaying dead letter {id} for {} failed: {e}",
+            letter.identifier
+        );
+        let body = serde_json::json!({ "id": id, "error": e.to_string() });
+        return (StatusCode::BAD_GATEWAY, Json(body)).into_response();
+    }
+    info!(
+        "replayed dead letter {id} for {} to {}",
+        letter.identifier, letter.label
+    );
+    if let Err(e) = state.storage.dead_letters().remove(id).await {
+        error!("failed to remove replayed dead letter {id}: {e}");
+    }
+    Json(serde_json::json!({ "id": id, "replayed": true })).into_response()
+}
+
+async fn discard_dead_letter_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    match state.storage.dead_letters().remove(id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("failed to discard dead letter {id}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// History
+// ---------------------------------------------------------------------------
+
+const HISTORY_PAGE_SIZE: usize = 50;
+const HISTORY_MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Issue identifier, e.g. `ENG-42`.
+    issue: Option<String>,
+    team: Option<String>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    id: i64,
+    received_at: chrono::DateTime<chrono::Utc>,
+    #[serde(rename = "type")]
+    kind: String,
+    action: String,
+    issue_id: String,
+    identifier: String,
+    team: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    state: String,
+    state_type: Option<String>,
+    priority: u8,
+    previous_priority: Option<u8>,
+    assignee: Option<String>,
+    disposition: String,
+    target: Option<String>,
+    outcome: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HistoryPage {
+    events: Vec<HistoryEntry>,
+    /// Pass as `cursor` to get the next page; absent on the last one.
+    next_cursor: Option<i64>,
+}
+
+/// Everything the bridge processed for an issue (`?issue=`) or a team
+/// (`?team=`, optionally bounded by `since` / `until`), oldest first.
+async fn history_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if query.issue.is_none() && query.team.is_none() {
+        return (StatusCode::BAD_REQUEST, "issue or team is required").into_response();
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(HISTORY_PAGE_SIZE)
+        .clamp(1, HISTORY_MAX_PAGE_SIZE);
+    let filter = HistoryFilter {
+        identifier: query.issue,
+        team_key: query.team,
+        since: query.since,
+        until: query.until,
+        after_id: query.cursor,
+        // One extra row tells whether there is another page.
+        limit: limit + 1,
+    };
+    let mut events = match state.storage.events().history(filter).await {
+        Ok(events) => events,
+        Err(e) => {
+            error!("history query failed: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let next_cursor = if events.len() > limit {
+        events.truncate(limit);
+        events.last().map(|(id, _)| *id)
+    } else {
+        None
+    };
+    let events = events
+        .into_iter()
+        .map(|(id, e)| HistoryEntry {
+            id,
+            received_at: e.received_at,
+            kind: e.kind,
+            action: e.action,
+            issue_id: e.issue_id,
+            identifier: e.identifier,
+            team: e.team_key,
+            title: (!state.redact_content).then_some(e.title),
+            state: e.state,
+            state_type: e.state_type,
+            priority: e.priority,
+            previous_priority: e.previous_priority,
+            assignee: e.assignee,
+            disposition: e.disposition,
+            target: e.target,
+            outcome: e.outcome,
+        })
+        .collect();
+
+    Json(HistoryPage {
+        events,
+        next_cursor,
+    })
+    .into_response()
+}
+
+const EXPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    team: Option<String>,
+    /// Comma-separated subset of [`export::COLUMNS`].
+    fields: Option<String>,
+}
+
+/// Streams matching events as CSV. The log is read in batches, so large
+/// exports are never held in memory at once.
+async fn export_events_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    if !is_admin_request(&state, &headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let columns = match export::select_columns(query.fields.as_deref(), state.redact_content) {
+        Ok(columns) => columns,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let first = HistoryFilter {
+        team_key: query.team,
+        since: query.since,
+        until: query.until,
+        limit: EXPORT_BATCH_SIZE,
+        ..HistoryFilter::default()
+    };
+    let header = Bytes::from(export::header(&columns));
+    let storage = state.storage.clone();
+    let rows = futures_util::stream::try_unfold(Some(first), move |filter| {
+        let storage = storage.clone();
+        let columns = columns.clone();
+        async move {
+            let Some(filter) = filter else {
+                return Ok(None);
+            };
+            let batch = storage
+                .events()
+                .history(filter.clone())
+                .await
+                .map_err(|e| {
+                    error!("csv export failed: {e}");
+                    std::io::Error::other(e.to_string())
+                })?;
+            let next = (batch.len() == EXPORT_BATCH_SIZE).then(|| HistoryFilter {
+                after_id: batch.last().map(|(id, _)| *id),
+                ..filter
+            });
+            let chunk: String = batch
+                .iter()
+                .map(|(_, e)| export::row(e, &columns))
+                .collect();
+            Ok::<_, std::io::Error>((!chunk.is_empty()).then(|| (Bytes::from(chunk), next)))
+        }
+    });
+    let body =
+        futures_util::StreamExt::chain(futures_util::stream::once(async { Ok(header) }), rows);
+
+    (
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (CONTENT_DISPOSITION, "attachment; filename=\"events.csv\""),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+// ---------------------------------------------------------------------------
+// Health-check
 // ---------------------------------------------------------------------------
-// Health-check
-// ---------------------------------------------------------------------------
 
-async fn health() -> &'static str {
-    "ok"
+/// Liveness plus the delivery queue's counters and the number of dead
+/// letters waiting for replay (`null` if the store cannot say).
+async fn health(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let dead_letters = match state.storage.dead_letters().count().await {
+        Ok(count) => Some(count),
+        Err(e) => {
+            error!("failed to count dead letters: {e}");
+            None
+        }
+    };
+    let mappings = match state.storage.lark_messages().count().await {
+        Ok(counts) => Some(counts),
+        Err(e) => {
+            error!("failed to count lark message mappings: {e}");
+            None
+        }
+    };
+    Json(serde_json::json!({
+        "status": "ok",
+        "delivery": state.delivery.stats(),
+        "totals": state.delivery.totals(),
+        "dead_letters": dead_letters,
+        "mappings": mappings,
+        "lark_app": state.lark_app.as_ref().map(|app| serde_json::json!({
+            "credentials_rejected": app.credentials_rejected(),
+        })),
+    }))
+}
+
+/// Counters and histograms in the Prometheus text format, plus the
+/// delivery queue and payload caps as of now.
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> Response {
+    let metrics = &state.metrics;
+    let stats = state.delivery.stats();
+    metrics.set(metrics::QUEUED, &[], stats.queued);
+    metrics.set(metrics::SENT, &[], stats.sent);
+    metrics.set(metrics::RETRIED, &[], stats.retried);
+    metrics.set(metrics::DROPPED, &[], stats.dropped);
+    match state.storage.dead_letters().count().await {
+        Ok(count) => metrics.set(metrics::DEAD_LETTERS, &[], count),
+        Err(e) => error!("failed to count dead letters: {e}"),
+    }
+    let caps = &limits::CAPS;
+    for (cap, counter) in [
+        ("text", &caps.text),
+        ("field", &caps.field),
+        ("event", &caps.event),
+    ] {
+        let count = counter.load(std::sync::atomic::Ordering::Relaxed);
+        metrics.set(metrics::CAPS, &[("cap", cap)], count);
+    }
+    let enricher = &state.enricher;
+    for (kind, stats) in [
+        ("description", &enricher.description_stats),
+        ("comment", &enricher.comment_stats),
+    ] {
+        for (outcome, counter) in [
+            ("hit", &stats.hit),
+            ("miss", &stats.miss),
+            ("timeout", &stats.timeout),
+        ] {
+            let count = counter.load(std::sync::atomic::Ordering::Relaxed);
+            metrics.set(
+                metrics::ENRICHMENT,
+                &[("kind", kind), ("outcome", outcome)],
+                count,
+            );
+        }
+    }
+
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+/// Wraps every routed request: takes the caller's `x-request-id` or makes
+/// one, runs the handler in a span carrying it (queued cards keep the
+/// span), echoes it back, and records the request's metrics.
+async fn track_requests(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64)
+        .map(str::to_string)
+        .unwrap_or_else(|| hex::encode(rand::random::<[u8; 8]>()));
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let method = request.method().to_string();
+
+    let span = info_span!("request", id = %id, method = %method, route = %route);
+    let started = Instant::now();
+    let mut response = next.run(request).instrument(span).await;
+
+    let status = response.status();
+    state.metrics.inc(
+        metrics::HTTP_REQUESTS,
+        &[
+            ("method", &method),
+            ("route", &route),
+            ("status", status.as_str()),
+        ],
+    );
+    state.metrics.observe(
+        metrics::HTTP_DURATION,
+        &[("route", &route)],
+        started.elapsed().as_secs_f64(),
+    );
+    if let Ok(id) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert("x-request-id", id);
+    }
+    response
+}
+
+/// Routes for the features `state` has configured; the rest are not
+/// registered at all.
+fn router(state: Arc<AppState>) -> Router {
+    let mut app = Router::new()
+        .route("/webhook", post(webhook_handler))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics_handler));
+
+    if state.lark_verification_token.is_some() {
+        app = app
+            .route("/lark/card-callback", post(card_callback_handler))
+            .route("/lark/events", post(lark_events_handler));
+    }
+
+    if state.oauth.is_some() {
+        app = app
+            .route("/auth/linear", get(oauth_start_handler))
+            .route("/auth/linear/callback", get(oauth_callback_handler));
+    }
+
+    if state.dev_mode {
+        app = app.route("/simulate", post(simulate_handler));
+    }
+
+    if state.admin_token.is_some() {
+        app = app
+            .route("/admin/mute", post(mute_handler))
+            .route("/admin/mute/{issue}", delete(unmute_handler))
+            .route("/admin/digest/run", post(digest_run_handler))
+            .route("/admin/maintenance/run", post(maintenance_run_handler))
+            .route("/admin/dead-letters", get(dead_letters_handler))
+            .route(
+                "/admin/dead-letters/{id}",
+                delete(discard_dead_letter_handler),
+            )
+            .route(
+                "/admin/dead-letters/{id}/replay",
+                post(replay_dead_letter_handler),
+            )
+            .route("/history", get(history_handler))
+            .route("/export/events.csv", get(export_events_handler));
+    }
+
+    app.route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        track_requests,
+    ))
+    .with_state(state)
 }
 
 // ---------------------------------------------------------------------------
@@ -304,22 +4470,452 @@ async fn main() {
 
     let webhook_secret =
         env::var("LINEAR_WEBHOOK_SECRET").expect("LINEAR_WEBHOOK_SECRET must be set");
-    let lark_webhook_url = env::var("LARK_WEBHOOK_URL").unwrap_or_else(|_| {
-        warn!("LARK_WEBHOOK_URL not set – lark notifications will fail");
+    let routes = match env::var("ROUTES_CONFIG") {
+        Ok(path) => {
+            let routes = Routes::load(path.as_ref())
+                .unwrap_or_else(|e| panic!("invalid ROUTES_CONFIG {path}: {e}"));
+            info!("loaded {} notification routes from {path}", routes.len());
+            routes
+        }
+        Err(_) => Routes::default(),
+    };
+    let ignore = IgnoreRules::new(
+        env_list("IGNORE_STATES"),
+        env_list("IGNORE_LABELS"),
+        env_list("IGNORE_ACTORS"),
+    );
+    if ignore.len() > 0 {
+        info!("ignoring events matching {} rules", ignore.len());
+    }
+    // Bot mode posts to a chat as the Lark app instead of a custom bot's
+    // webhook, which lets cards be edited when their issue changes.
+    let bot_delivery = match env::var("LARK_DELIVERY").as_deref() {
+        Err(_) | Ok("webhook") => false,
+        Ok("bot") => true,
+        Ok(other) => panic!("invalid LARK_DELIVERY {other:?}, expected webhook or bot"),
+    };
+    let default_destination = if bot_delivery {
+        "LARK_CHAT_ID"
+    } else {
+        "LARK_WEBHOOK_URL"
+    };
+    let lark_webhook_url = env::var(default_destination).unwrap_or_else(|_| {
+        if routes.len() == 0 {
+            warn!("{default_destination} not set – lark notifications will fail");
+        } else {
+            warn!("{default_destination} not set – issues no route matches are not sent");
+        }
         String::new()
     });
+    if bot_delivery && !lark_webhook_url.is_empty() && !is_chat_id(&lark_webhook_url) {
+        panic!("invalid LARK_CHAT_ID {lark_webhook_url:?}, expected an oc_ chat id");
+    }
+    let lark_verification_token = env::var("LARK_VERIFICATION_TOKEN").ok();
+    if lark_verification_token.is_none() {
+        info!("LARK_VERIFICATION_TOKEN not set – card callbacks and lark events disabled");
+    }
+    let lark_encrypt_key = env::var("LARK_ENCRYPT_KEY").ok();
+    let lark_ops_webhook_url = env::var("LARK_OPS_WEBHOOK_URL").ok();
+    let store_path = env::var("STORE_PATH").unwrap_or_else(|_| "bridge.db".into());
+    let storage = Storage::open(store_path.as_ref())
+        .unwrap_or_else(|e| panic!("failed to open store at {store_path}: {e}"));
+    let http = Client::new();
+    let admin_token = env_secret("ADMIN_TOKEN");
+    let redact_content = env::var("REDACT_CONTENT").is_ok_and(|v| v == "true");
+    let parse_mode = env::var("PARSE_MODE")
+        .ok()
+        .map(|v| {
+            v.parse::<ParseMode>()
+                .unwrap_or_else(|e| panic!("invalid PARSE_MODE: {e}"))
+        })
+        .unwrap_or_default();
+    let dev_mode = env::var("DEV_MODE").is_ok_and(|v| v == "true");
+    if dev_mode {
+        let confirmed = env::var("DEV_MODE_CONFIRM").is_ok_and(|v| v == DEV_MODE_CONFIRMATION);
+        assert!(
+            confirmed,
+            "DEV_MODE=true also needs DEV_MODE_CONFIRM={DEV_MODE_CONFIRMATION}"
+        );
+        warn!(
+            "DEV_MODE is on – POST /simulate accepts UNSIGNED payloads, never run this in production"
+        );
+    }
+
+    // OAuth wins over a personal key when both are configured.
+    let oauth = match env::var("LINEAR_OAUTH_CLIENT_ID").ok() {
+        Some(client_id) => {
+            let client_secret = env_secret("LINEAR_OAUTH_CLIENT_SECRET")
+                .expect("LINEAR_OAUTH_CLIENT_SECRET must be set with LINEAR_OAUTH_CLIENT_ID");
+            let public_url =
+                env::var("PUBLIC_URL").expect("PUBLIC_URL must be set for linear oauth");
+            let config = OAuthConfig {
+                client_id,
+                client_secret,
+                redirect_uri: format!("{}/auth/linear/callback", public_url.trim_end_matches('/')),
+                scopes: env::var("LINEAR_OAUTH_SCOPES").unwrap_or_else(|_| "read,write".into()),
+            };
+            Some(Arc::new(
+                OAuth::new(config, http.clone(), storage.clone()).await,
+            ))
+        }
+        None => None,
+    };
+    let linear_auth = match &oauth {
+        Some(oauth) => Some(LinearAuth::OAuth(oauth.clone())),
+        None => env_secret("LINEAR_API_KEY").map(LinearAuth::ApiKey),
+    };
+    let linear = linear_auth.map(|auth| {
+        let timeout = env::var("LINEAR_API_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+        let client = LinearClient::new(auth, Duration::from_secs(timeout));
+        match env::var("LINEAR_API_URL") {
+            Ok(url) => client.with_endpoint(url),
+            Err(_) => client,
+        }
+    });
+    if linear.is_none() {
+        info!("neither LINEAR_API_KEY nor linear oauth set – linear api features disabled");
+    }
+    let card_template = env::var("LARK_CARD_TEMPLATE_ID").ok().map(|id| {
+        CardTemplate::new(
+            id,
+            env::var("LARK_CARD_TEMPLATE_VERSION").ok(),
+            env::var("LARK_CARD_TEMPLATE_VARIABLES").ok().as_deref(),
+        )
+        .unwrap_or_else(|e| panic!("invalid LARK_CARD_TEMPLATE_VARIABLES: {e}"))
+    });
+    let card_language = env::var("CARD_LANGUAGE")
+        .ok()
+        .map(|lang| {
+            lang.parse::<CardLanguage>()
+                .unwrap_or_else(|e| panic!("invalid CARD_LANGUAGE: {e}"))
+        })
+        .unwrap_or_default();
+    let card_include_description = env::var("CARD_INCLUDE_DESCRIPTION").is_ok_and(|v| v == "true");
+    let linear_workspace = env::var("LINEAR_WORKSPACE").ok();
+    let card_include_latest_comment =
+        env::var("CARD_INCLUDE_LATEST_COMMENT").is_ok_and(|v| v == "true");
+    let persisted_counters: Vec<String> = match env::var("PERSISTED_COUNTERS") {
+        Ok(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                assert!(
+                    jobs::counters::COUNTERS.contains(&name),
+                    "unknown PERSISTED_COUNTERS entry {name:?}, expected one of {:?}",
+                    jobs::counters::COUNTERS
+                );
+                name.to_string()
+            })
+            .collect(),
+        Err(_) => jobs::counters::COUNTERS
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+    };
+    let counter_snapshots = Duration::from_secs(
+        env::var("COUNTER_SNAPSHOT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300),
+    );
+    let card_update_max_age = Duration::from_secs(
+        env::var("CARD_UPDATE_MAX_AGE_HOURS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(24)
+            * 60
+            * 60,
+    );
+    let enrichment_budget = env::var("ENRICHMENT_BUDGET_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(800);
+    let timezone: Tz = env::var("TIMEZONE")
+        .ok()
+        .map(|tz| {
+            tz.parse()
+                .unwrap_or_else(|e| panic!("invalid TIMEZONE {tz:?}: {e}"))
+        })
+        .unwrap_or(Tz::UTC);
+    let due_reminders = env_schedule("DUE_REMINDER_CRON").map(|schedule| {
+        let config = DueDateConfig {
+            team_keys: env_list("DUE_REMINDER_TEAMS"),
+            days_ahead: env::var("DUE_REMINDER_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
+            repeat: env::var("DUE_REMINDER_REPEAT").is_ok_and(|v| v == "true"),
+        };
+        (schedule, config)
+    });
+    let stale_issues = env_schedule("STALE_ISSUES_CRON").map(|schedule| {
+        let config = StaleConfig {
+            team_keys: env_list("STALE_ISSUES_TEAMS"),
+            idle_days: env::var("STALE_ISSUES_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(7),
+        };
+        (schedule, config)
+    });
+    let retention_days = |name: &str, default: u64| {
+        let days: u64 = env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default);
+        Duration::from_secs(days * 24 * 60 * 60)
+    };
+    let retention = Retention {
+        events: retention_days("RETENTION_EVENTS_DAYS", 90),
+        dedup: retention_days("RETENTION_DEDUP_DAYS", 7),
+        dead_letters: retention_days("RETENTION_DEAD_LETTERS_DAYS", 30),
+        mappings: retention_days("RETENTION_MAPPINGS_DAYS", 90),
+    };
+    let maintenance = env_schedule("MAINTENANCE_CRON")
+        .unwrap_or_else(|| "0 30 3 * * *".parse().expect("default schedule is valid"));
+    let sla_monitor = env::var("SLA_MONITOR_INTERVAL_SECS").ok().map(|secs| {
+        let interval = secs
+            .parse()
+            .map(Duration::from_secs)
+            .unwrap_or_else(|e| panic!("invalid SLA_MONITOR_INTERVAL_SECS {secs:?}: {e}"));
+        let config = SlaConfig {
+            team_keys: env_list("SLA_MONITOR_TEAMS"),
+            lookahead: chrono::TimeDelta::minutes(
+                env::var("SLA_LOOKAHEAD_MINUTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(60),
+            ),
+            realert: env::var("SLA_REALERT")
+                .ok()
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|e| panic!("invalid SLA_REALERT: {e}"))
+                })
+                .unwrap_or(jobs::sla::RealertPolicy::Once),
+            webhook_url: env::var("SLA_ALERT_WEBHOOK_URL").ok(),
+        };
+        (interval, config)
+    });
+    let digest = env_schedule("DIGEST_CRON");
+    let weekly_summary = env_schedule("WEEKLY_SUMMARY_CRON");
+    let delivery_config = {
+        let defaults = DeliveryConfig::default();
+        DeliveryConfig {
+            capacity: env::var("DELIVERY_QUEUE_CAPACITY")
+                .ok()
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|e| panic!("invalid DELIVERY_QUEUE_CAPACITY: {e}"))
+                })
+                .unwrap_or(defaults.capacity),
+            max_attempts: env::var("DELIVERY_MAX_ATTEMPTS")
+                .ok()
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|e| panic!("invalid DELIVERY_MAX_ATTEMPTS: {e}"))
+                })
+                .unwrap_or(defaults.max_attempts),
+            debounce: env::var("DEBOUNCE_SECS")
+                .ok()
+                .map(|v| {
+                    Duration::from_secs(
+                        v.parse()
+                            .unwrap_or_else(|e| panic!("invalid DEBOUNCE_SECS: {e}")),
+                    )
+                })
+                .unwrap_or(defaults.debounce),
+            workers: env::var("DELIVERY_WORKERS")
+                .ok()
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|e| panic!("invalid DELIVERY_WORKERS: {e}"))
+                })
+                .unwrap_or(defaults.workers),
+            ..defaults
+        }
+    };
+    // Inside Docker's default 10s stop timeout.
+    let drain_timeout = Duration::from_secs(
+        env::var("DELIVERY_DRAIN_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(8),
+    );
+    let (delivery, delivery_jobs) = Delivery::new(delivery_config, storage.clone());
+    let lark_app = match (env::var("LARK_APP_ID"), env_secret("LARK_APP_SECRET")) {
+        (Ok(app_id), Some(app_secret)) => {
+            let base_url =
+                env::var("LARK_API_BASE").unwrap_or_else(|_| "https://open.larksuite.com".into());
+            Some(Arc::new(LarkApp::new(&base_url, app_id, app_secret)))
+        }
+        (Ok(_), None) | (Err(_), Some(_)) => {
+            warn!("only one of LARK_APP_ID and LARK_APP_SECRET set – lark mentions disabled");
+            None
+        }
+        (Err(_), None) => None,
+    };
+    if bot_delivery && lark_app.is_none() {
+        panic!("LARK_DELIVERY=bot needs LARK_APP_ID and LARK_APP_SECRET");
+    }
+    let contacts = lark_app.clone().map(Contacts::new);
     let port = env::var("PORT").unwrap_or_else(|_| "3000".into());
 
+    // Webhook (de)registration needs the api key and our public address.
+    let deregister = env::args().skip(1).any(|arg| arg == "--deregister");
+    let auto_register = env::var("AUTO_REGISTER_WEBHOOK").is_ok_and(|v| v == "true");
+    if deregister || auto_register {
+        let linear = linear
+            .as_ref()
+            .expect("LINEAR_API_KEY must be set to manage the linear webhook");
+        let public_url =
+            env::var("PUBLIC_URL").expect("PUBLIC_URL must be set to manage the linear webhook");
+        let url = format!("{}/webhook", public_url.trim_end_matches('/'));
+
+        if deregister {
+            registration::deregister(linear, &url)
+                .await
+                .unwrap_or_else(|e| panic!("failed to deregister linear webhook: {e}"));
+            return;
+        }
+
+        let registration = Registration {
+            url,
+            secret: webhook_secret.clone(),
+            resource_types: registration::resource_types(env_list("WEBHOOK_RESOURCE_TYPES")),
+        };
+        // Keep serving on failure: the webhook may well exist already.
+        if let Err(e) = registration::register(linear, &registration).await {
+            error!("failed to register linear webhook: {e}");
+        }
+    }
+
     let state = Arc::new(AppState {
         webhook_secret,
         lark_webhook_url,
-        http: Client::new(),
+        routes,
+        ignore,
+        lark_verification_token,
+        lark_encrypt_key,
+        lark_ops_webhook_url,
+        alerted_error_codes: Mutex::new(HashSet::new()),
+        linear,
+        linear_workspace,
+        card_template,
+        card_language,
+        card_include_description,
+        card_include_latest_comment,
+        card_update_max_age,
+        persisted_counters,
+        enricher: Enricher::new(Duration::from_millis(enrichment_budget)),
+        workflow_states: Mutex::new(HashMap::new()),
+        oauth,
+        admin_token,
+        storage,
+        retention,
+        redact_content,
+        delivery,
+        metrics: Metrics::default(),
+        lark_app,
+        contacts,
+        parse_mode,
+        dev_mode,
+        timezone,
+        http,
     });
 
-    let app = Router::new()
-        .route("/webhook", post(webhook_handler))
-        .route("/health", axum::routing::get(health))
-        .with_state(state);
+    tokio::spawn(lark::delivery::run(state.clone(), delivery_jobs));
+    state.delivery.resume().await;
+
+    if let Some((schedule, config)) = due_reminders {
+        if state.linear.is_none() {
+            warn!("DUE_REMINDER_CRON set without linear api access – due date reminders disabled");
+        } else {
+            let state = state.clone();
+            let config = Arc::new(config);
+            jobs::spawn("due date reminders", schedule, state.timezone, move || {
+                let state = state.clone();
+                let config = config.clone();
+                async move { jobs::due_dates::run(&state, &config).await }
+            });
+        }
+    }
+
+    if let Some((schedule, config)) = stale_issues {
+        if state.linear.is_none() {
+            warn!("STALE_ISSUES_CRON set without linear api access – stale issue nudges disabled");
+        } else {
+            let state = state.clone();
+            let config = Arc::new(config);
+            jobs::spawn("stale issues", schedule, state.timezone, move || {
+                let state = state.clone();
+                let config = config.clone();
+                async move { jobs::stale::run(&state, &config).await }
+            });
+        }
+    }
+
+    jobs::counters::restore(&state).await;
+    {
+        let state = state.clone();
+        jobs::spawn_interval("counter snapshot", counter_snapshots, move || {
+            let state = state.clone();
+            async move { jobs::counters::snapshot(&state).await }
+        });
+    }
+
+    {
+        let state = state.clone();
+        jobs::spawn(
+            "store maintenance",
+            maintenance,
+            state.timezone,
+            move || {
+                let state = state.clone();
+                async move {
+                    jobs::maintenance::run(&state, &state.retention).await;
+                }
+            },
+        );
+    }
+
+    if let Some((interval, config)) = sla_monitor {
+        if state.linear.is_none() {
+            warn!("SLA_MONITOR_INTERVAL_SECS set without linear api access – sla monitor disabled");
+        } else {
+            let state = state.clone();
+            let config = Arc::new(config);
+            jobs::spawn_interval("sla monitor", interval, move || {
+                let state = state.clone();
+                let config = config.clone();
+                async move { jobs::sla::run(&state, &config).await }
+            });
+        }
+    }
+
+    if let Some(schedule) = digest {
+        let state = state.clone();
+        jobs::spawn("daily digest", schedule, state.timezone, move || {
+            let state = state.clone();
+            async move {
+                jobs::digest::run(&state).await;
+            }
+        });
+    }
+
+    if let Some(schedule) = weekly_summary {
+        let state = state.clone();
+        jobs::spawn("weekly summary", schedule, state.timezone, move || {
+            let state = state.clone();
+            async move { jobs::weekly::run(&state).await }
+        });
+    }
+
+    let app = router(state.clone());
 
     let addr = format!("0.0.0.0:{port}");
     info!("listening on {addr}");
@@ -328,5 +4924,585 @@ async fn main() {
         .await
         .expect("failed to bind");
 
-    axum::serve(listener, app).await.expect("server error");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .expect("server error");
+
+    lark::delivery::drain_on_shutdown(&state.delivery, drain_timeout).await;
+    jobs::counters::snapshot(&state).await;
+}
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM (what `docker stop` sends).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+    info!("shutting down, no longer accepting webhooks");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload_with_priority(priority: Option<&str>) -> LinearPayload {
+        let priority = priority.map_or(String::new(), |p| format!(r#""priority": {p},"#));
+        let json = format!(
+            r#"{{
+                "action": "update",
+                "type": "Issue",
+                "url": "https://linear.app/acme/issue/ENG-1",
+                "data": {{
+                    "id": "issue-1",
+                    "identifier": "ENG-1",
+                    "title": "Fix login",
+                    {priority}
+                    "state": {{ "name": "Todo", "type": "unstarted" }},
+                    "assignee": null
+                }}
+            }}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn issue(payload: &LinearPayload) -> &Issue {
+        match &payload.data {
+            PayloadData::Issue(issue) => issue,
+            other => panic!("expected an issue, got {other:?}"),
+        }
+    }
+
+    fn summary(payload: &LinearPayload, workspace: Option<&str>) -> IssueSummary {
+        IssueSummary::from_payload(payload, issue(payload), workspace)
+    }
+
+    fn parse(kind: &str, data: &str) -> LinearPayload {
+        let json = format!(r#"{{ "action": "create", "type": "{kind}", "data": {data} }}"#);
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn issue_payload_parses_as_issue() {
+        let payload = payload_with_priority(Some("2"));
+        assert_eq!(payload.kind, "Issue");
+        assert_eq!(issue(&payload).identifier, "ENG-1");
+    }
+
+    #[test]
+    fn comment_payload_parses_as_comment() {
+        let payload = parse(
+            "Comment",
+            r#"{ "id": "c1", "body": "Looks good", "issueId": "issue-1", "userId": "u1" }"#,
+        );
+        let PayloadData::Comment(comment) = &payload.data else {
+            panic!("expected a comment, got {:?}", payload.data);
+        };
+        assert_eq!(comment.id, "c1");
+        assert_eq!(comment.issue_id.as_deref(), Some("issue-1"));
+    }
+
+    #[test]
+    fn project_payload_parses_as_project() {
+        let payload = parse(
+            "Project",
+            r#"{ "id": "p1", "name": "Billing revamp", "state": "started" }"#,
+        );
+        assert_eq!(payload.data.describe(), "project Billing revamp");
+    }
+
+    #[test]
+    fn cycle_payload_parses_as_cycle() {
+        let payload = parse(
+            "Cycle",
+            r#"{ "id": "cy1", "number": 12, "name": null, "startsAt": "2026-10-05T00:00:00Z" }"#,
+        );
+        assert_eq!(payload.data.describe(), "cycle 12");
+    }
+
+    #[test]
+    fn unknown_kinds_parse_instead_of_failing() {
+        let payload = parse(
+            "IssueLabel",
+            r#"{ "id": "l1", "name": "bug", "color": "red" }"#,
+        );
+        assert!(matches!(payload.data, PayloadData::Unknown(_)));
+        assert_eq!(payload.data.describe(), "l1");
+    }
+
+    #[test]
+    fn parses_integer_priority() {
+        assert_eq!(issue(&payload_with_priority(Some("2"))).priority, Some(2));
+    }
+
+    #[test]
+    fn parses_float_priority() {
+        assert_eq!(issue(&payload_with_priority(Some("1.0"))).priority, Some(1));
+        assert_eq!(issue(&payload_with_priority(Some("3.4"))).priority, Some(3));
+    }
+
+    #[test]
+    fn null_and_missing_priority_mean_none() {
+        assert_eq!(issue(&payload_with_priority(Some("null"))).priority, None);
+        assert_eq!(issue(&payload_with_priority(None)).priority, None);
+        assert_eq!(EN_LABELS.priority_label(0), EN_LABELS.priorities[0]);
+        assert_eq!(priority_color(0), "blue");
+    }
+
+    #[test]
+    fn out_of_range_priority_is_clamped() {
+        assert_eq!(issue(&payload_with_priority(Some("9"))).priority, Some(4));
+        assert_eq!(issue(&payload_with_priority(Some("-1"))).priority, Some(0));
+    }
+
+    fn buttons(card: &LarkMessage) -> Vec<serde_json::Value> {
+        card.card
+            .elements
+            .iter()
+            .filter(|e| e["tag"] == "action")
+            .flat_map(|e| e["actions"].as_array().unwrap().clone())
+            .collect()
+    }
+
+    fn options() -> CardOptions {
+        CardOptions {
+            language: CardLanguage::Single(Lang::En),
+            callbacks: false,
+            transitions: false,
+            assign: false,
+            updatable: false,
+        }
+    }
+
+    #[test]
+    fn secrets_compare_equal_only_when_identical() {
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+        assert!(!constant_time_eq(b"", b"s"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn failed_actions_are_explained_in_each_language() {
+        let issue = summary(&payload_with_priority(Some("2")), None);
+        let options = CardOptions {
+            language: CardLanguage::I18n,
+            ..options()
+        };
+        let card = render_issue_card(&issue, options, Some("timed out"));
+        let notes: Vec<_> = card
+            .i18n_elements
+            .unwrap()
+            .into_iter()
+            .map(|(code, elements)| {
+                let note = elements
+                    .into_iter()
+                    .rev()
+                    .find(|e| e["tag"] == "note")
+                    .unwrap();
+                (
+                    code,
+                    note["elements"][0]["content"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("en_us", "⚠️ Could not update Linear: timed out".to_string()),
+                ("zh_cn", "⚠️ 无法更新 Linear： timed out".to_string()),
+            ]
+        );
+    }
+
+    /// Lark rejects cards whose JSON is larger than this.
+    const LARK_CARD_BYTES: usize = 30 * 1024;
+
+    #[test]
+    fn fullest_i18n_card_fits_in_a_lark_message() {
+        let mut issue = summary(&payload_with_priority(Some("1")), None);
+        issue.title = "标".repeat(limits::MAX_FIELD_CHARS);
+        issue.description = Some(truncate_chars(&"描述".repeat(1000), MAX_DESCRIPTION_CHARS));
+        issue.latest_comment = Some(CommentSnippet {
+            author: Some("评".repeat(limits::MAX_FIELD_CHARS)),
+            body: truncate_chars(&"评论".repeat(1000), MAX_COMMENT_CHARS),
+        });
+        issue.labels = (0..limits::MAX_LABELS)
+            .map(|i| format!("{i}{}", "签".repeat(limits::MAX_FIELD_CHARS)))
+            .collect();
+        issue.changes = vec![
+            Change::Status {
+                from: Some("状".repeat(limits::MAX_FIELD_CHARS)),
+            },
+            Change::Priority { from: 3 },
+            Change::Title {
+                from: "旧".repeat(limits::MAX_FIELD_CHARS),
+            },
+        ];
+        issue.state = Some("态".repeat(limits::MAX_FIELD_CHARS));
+        issue.assignee = Some("人".repeat(limits::MAX_FIELD_CHARS));
+        issue.due_date = Some("2026-10-01".into());
+        issue.estimate = Some(3.0);
+        let options = CardOptions {
+            language: CardLanguage::I18n,
+            callbacks: true,
+            transitions: true,
+            assign: true,
+            updatable: true,
+        };
+
+        let card = render_issue_card(&issue, options, Some("timed out"));
+        let size = serde_json::to_string(&card).unwrap().len();
+        assert!(size < LARK_CARD_BYTES, "card is {size} bytes");
+
+        let values: Vec<_> = card
+            .i18n_elements
+            .unwrap()
+            .into_values()
+            .flatten()
+            .collect();
+        let values: Vec<_> = values
+            .iter()
+            .filter(|e| e["tag"] == "action")
+            .flat_map(|e| e["actions"].as_array().unwrap())
+            .filter_map(|button| button.get("value").cloned())
+            .collect();
+        assert!(!values.is_empty());
+        for value in values {
+            assert_eq!(value["issue_id"], issue.id.as_str());
+            assert_eq!(value.as_object().unwrap().len(), 2, "{value}");
+        }
+    }
+
+    #[test]
+    fn missing_url_falls_back_to_the_workspace_link() {
+        let mut payload = payload_with_priority(Some("2"));
+        payload.url = None;
+        let issue = summary(&payload, Some("acme"));
+        assert_eq!(
+            issue.url.as_deref(),
+            Some("https://linear.app/acme/issue/ENG-1")
+        );
+
+        let payload = payload_with_priority(Some("2"));
+        let issue = summary(&payload, Some("other"));
+        assert_eq!(
+            issue.url.as_deref(),
+            Some("https://linear.app/acme/issue/ENG-1")
+        );
+    }
+
+    #[test]
+    fn card_without_a_url_has_no_view_button() {
+        let mut payload = payload_with_priority(Some("2"));
+        payload.url = None;
+        let issue = summary(&payload, None);
+        assert!(buttons(&build_lark_card(&issue, options())).is_empty());
+
+        let issue = summary(&payload_with_priority(Some("2")), None);
+        let buttons = buttons(&build_lark_card(&issue, options()));
+        assert_eq!(buttons[0]["url"], "https://linear.app/acme/issue/ENG-1");
+    }
+
+    fn fixture(json: &str) -> LinearPayload {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn button_actions(card: &LarkMessage) -> Vec<String> {
+        buttons(card)
+            .iter()
+            .filter_map(|b| b["value"]["action"].as_str())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn labels_due_date_and_estimate_are_shown() {
+        let payload = parse(
+            "Issue",
+            r#"{ "id": "i1", "identifier": "ENG-1", "title": "Fix login",
+                 "state": { "name": "Todo", "type": "unstarted" },
+                 "labels": [{ "name": "bug" }, { "name": "security" }],
+                 "dueDate": "2026-10-01", "estimate": 3 }"#,
+        );
+        let mut issue = summary(&payload, None);
+        let today = chrono::NaiveDate::from_ymd_opt(2026, 10, 2).unwrap();
+        assert!(issue.is_overdue_on(today));
+        assert!(!issue.is_overdue_on(today.pred_opt().unwrap()));
+
+        issue.overdue = true;
+        let card = build_lark_card(&issue, options());
+        let fields = field_texts(&card);
+        assert!(fields.contains(&"**Due:** <font color='red'>2026-10-01</font>".to_string()));
+        assert!(fields.contains(&"**Estimate:** 3".to_string()));
+        let note = card
+            .card
+            .elements
+            .iter()
+            .find(|e| e["tag"] == "note")
+            .unwrap();
+        assert_eq!(note["elements"][0]["content"], "🏷 bug  🏷 security");
+
+        issue.state_type = Some("completed".into());
+        assert!(!issue.is_overdue_on(today));
+    }
+
+    #[test]
+    fn open_issues_offer_assign_and_urgent() {
+        let options = CardOptions {
+            callbacks: true,
+            transitions: true,
+            assign: true,
+            ..options()
+        };
+        // No team id, so no transitions.
+        let mut issue = summary(&payload_with_priority(Some("2")), None);
+        assert_eq!(
+            button_actions(&build_lark_card(&issue, options)),
+            vec!["ack", "assign", "urgent"]
+        );
+
+        issue.priority = Some(1);
+        issue.assignee_open_id = Some("ou_ann".into());
+        assert_eq!(
+            button_actions(&build_lark_card(&issue, options)),
+            vec!["ack"]
+        );
+    }
+
+    fn field_texts(card: &LarkMessage) -> Vec<String> {
+        card.card
+            .elements
+            .iter()
+            .filter_map(|e| e["fields"].as_array())
+            .flatten()
+            .map(|f| f["text"]["content"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn delete_payload_renders_a_deletion_card() {
+        let payload = fixture(include_str!("../tests/fixtures/issue_delete.json"));
+        let issue = summary(&payload, Some("acme"));
+        assert_eq!(issue.action, "delete");
+        assert_eq!(issue.state, None);
+        assert_eq!(issue.priority, None);
+
+        let options = CardOptions {
+            callbacks: true,
+            transitions: true,
+            assign: true,
+            ..options()
+        };
+        let card = build_lark_card(&issue, options);
+        assert_eq!(card.card.header.template, "grey");
+        assert_eq!(card.card.header.title.content, "[Linear] Deleted: ENG-42");
+        assert_eq!(field_texts(&card), vec!["**Assignee:** Unassigned"]);
+        // Only the link is left; there is nothing to ack or move.
+        assert_eq!(buttons(&card).len(), 1);
+    }
+
+    #[test]
+    fn archive_payload_renders_an_archive_card() {
+        let payload = fixture(include_str!("../tests/fixtures/issue_archive.json"));
+        let issue = summary(&payload, None);
+        assert_eq!(issue.action, "archive");
+
+        let card = build_lark_card(&issue, options());
+        assert_eq!(card.card.header.title.content, "[Linear] Archived: ENG-17");
+        assert_eq!(field_texts(&card), vec!["**Assignee:** Ann Lee"]);
+    }
+
+    #[test]
+    fn live_issue_without_priority_still_shows_none() {
+        let issue = summary(&payload_with_priority(None), None);
+        let fields = field_texts(&build_lark_card(&issue, options()));
+        assert!(fields.contains(&"**Priority:** None".to_string()));
+    }
+
+    #[test]
+    fn oversized_content_is_capped_while_parsing() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issue_create.json")).unwrap();
+        json["data"]["description"] = "log line\n".repeat(50_000).into();
+        json["data"]["title"] = "x".repeat(5_000).into();
+        let payload: LinearPayload = serde_json::from_value(json).unwrap();
+
+        let issue = issue(&payload);
+        let description = issue.description.as_deref().unwrap();
+        assert!(description.chars().count() <= limits::MAX_TEXT_CHARS + 20);
+        assert!(description.ends_with("[truncated]"));
+        assert!(issue.title.chars().count() <= limits::MAX_FIELD_CHARS + 20);
+    }
+
+    #[test]
+    fn comment_and_update_bodies_fit_the_event_size_cap() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/comment_create.json")).unwrap();
+        json["data"]["body"] = "评".repeat(50_000).into();
+        json["data"]["issue"]["title"] = "题".repeat(5_000).into();
+        let payload: LinearPayload = serde_json::from_value(json).unwrap();
+        let PayloadData::Comment(comment) = &payload.data else {
+            panic!("expected a comment");
+        };
+        let title = &comment.issue.as_ref().unwrap().title;
+        assert!(comment.body.ends_with("[truncated]"));
+        assert!(comment.body.len() + title.len() <= limits::MAX_EVENT_BYTES);
+
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/project_status_update.json"))
+                .unwrap();
+        json["data"]["body"] = "进".repeat(50_000).into();
+        let payload: LinearPayload = serde_json::from_value(json).unwrap();
+        let PayloadData::ProjectUpdate(update) = &payload.data else {
+            panic!("expected a project update");
+        };
+        assert!(update.body.len() <= limits::MAX_EVENT_BYTES);
+    }
+
+    #[test]
+    fn drift_summaries_name_a_bounded_number_of_keys() {
+        let mut payload: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/issue_create.json")).unwrap();
+        for i in 0..(MAX_DRIFT_KEYS + 5) {
+            payload["data"][format!("n{i}")] = true.into();
+        }
+        let summary = drift_summary(&payload).unwrap();
+        assert!(summary.starts_with("Issue create payload has unknown keys: data: n0, "));
+        assert!(summary.ends_with("(+5 more)"), "{summary}");
+
+        payload["data"]["k".repeat(5_000)] = true.into();
+        let summary = drift_summary(&payload).unwrap();
+        assert!(summary.chars().count() <= limits::MAX_FIELD_CHARS + 20);
+        assert!(drift_summary(&serde_json::json!({ "type": "Issue" })).is_none());
+    }
+
+    #[test]
+    fn markdown_in_titles_does_not_leak_into_the_card() {
+        let mut issue = summary(&payload_with_priority(Some("2")), None);
+        issue.title = "**Urgent** fix for `<at id=all></at>`".into();
+        issue.assignee = Some("Ann_[ops]".into());
+        let card = build_lark_card(&issue, options());
+
+        let title = card.card.elements[0]["text"]["content"].as_str().unwrap();
+        assert_eq!(title.matches("**").count(), 2);
+        assert!(!title.contains("<at"));
+        assert_eq!(
+            field_texts(&card),
+            vec![
+                "**Status:** Todo",
+                "**Priority:** High",
+                "**Assignee:** Ann&#95;&#91;ops&#93;",
+            ]
+        );
+    }
+
+    #[test]
+    fn descriptions_cannot_mention_the_chat() {
+        let mut issue = summary(&payload_with_priority(Some("2")), None);
+        issue.set_description("**Heads up** <at id=all></at>");
+        let card = build_lark_card(&issue, options());
+
+        let description = card.card.elements[1]["text"]["content"].as_str().unwrap();
+        assert!(description.starts_with("**Heads up**"));
+        assert!(!description.contains("<at"));
+    }
+
+    #[test]
+    fn resolved_assignees_are_mentioned() {
+        let mut issue = summary(&payload_with_priority(Some("2")), None);
+        issue.assignee = Some("Ann Lee".into());
+        issue.assignee_open_id = Some("ou_ann".into());
+        let card = build_lark_card(&issue, options());
+        assert_eq!(
+            field_texts(&card).last().unwrap(),
+            "**Assignee:** <at id=ou_ann></at>"
+        );
+    }
+
+    fn changes_text(card: &LarkMessage) -> Option<String> {
+        card.card
+            .elements
+            .iter()
+            .filter_map(|e| e["text"]["content"].as_str())
+            .find(|text| text.starts_with("**Changes**"))
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn update_cards_list_the_changed_fields() {
+        let mut payload = fixture(include_str!("../tests/fixtures/issue_update.json"));
+        payload.updated_from = Some(
+            serde_json::from_str(
+                r#"{ "stateId": "s0", "priority": 3, "assigneeId": null, "title": "Login *broken*" }"#,
+            )
+            .unwrap(),
+        );
+        let mut issue = summary(&payload, None);
+        // As resolved from the team's workflow states.
+        issue.changes[0] = Change::Status {
+            from: Some("Todo".into()),
+        };
+
+        assert_eq!(
+            changes_text(&build_lark_card(&issue, options())).unwrap(),
+            "**Changes**\n\
+             Status: Todo → In Progress\n\
+             Priority: Medium → Urgent\n\
+             Assignee: Unassigned → Bob Chen\n\
+             Title: Login &#42;broken&#42; → Login fails with SSO when the session cookie expired"
+        );
+    }
+
+    #[test]
+    fn unknown_previous_values_show_as_changed() {
+        let mut payload = fixture(include_str!("../tests/fixtures/issue_update.json"));
+        payload.updated_from =
+            Some(serde_json::from_str(r#"{ "stateId": "s0", "assigneeId": "u0" }"#).unwrap());
+        let issue = summary(&payload, None);
+        assert_eq!(
+            changes_text(&build_lark_card(&issue, options())).unwrap(),
+            "**Changes**\nStatus changed → In Progress\nAssignee changed → Bob Chen"
+        );
+    }
+
+    #[test]
+    fn create_cards_have_no_changes_section() {
+        let payload = fixture(include_str!("../tests/fixtures/issue_create.json"));
+        let issue = summary(&payload, None);
+        assert!(changes_text(&build_lark_card(&issue, options())).is_none());
+    }
+
+    #[test]
+    fn notable_fields_ignore_other_edits() {
+        let from: UpdatedFrom =
+            serde_json::from_str(r#"{ "description": "old", "labelIds": [] }"#).unwrap();
+        assert!(from.notable_fields().is_empty());
+        let from: UpdatedFrom = serde_json::from_str(r#"{ "assigneeId": null }"#).unwrap();
+        assert_eq!(from.notable_fields(), vec!["assignee"]);
+    }
+
+    #[test]
+    fn previous_priority_accepts_floats() {
+        let from: UpdatedFrom = serde_json::from_str(r#"{ "priority": 3.0 }"#).unwrap();
+        assert_eq!(from.priority, Some(3));
+        let from: UpdatedFrom = serde_json::from_str(r#"{ "stateId": "s1" }"#).unwrap();
+        assert_eq!(from.priority, None);
+    }
 }
@@ -0,0 +1,229 @@
+//! Prometheus metrics, served as text at `GET /metrics`.
+//!
+//! Labelled counters and histograms kept in memory since startup; they
+//! reset with every restart, unlike the delivery totals in `/health` (see
+//! [`crate::jobs::counters`]). Every
+//! metric is declared in [`METRICS`] with its type and help text; the
+//! request layer in `main.rs` records HTTP traffic for every route, and the
+//! handler adds the delivery queue's counters and the payload caps when
+//! scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+pub const WEBHOOKS: &str = "linear_lark_webhooks_total";
+pub const SIGNATURE_FAILURES: &str = "linear_lark_signature_failures_total";
+pub const LARK_SENDS: &str = "linear_lark_lark_sends_total";
+pub const DELIVERY_LATENCY: &str = "linear_lark_delivery_latency_seconds";
+pub const HTTP_REQUESTS: &str = "linear_lark_http_requests_total";
+pub const HTTP_DURATION: &str = "linear_lark_http_request_duration_seconds";
+pub const QUEUED: &str = "linear_lark_delivery_queued";
+pub const SENT: &str = "linear_lark_delivery_sent_total";
+pub const RETRIED: &str = "linear_lark_delivery_retried_total";
+pub const DROPPED: &str = "linear_lark_delivery_dropped_total";
+pub const DEAD_LETTERS: &str = "linear_lark_dead_letters";
+pub const CAPS: &str = "linear_lark_payload_caps_total";
+pub const ENRICHMENT: &str = "linear_lark_enrichment_total";
+pub const MAINTENANCE_DELETED: &str = "linear_lark_maintenance_rows_deleted_total";
+pub const PAYLOAD_DRIFT: &str = "linear_lark_payload_drift_total";
+
+/// (name, type, help), in exposition order.
+const METRICS: &[(&str, &str, &str)] = &[
+    (
+        WEBHOOKS,
+        "counter",
+        "Signed Linear webhooks parsed, by payload type and action.",
+    ),
+    (
+        SIGNATURE_FAILURES,
+        "counter",
+        "Linear webhooks rejected for a missing or wrong signature.",
+    ),
+    (
+        LARK_SENDS,
+        "counter",
+        "Posts to Lark, by HTTP status (`api` for bot messages) and Lark code (0 on success).",
+    ),
+    (
+        DELIVERY_LATENCY,
+        "histogram",
+        "Time from receiving a webhook to Lark accepting its card.",
+    ),
+    (
+        HTTP_REQUESTS,
+        "counter",
+        "HTTP requests served, by method, route and status.",
+    ),
+    (
+        HTTP_DURATION,
+        "histogram",
+        "Time to answer HTTP requests, by route.",
+    ),
+    (QUEUED, "gauge", "Cards queued or in flight."),
+    (SENT, "counter", "Queued cards delivered."),
+    (RETRIED, "counter", "Retries of queued cards."),
+    (DROPPED, "counter", "Queued cards given up on."),
+    (DEAD_LETTERS, "gauge", "Dead letters waiting for replay."),
+    (CAPS, "counter", "Payload values truncated, by cap."),
+    (
+        ENRICHMENT,
+        "counter",
+        "Card enrichment lookups, by kind and outcome (hit, miss or timeout).",
+    ),
+    (
+        MAINTENANCE_DELETED,
+        "counter",
+        "Rows store maintenance deleted past retention, by table.",
+    ),
+    (
+        PAYLOAD_DRIFT,
+        "counter",
+        "Payloads with keys outside the known shape (PARSE_MODE=strict), by payload type.",
+    ),
+];
+
+/// Upper bounds in seconds; both histograms span a fast 200 to a retried
+/// send.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Default)]
+pub struct Metrics {
+    values: Mutex<BTreeMap<(&'static str, Labels), u64>>,
+    histograms: Mutex<BTreeMap<(&'static str, Labels), Histogram>>,
+}
+
+struct Histogram {
+    /// Per bucket of [`BUCKETS`], not cumulative.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn inc(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .entry(key(name, labels))
+            .or_default() += value;
+    }
+
+    /// Replaces a value kept elsewhere, such as the delivery counters.
+    pub fn set(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        self.values.lock().unwrap().insert(key(name, labels), value);
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], seconds: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms
+            .entry(key(name, labels))
+            .or_insert_with(|| Histogram {
+                counts: vec![0; BUCKETS.len()],
+                sum: 0.0,
+                count: 0,
+            });
+        if let Some(bucket) = BUCKETS.iter().position(|le| seconds <= *le) {
+            histogram.counts[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    /// The text exposition format.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let histograms = self.histograms.lock().unwrap();
+        let mut out = String::new();
+        for (name, kind, help) in METRICS {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+            for ((_, labels), value) in values.iter().filter(|((n, _), _)| n == name) {
+                let _ = writeln!(out, "{name}{} {value}", format_labels(labels, None));
+            }
+            for ((_, labels), histogram) in histograms.iter().filter(|((n, _), _)| n == name) {
+                let mut cumulative = 0;
+                for (le, count) in BUCKETS.iter().zip(&histogram.counts) {
+                    cumulative += count;
+                    let le = le.to_string();
+                    let labels = format_labels(labels, Some(&le));
+                    let _ = writeln!(out, "{name}_bucket{labels} {cumulative}");
+                }
+                let inf = format_labels(labels, Some("+Inf"));
+                let labels = format_labels(labels, None);
+                let _ = writeln!(out, "{name}_bucket{inf} {}", histogram.count);
+                let _ = writeln!(out, "{name}_sum{labels} {}", histogram.sum);
+                let _ = writeln!(out, "{name}_count{labels} {}", histogram.count);
+            }
+        }
+        out
+    }
+}
+
+fn key(name: &'static str, labels: &[(&'static str, &str)]) -> (&'static str, Labels) {
+    let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    (name, labels)
+}
+
+/// `{a="1",b="2"}`, with `le` appended for histogram buckets; empty when
+/// there are no labels at all.
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{le}\""));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_histograms() {
+        let metrics = Metrics::default();
+        metrics.inc(WEBHOOKS, &[("type", "Issue"), ("action", "create")]);
+        metrics.inc(WEBHOOKS, &[("type", "Issue"), ("action", "create")]);
+        metrics.inc(SIGNATURE_FAILURES, &[]);
+        metrics.observe(DELIVERY_LATENCY, &[], 0.3);
+        metrics.observe(DELIVERY_LATENCY, &[], 120.0);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE linear_lark_webhooks_total counter\n"));
+        assert!(text.contains("linear_lark_webhooks_total{type=\"Issue\",action=\"create\"} 2\n"));
+        assert!(text.contains("linear_lark_signature_failures_total 1\n"));
+        assert!(text.contains("linear_lark_delivery_latency_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("linear_lark_delivery_latency_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("linear_lark_delivery_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("linear_lark_delivery_latency_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let metrics = Metrics::default();
+        metrics.inc(HTTP_REQUESTS, &[("route", "a\"b")]);
+        assert!(metrics.render().contains("{route=\"a\\\"b\"} 1"));
+    }
+}
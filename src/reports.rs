@@ -0,0 +1,306 @@
+//! Statistics over the event log, kept free of I/O so they can be checked
+//! against fixture events.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{Days, NaiveDate, TimeDelta, Weekday};
+
+use crate::storage::Event;
+
+/// How many assignees the weekly summary ranks.
+const TOP_ASSIGNEES: usize = 3;
+
+/// Team label for events whose payload carried no team.
+pub const NO_TEAM: &str = "No team";
+
+/// Monday to Monday (exclusive) of the week before the one `today` is in.
+pub fn last_week(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let this_monday = today.week(Weekday::Mon).first_day();
+    (this_monday - Days::new(7), this_monday)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WeeklySummary {
+    pub created: usize,
+    pub completed: usize,
+    /// Created minus completed; positive means the backlog grew.
+    pub backlog_change: i64,
+    /// `createdAt` to `completedAt` of the completed issues.
+    pub median_cycle_time: Option<TimeDelta>,
+    /// Most completions first, ties by name.
+    pub top_assignees: Vec<(String, usize)>,
+    /// Issues whose priority was raised at least once.
+    pub escalated: usize,
+}
+
+/// One summary per team key over `events`, which should span a week.
+/// Every count is of distinct issues.
+pub fn weekly_summaries(events: &[Event]) -> BTreeMap<String, WeeklySummary> {
+    let mut by_team: BTreeMap<&str, Vec<&Event>> = BTreeMap::new();
+    // Comments are filed under their issue, but are not issue activity.
+    let issue_events = events
+        .iter()
+        .filter(|e| e.kind == "Issue" && e.disposition != "ignored");
+    for event in issue_events {
+        by_team
+            .entry(event.team_key.as_deref().unwrap_or(NO_TEAM))
+            .or_default()
+            .push(event);
+    }
+
+    by_team
+        .into_iter()
+        .map(|(team, events)| (team.to_string(), weekly_summary(&events)))
+        .collect()
+}
+
+fn weekly_summary(events: &[&Event]) -> WeeklySummary {
+    let created: HashSet<&str> = events
+        .iter()
+        .filter(|e| e.action == "create")
+        .map(|e| e.issue_id.as_str())
+        .collect();
+    let escalated: HashSet<&str> = events
+        .iter()
+        .filter(|e| e.escalated())
+        .map(|e| e.issue_id.as_str())
+        .collect();
+
+    // Last completion per issue, in case one was reopened and done again.
+    let mut completed: HashMap<&str, &Event> = HashMap::new();
+    for event in events.iter().filter(|e| e.completed()) {
+        completed.insert(&event.issue_id, event);
+    }
+
+    let cycle_times = completed
+        .values()
+        .filter_map(|e| {
+            let done = e.completed_at.unwrap_or(e.received_at);
+            e.issue_created_at.map(|created| done - created)
+        })
+        .collect();
+
+    let mut completions: HashMap<&str, usize> = HashMap::new();
+    for event in completed.values() {
+        if let Some(assignee) = &event.assignee {
+            *completions.entry(assignee).or_default() += 1;
+        }
+    }
+    let mut top_assignees: Vec<(String, usize)> = completions
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    top_assignees.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_assignees.truncate(TOP_ASSIGNEES);
+
+    WeeklySummary {
+        created: created.len(),
+        completed: completed.len(),
+        backlog_change: created.len() as i64 - completed.len() as i64,
+        median_cycle_time: median(cycle_times),
+        top_assignees,
+        escalated: escalated.len(),
+    }
+}
+
+fn median(mut values: Vec<TimeDelta>) -> Option<TimeDelta> {
+    values.sort();
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(values[mid]),
+        _ => Some((values[mid - 1] + values[mid]) / 2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    fn at(ts: &str) -> DateTime<Utc> {
+        ts.parse().unwrap()
+    }
+
+    fn event(issue: &str, action: &str) -> Event {
+        Event {
+            received_at: at("2026-10-06T10:00:00Z"),
+            kind: "Issue".into(),
+            action: action.into(),
+            issue_id: issue.into(),
+            identifier: issue.to_uppercase(),
+            team_key: Some("ENG".into()),
+            title: format!("{issue} title"),
+            state: "Todo".into(),
+            state_type: Some("unstarted".into()),
+            priority: 3,
+            previous_priority: None,
+            state_changed: false,
+            assignee: None,
+            issue_created_at: None,
+            completed_at: None,
+            disposition: "sent".into(),
+            target: Some("webhook".into()),
+            outcome: None,
+        }
+    }
+
+    fn completion(issue: &str, assignee: &str, created: &str, completed: &str) -> Event {
+        Event {
+            state: "Done".into(),
+            state_type: Some("completed".into()),
+            state_changed: true,
+            assignee: Some(assignee.into()),
+            issue_created_at: Some(at(created)),
+            completed_at: Some(at(completed)),
+            ..event(issue, "update")
+        }
+    }
+
+    fn escalation(issue: &str, from: u8, to: u8) -> Event {
+        Event {
+            priority: to,
+            previous_priority: Some(from),
+            ..event(issue, "update")
+        }
+    }
+
+    #[test]
+    fn last_week_runs_monday_to_monday() {
+        let monday = NaiveDate::from_ymd_opt(2026, 10, 12).unwrap();
+        let expected = (
+            NaiveDate::from_ymd_opt(2026, 10, 5).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 10, 12).unwrap(),
+        );
+        assert_eq!(last_week(monday), expected);
+        // Mid-week runs still report the previous full week.
+        assert_eq!(
+            last_week(NaiveDate::from_ymd_opt(2026, 10, 15).unwrap()),
+            expected
+        );
+    }
+
+    #[test]
+    fn counts_distinct_issues_and_backlog_change() {
+        let events = [
+            event("a", "create"),
+            event("b", "create"),
+            event("c", "create"),
+            event("a", "update"),
+            completion("a", "Ann", "2026-10-06T00:00:00Z", "2026-10-07T00:00:00Z"),
+            completion("d", "Bob", "2026-10-01T00:00:00Z", "2026-10-08T00:00:00Z"),
+        ];
+        let summary = &weekly_summaries(&events)["ENG"];
+        assert_eq!(summary.created, 3);
+        assert_eq!(summary.completed, 2);
+        assert_eq!(summary.backlog_change, 1);
+    }
+
+    #[test]
+    fn median_cycle_time_of_odd_and_even_counts() {
+        let mut events = vec![
+            completion("a", "Ann", "2026-10-06T00:00:00Z", "2026-10-06T02:00:00Z"),
+            completion("b", "Ann", "2026-10-06T00:00:00Z", "2026-10-06T10:00:00Z"),
+            completion("c", "Ann", "2026-10-06T00:00:00Z", "2026-10-07T00:00:00Z"),
+        ];
+        let odd = weekly_summaries(&events)["ENG"].median_cycle_time;
+        assert_eq!(odd, Some(TimeDelta::hours(10)));
+
+        events.push(completion(
+            "d",
+            "Ann",
+            "2026-10-06T00:00:00Z",
+            "2026-10-08T00:00:00Z",
+        ));
+        let even = weekly_summaries(&events)["ENG"].median_cycle_time;
+        assert_eq!(even, Some(TimeDelta::hours(17)));
+    }
+
+    #[test]
+    fn reopened_issues_count_once_with_their_last_completion() {
+        let events = [
+            completion("a", "Ann", "2026-10-06T00:00:00Z", "2026-10-06T01:00:00Z"),
+            completion("a", "Bob", "2026-10-06T00:00:00Z", "2026-10-06T05:00:00Z"),
+        ];
+        let summary = &weekly_summaries(&events)["ENG"];
+        assert_eq!(summary.completed, 1);
+        assert_eq!(summary.median_cycle_time, Some(TimeDelta::hours(5)));
+        assert_eq!(summary.top_assignees, vec![("Bob".to_string(), 1)]);
+    }
+
+    #[test]
+    fn no_cycle_time_without_created_at() {
+        let events = [Event {
+            issue_created_at: None,
+            ..completion("a", "Ann", "2026-10-06T00:00:00Z", "2026-10-06T01:00:00Z")
+        }];
+        let summary = &weekly_summaries(&events)["ENG"];
+        assert_eq!(summary.completed, 1);
+        assert_eq!(summary.median_cycle_time, None);
+    }
+
+    #[test]
+    fn top_assignees_ranked_by_completions_then_name() {
+        let done =
+            |issue, who| completion(issue, who, "2026-10-06T00:00:00Z", "2026-10-07T00:00:00Z");
+        let events = [
+            done("a", "Cid"),
+            done("b", "Ann"),
+            done("c", "Ann"),
+            done("d", "Bob"),
+            done("e", "Dee"),
+            done("f", "Dee"),
+        ];
+        let summary = &weekly_summaries(&events)["ENG"];
+        assert_eq!(
+            summary.top_assignees,
+            vec![
+                ("Ann".to_string(), 2),
+                ("Dee".to_string(), 2),
+                ("Bob".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn escalations_count_upward_priority_changes_only() {
+        let events = [
+            escalation("a", 3, 1),
+            escalation("a", 2, 1),
+            escalation("b", 0, 4),
+            escalation("c", 2, 3),
+            escalation("d", 1, 0),
+        ];
+        assert_eq!(weekly_summaries(&events)["ENG"].escalated, 2);
+    }
+
+    #[test]
+    fn splits_by_team_and_skips_ignored_events_and_comments() {
+        let events = [
+            event("a", "create"),
+            Event {
+                team_key: Some("OPS".into()),
+                ..event("b", "create")
+            },
+            Event {
+                team_key: None,
+                ..event("c", "create")
+            },
+            Event {
+                disposition: "ignored".into(),
+                ..event("d", "create")
+            },
+            Event {
+                kind: "Comment".into(),
+                ..event("e", "create")
+            },
+        ];
+        let summaries = weekly_summaries(&events);
+        assert_eq!(
+            summaries.keys().collect::<Vec<_>>(),
+            vec!["ENG", NO_TEAM, "OPS"]
+        );
+        assert_eq!(summaries["ENG"].created, 1);
+    }
+}
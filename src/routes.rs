@@ -0,0 +1,260 @@
+//! Per-team and per-project Lark destinations from `ROUTES_CONFIG`.
+//!
+//! ```toml
+//! [teams]
+//! ENG = "https://open.larksuite.com/open-apis/bot/v2/hook/..."
+//! OPS = ["https://...", "https://..."]
+//!
+//! [projects]
+//! "SSO hardening" = "https://..."
+//!
+//! [labels]
+//! security = "https://..."
+//! ```
+//!
+//! A project rule wins over label rules, which win over a team rule, and
+//! `LARK_WEBHOOK_URL` catches whatever matches none. Teams match by key or
+//! name, projects by name or id, labels by name. An issue with several
+//! routed labels goes to all of their webhooks.
+//!
+//! A destination may also be a Lark chat id (`oc_…`), which is posted to as
+//! the bot of the Lark app instead; see `LARK_DELIVERY`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::lark::app::is_chat_id;
+
+#[derive(Debug, Default)]
+pub struct Routes {
+    teams: HashMap<String, Vec<String>>,
+    projects: HashMap<String, Vec<String>>,
+    labels: HashMap<String, Vec<String>>,
+}
+
+/// What an event can be routed by, each by every name it goes by.
+#[derive(Debug, Default)]
+pub struct RouteKeys<'a> {
+    pub teams: Vec<&'a str>,
+    pub projects: Vec<&'a str>,
+    pub labels: Vec<&'a str>,
+}
+
+/// Where one card goes. `label` names the rule in logs and the event log,
+/// since the URL itself is a credential.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Destination {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutesFile {
+    #[serde(default)]
+    teams: HashMap<String, Urls>,
+    #[serde(default)]
+    projects: HashMap<String, Urls>,
+    #[serde(default)]
+    labels: HashMap<String, Urls>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Urls {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Routes {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let file: RoutesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        Ok(Self {
+            teams: validate("teams", file.teams)?,
+            projects: validate("projects", file.projects)?,
+            labels: validate("labels", file.labels)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.teams.len() + self.projects.len() + self.labels.len()
+    }
+
+    /// Destinations for an event, falling back to `default`. Empty when no
+    /// rule matches and there is no default.
+    pub fn resolve(&self, keys: &RouteKeys, default: Option<&str>) -> Vec<Destination> {
+        let rule = |rules: &HashMap<String, Vec<String>>, kind: &str, names: &[&str]| {
+            names.iter().find_map(|name| {
+                let urls = rules.get(*name)?;
+                Some(destinations(&format!("{kind}:{name}"), urls))
+            })
+        };
+
+        if let Some(destinations) = rule(&self.projects, "project", &keys.projects) {
+            return destinations;
+        }
+
+        let mut labelled: Vec<Destination> = Vec::new();
+        for label in &keys.labels {
+            for destination in self
+                .labels
+                .get(*label)
+                .map(|urls| destinations(&format!("label:{label}"), urls))
+                .unwrap_or_default()
+            {
+                if !labelled.iter().any(|d| d.url == destination.url) {
+                    labelled.push(destination);
+                }
+            }
+        }
+        if !labelled.is_empty() {
+            return labelled;
+        }
+
+        rule(&self.teams, "team", &keys.teams).unwrap_or_else(|| {
+            default
+                .into_iter()
+                .map(|url| Destination {
+                    label: "webhook".into(),
+                    url: url.to_string(),
+                })
+                .collect()
+        })
+    }
+}
+
+fn destinations(label: &str, urls: &[String]) -> Vec<Destination> {
+    urls.iter()
+        .map(|url| Destination {
+            label: label.to_string(),
+            url: url.clone(),
+        })
+        .collect()
+}
+
+fn validate(
+    section: &str,
+    rules: HashMap<String, Urls>,
+) -> Result<HashMap<String, Vec<String>>, String> {
+    rules
+        .into_iter()
+        .map(|(name, urls)| {
+            let urls = match urls {
+                Urls::One(url) => vec![url],
+                Urls::Many(urls) => urls,
+            };
+            if urls.is_empty() {
+                return Err(format!("{section}.{name:?} has no webhook urls"));
+            }
+            for url in urls.iter().filter(|url| !is_chat_id(url)) {
+                let scheme = Url::parse(url).map(|u| u.scheme().to_string());
+                if !matches!(scheme.as_deref(), Ok("http" | "https")) {
+                    return Err(format!(
+                        "{section}.{name:?}: {url:?} is not an http(s) url or chat id"
+                    ));
+                }
+            }
+            Ok((name, urls))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [teams]
+        ENG = "https://lark.test/eng"
+        Operations = ["https://lark.test/ops-1", "https://lark.test/ops-2"]
+
+        [projects]
+        "SSO hardening" = "https://lark.test/sso"
+
+        [labels]
+        security = "https://lark.test/security"
+        incident = ["https://lark.test/security", "https://lark.test/oncall"]
+    "#;
+
+    fn keys<'a>(teams: &[&'a str], projects: &[&'a str], labels: &[&'a str]) -> RouteKeys<'a> {
+        RouteKeys {
+            teams: teams.to_vec(),
+            projects: projects.to_vec(),
+            labels: labels.to_vec(),
+        }
+    }
+
+    fn urls(destinations: Vec<Destination>) -> Vec<String> {
+        destinations.into_iter().map(|d| d.url).collect()
+    }
+
+    #[test]
+    fn project_rules_win_over_team_rules() {
+        let routes = Routes::parse(CONFIG).unwrap();
+        let destinations = routes.resolve(&keys(&["ENG"], &["SSO hardening"], &["security"]), None);
+        assert_eq!(
+            destinations,
+            vec![Destination {
+                label: "project:SSO hardening".into(),
+                url: "https://lark.test/sso".into(),
+            }]
+        );
+        assert_eq!(
+            urls(routes.resolve(&keys(&["ENG"], &["Other"], &[]), None)),
+            vec!["https://lark.test/eng"]
+        );
+    }
+
+    #[test]
+    fn teams_match_by_key_or_name_and_fan_out() {
+        let routes = Routes::parse(CONFIG).unwrap();
+        assert_eq!(
+            urls(routes.resolve(&keys(&["OPS", "Operations"], &[], &[]), None)),
+            vec!["https://lark.test/ops-1", "https://lark.test/ops-2"]
+        );
+    }
+
+    #[test]
+    fn unmatched_events_use_the_default_or_nothing() {
+        let routes = Routes::parse(CONFIG).unwrap();
+        assert_eq!(
+            urls(routes.resolve(&keys(&["DES"], &[], &[]), Some("https://lark.test/all"))),
+            vec!["https://lark.test/all"]
+        );
+        assert!(routes.resolve(&keys(&["DES"], &[], &[]), None).is_empty());
+    }
+
+    #[test]
+    fn label_rules_win_over_team_rules_and_merge() {
+        let routes = Routes::parse(CONFIG).unwrap();
+        assert_eq!(
+            urls(routes.resolve(&keys(&["ENG"], &[], &["ux", "incident", "security"]), None)),
+            vec!["https://lark.test/security", "https://lark.test/oncall"]
+        );
+    }
+
+    #[test]
+    fn chat_ids_are_destinations_too() {
+        let routes =
+            Routes::parse("[teams]\nENG = [\"oc_eng\", \"https://lark.test/eng\"]").unwrap();
+        assert_eq!(
+            urls(routes.resolve(&keys(&["ENG"], &[], &[]), None)),
+            vec!["oc_eng", "https://lark.test/eng"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert!(Routes::parse("[teams]\nENG = []").is_err());
+        assert!(Routes::parse("[teams]\nENG = \"not a url\"").is_err());
+        assert!(Routes::parse("[team]\nENG = \"https://lark.test\"").is_err());
+    }
+}
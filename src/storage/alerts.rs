@@ -0,0 +1,81 @@
+//! Which SLA alerts already went out, so the monitor only reports news.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use super::{Storage, StorageError};
+
+/// The last alert sent for an issue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaMarker {
+    /// The breach time alerted on; a reset SLA gets a new one.
+    pub breaches_at: DateTime<Utc>,
+    /// Whether the alert was for an actual breach rather than a warning.
+    pub breached: bool,
+    pub alerted_at: DateTime<Utc>,
+}
+
+pub struct Alerts<'a>(pub(super) &'a Storage);
+
+impl Alerts<'_> {
+    pub async fn sla_markers(&self) -> Result<HashMap<String, SlaMarker>, StorageError> {
+        self.0
+            .call(|conn| {
+                let markers = conn
+                    .prepare("SELECT issue_id, breaches_at, breached, alerted_at FROM sla_alerts")?
+                    .query_map([], |row| {
+                        Ok((
+                            row.get(0)?,
+                            SlaMarker {
+                                breaches_at: row.get(1)?,
+                                breached: row.get(2)?,
+                                alerted_at: row.get(3)?,
+                            },
+                        ))
+                    })?
+                    .collect::<Result<_, _>>()?;
+                Ok(markers)
+            })
+            .await
+    }
+
+    pub async fn mark_sla(&self, issue_id: &str, marker: SlaMarker) -> Result<(), StorageError> {
+        let issue_id = issue_id.to_string();
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO sla_alerts (issue_id, breaches_at, breached, alerted_at)
+                     VALUES (?1, ?2, ?3, ?4)
+                     ON CONFLICT (issue_id) DO UPDATE SET breaches_at = excluded.breaches_at,
+                         breached = excluded.breached, alerted_at = excluded.alerted_at",
+                    (
+                        issue_id,
+                        marker.breaches_at,
+                        marker.breached,
+                        marker.alerted_at,
+                    ),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Drops the markers of every issue not in `watched`.
+    pub async fn forget_sla_except(&self, watched: HashSet<String>) -> Result<(), StorageError> {
+        self.0
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let known: Vec<String> = tx
+                    .prepare("SELECT issue_id FROM sla_alerts")?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?;
+                for issue_id in known.iter().filter(|id| !watched.contains(*id)) {
+                    tx.execute("DELETE FROM sla_alerts WHERE issue_id = ?1", [issue_id])?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+}
@@ -0,0 +1,154 @@
+//! Cards the delivery worker gave up on, kept for replay.
+
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+
+use super::{Storage, StorageError};
+
+/// One undelivered card. The webhook URL is kept for replay but never
+/// listed, since it is a credential.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub failed_at: DateTime<Utc>,
+    /// The issue or project the card is about.
+    pub identifier: String,
+    /// The route it was for, e.g. `team:ENG`.
+    pub label: String,
+    #[serde(skip)]
+    pub url: String,
+    /// The message as last posted.
+    #[serde(skip)]
+    pub message: serde_json::Value,
+    pub attempts: u32,
+    pub error: String,
+}
+
+pub struct DeadLetters<'a>(pub(super) &'a Storage);
+
+impl DeadLetters<'_> {
+    /// Stores `letter`; its `id` is ignored and the new one returned.
+    pub async fn add(&self, letter: DeadLetter) -> Result<i64, StorageError> {
+        let message = serde_json::to_string(&letter.message)?;
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO dead_letters
+                         (failed_at, identifier, label, url, message, attempts, error)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    (
+                        letter.failed_at,
+                        letter.identifier,
+                        letter.label,
+                        letter.url,
+                        message,
+                        letter.attempts,
+                        letter.error,
+                    ),
+                )?;
+                Ok(conn.last_insert_rowid())
+            })
+            .await
+    }
+
+    /// Every dead letter, oldest first.
+    pub async fn list(&self) -> Result<Vec<DeadLetter>, StorageError> {
+        self.0
+            .call(|conn| {
+                let rows: Vec<(DeadLetter, String)> = conn
+                    .prepare(&format!("SELECT {COLUMNS} FROM dead_letters ORDER BY id"))?
+                    .query_map([], from_row)?
+                    .collect::<Result<_, _>>()?;
+                rows.into_iter().map(with_message).collect()
+            })
+            .await
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<DeadLetter>, StorageError> {
+        self.0
+            .call(move |conn| {
+                let row = conn
+                    .query_row(
+                        &format!("SELECT {COLUMNS} FROM dead_letters WHERE id = ?1"),
+                        [id],
+                        from_row,
+                    )
+                    .optional()?;
+                row.map(with_message).transpose()
+            })
+            .await
+    }
+
+    /// Returns whether there was such a letter.
+    pub async fn remove(&self, id: i64) -> Result<bool, StorageError> {
+        self.0
+            .call(move |conn| Ok(conn.execute("DELETE FROM dead_letters WHERE id = ?1", [id])? > 0))
+            .await
+    }
+
+    pub async fn count(&self) -> Result<u64, StorageError> {
+        self.0
+            .call(|conn| {
+                Ok(conn.query_row("SELECT COUNT(*) FROM dead_letters", [], |row| row.get(0))?)
+            })
+            .await
+    }
+}
+
+const COLUMNS: &str = "id, failed_at, identifier, label, url, message, attempts, error";
+
+/// A row of [`COLUMNS`], with the message still as text.
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<(DeadLetter, String)> {
+    Ok((
+        DeadLetter {
+            id: row.get(0)?,
+            failed_at: row.get(1)?,
+            identifier: row.get(2)?,
+            label: row.get(3)?,
+            url: row.get(4)?,
+            message: serde_json::Value::Null,
+            attempts: row.get(6)?,
+            error: row.get(7)?,
+        },
+        row.get(5)?,
+    ))
+}
+
+fn with_message((mut letter, message): (DeadLetter, String)) -> Result<DeadLetter, StorageError> {
+    letter.message = serde_json::from_str(&message)?;
+    Ok(letter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn keeps_letters_until_removed() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let letter = DeadLetter {
+            id: 0,
+            failed_at: Utc::now(),
+            identifier: "ENG-1".into(),
+            label: "team:ENG".into(),
+            url: "https://lark.test/eng".into(),
+            message: serde_json::json!({ "msg_type": "interactive" }),
+            attempts: 5,
+            error: "rate limited".into(),
+        };
+        let id = storage.dead_letters().add(letter).await.unwrap();
+
+        let stored = storage.dead_letters().get(id).await.unwrap().unwrap();
+        assert_eq!(stored.url, "https://lark.test/eng");
+        assert_eq!(stored.message["msg_type"], "interactive");
+        assert_eq!(storage.dead_letters().count().await.unwrap(), 1);
+        assert_eq!(storage.dead_letters().list().await.unwrap()[0].id, id);
+
+        assert!(storage.dead_letters().remove(id).await.unwrap());
+        assert!(!storage.dead_letters().remove(id).await.unwrap());
+        assert!(storage.dead_letters().get(id).await.unwrap().is_none());
+    }
+}
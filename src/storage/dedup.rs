@@ -0,0 +1,91 @@
+//! Keys of deliveries that were already handled.
+
+use chrono::Utc;
+
+use super::{Storage, StorageError};
+
+pub struct Dedup<'a>(pub(super) &'a Storage);
+
+impl Dedup<'_> {
+    /// Remembers `key`, returning `false` when it was seen before.
+    pub async fn first_seen(&self, key: &str) -> Result<bool, StorageError> {
+        let key = key.to_string();
+        self.0
+            .call(move |conn| {
+                let inserted = conn.execute(
+                    "INSERT OR IGNORE INTO dedup (key, seen_at) VALUES (?1, ?2)",
+                    (key, Utc::now()),
+                )?;
+                Ok(inserted == 1)
+            })
+            .await
+    }
+
+    /// Forgets `key`, so its next delivery counts as new.
+    pub async fn forget(&self, key: &str) -> Result<(), StorageError> {
+        let key = key.to_string();
+        self.0
+            .call(move |conn| {
+                conn.execute("DELETE FROM dedup WHERE key = ?1", [key])?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn seen_keys_survive_a_restart() {
+        let db = TempDb::new();
+
+        let storage = Storage::open(&db.0).unwrap();
+        assert!(storage.dedup().first_seen("delivery-1").await.unwrap());
+        assert!(!storage.dedup().first_seen("delivery-1").await.unwrap());
+        drop(storage);
+
+        let storage = Storage::open(&db.0).unwrap();
+        assert!(!storage.dedup().first_seen("delivery-1").await.unwrap());
+        assert!(storage.dedup().first_seen("delivery-2").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn forgotten_keys_are_new_again() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        assert!(storage.dedup().first_seen("delivery-1").await.unwrap());
+        storage.dedup().forget("delivery-1").await.unwrap();
+        assert!(storage.dedup().first_seen("delivery-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn maintenance_forgets_expired_keys_only() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        storage.dedup().first_seen("fresh").await.unwrap();
+        storage
+            .call(|conn| {
+                conn.execute(
+                    "INSERT INTO dedup (key, seen_at) VALUES ('stale', ?1)",
+                    [Utc::now() - chrono::TimeDelta::days(2)],
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let retention = crate::storage::Retention {
+            events: std::time::Duration::from_secs(90 * 24 * 60 * 60),
+            dedup: std::time::Duration::from_secs(24 * 60 * 60),
+            dead_letters: std::time::Duration::from_secs(30 * 24 * 60 * 60),
+            mappings: std::time::Duration::from_secs(90 * 24 * 60 * 60),
+        };
+        let report = storage.maintain(&retention).await.unwrap();
+        assert_eq!(report.dedup_deleted, 1);
+        assert!(storage.dedup().first_seen("stale").await.unwrap());
+        assert!(!storage.dedup().first_seen("fresh").await.unwrap());
+    }
+}
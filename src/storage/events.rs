@@ -0,0 +1,255 @@
+//! Log of every webhook the bridge processed and what became of it.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, ToSql, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+
+use super::{Storage, StorageError};
+
+const COLUMNS: &str = "received_at, kind, action, issue_id, identifier, team_key, title, state,
+    state_type, priority, previous_priority, state_changed, assignee, issue_created_at, completed_at, disposition, target, outcome";
+
+/// One processed webhook. Serialized only while its card is held, see
+/// [`super::HeldCards`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Event {
+    pub received_at: DateTime<Utc>,
+    /// Linear's payload `type`, e.g. `Issue`.
+    pub kind: String,
+    pub action: String,
+    pub issue_id: String,
+    pub identifier: String,
+    pub team_key: Option<String>,
+    pub title: String,
+    pub state: String,
+    pub state_type: Option<String>,
+    pub priority: u8,
+    /// Set when the update changed the priority.
+    pub previous_priority: Option<u8>,
+    pub state_changed: bool,
+    pub assignee: Option<String>,
+    /// The issue's own `createdAt` / `completedAt`, as of this event.
+    pub issue_created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// `sent`, `failed`, `ignored`, or `collapsed` when a later update of
+    /// the issue was sent in its place.
+    pub disposition: String,
+    /// Where the notification went, when one was attempted.
+    pub target: Option<String>,
+    /// Error detail for failed sends.
+    pub outcome: Option<String>,
+}
+
+impl Event {
+    /// Whether this update moved the issue to a more urgent priority.
+    /// Linear counts 1 (urgent) to 4 (low), with 0 meaning none.
+    pub fn escalated(&self) -> bool {
+        let rank = |priority: u8| if priority == 0 { u8::MAX } else { priority };
+        self.previous_priority
+            .is_some_and(|previous| rank(self.priority) < rank(previous))
+    }
+
+    pub fn completed(&self) -> bool {
+        self.state_changed && self.state_type.as_deref() == Some("completed")
+    }
+
+    /// Reads the [`COLUMNS`] starting at column `first`.
+    fn from_row(row: &Row, first: usize) -> rusqlite::Result<Self> {
+        Ok(Self {
+            received_at: row.get(first)?,
+            kind: row.get(first + 1)?,
+            action: row.get(first + 2)?,
+            issue_id: row.get(first + 3)?,
+            identifier: row.get(first + 4)?,
+            team_key: row.get(first + 5)?,
+            title: row.get(first + 6)?,
+            state: row.get(first + 7)?,
+            state_type: row.get(first + 8)?,
+            priority: row.get(first + 9)?,
+            previous_priority: row.get(first + 10)?,
+            state_changed: row.get(first + 11)?,
+            assignee: row.get(first + 12)?,
+            issue_created_at: row.get(first + 13)?,
+            completed_at: row.get(first + 14)?,
+            disposition: row.get(first + 15)?,
+            target: row.get(first + 16)?,
+            outcome: row.get(first + 17)?,
+        })
+    }
+}
+
+/// Selects events for [`Events::history`]. Unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct HistoryFilter {
+    pub identifier: Option<String>,
+    pub team_key: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only events after this id, for paging.
+    pub after_id: Option<i64>,
+    pub limit: usize,
+}
+
+pub struct Events<'a>(pub(super) &'a Storage);
+
+impl Events<'_> {
+    pub async fn record(&self, event: Event) -> Result<(), StorageError> {
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    &format!(
+                        "INSERT INTO events ({COLUMNS})
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                                 ?17, ?18)"
+                    ),
+                    params![
+                        event.received_at,
+                        event.kind,
+                        event.action,
+                        event.issue_id,
+                        event.identifier,
+                        event.team_key,
+                        event.title,
+                        event.state,
+                        event.state_type,
+                        event.priority,
+                        event.previous_priority,
+                        event.state_changed,
+                        event.assignee,
+                        event.issue_created_at,
+                        event.completed_at,
+                        event.disposition,
+                        event.target,
+                        event.outcome,
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Matching events with their ids, oldest first.
+    pub async fn history(&self, filter: HistoryFilter) -> Result<Vec<(i64, Event)>, StorageError> {
+        self.0
+            .call(move |conn| {
+                let mut clauses = Vec::new();
+                let mut values: Vec<Box<dyn ToSql + Send>> = Vec::new();
+                let mut add = |clause: &str, value: Box<dyn ToSql + Send>| {
+                    values.push(value);
+                    clauses.push(format!("{clause} ?{}", values.len()));
+                };
+                if let Some(identifier) = filter.identifier {
+                    add("identifier =", Box::new(identifier));
+                }
+                if let Some(team_key) = filter.team_key {
+                    add("team_key =", Box::new(team_key));
+                }
+                if let Some(since) = filter.since {
+                    add("received_at >=", Box::new(since));
+                }
+                if let Some(until) = filter.until {
+                    add("received_at <", Box::new(until));
+                }
+                if let Some(after_id) = filter.after_id {
+                    add("id >", Box::new(after_id));
+                }
+                let clauses = if clauses.is_empty() {
+                    String::new()
+                } else {
+                    format!("WHERE {}", clauses.join(" AND "))
+                };
+
+                let events = conn
+                    .prepare(&format!(
+                        "SELECT id, {COLUMNS} FROM events {clauses} ORDER BY id LIMIT {}",
+                        filter.limit
+                    ))?
+                    .query_map(params_from_iter(values.iter()), |row| {
+                        let id = row.get(0)?;
+                        Ok((id, Event::from_row(row, 1)?))
+                    })?
+                    .collect::<Result<_, _>>()?;
+                Ok(events)
+            })
+            .await
+    }
+
+    /// Events received in `[from, to)`, oldest first.
+    pub async fn between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Event>, StorageError> {
+        self.0
+            .call(move |conn| {
+                let events = conn
+                    .prepare(&format!(
+                        "SELECT {COLUMNS} FROM events
+                         WHERE received_at >= ?1 AND received_at < ?2
+                         ORDER BY received_at, id"
+                    ))?
+                    .query_map((from, to), |row| Event::from_row(row, 0))?
+                    .collect::<Result<_, _>>()?;
+                Ok(events)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn recorded_events_read_back_in_range() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+
+        let at = |ts: &str| ts.parse::<DateTime<Utc>>().unwrap();
+        let event = Event {
+            received_at: at("2026-10-06T10:00:00.250Z"),
+            kind: "Issue".into(),
+            action: "update".into(),
+            issue_id: "i1".into(),
+            identifier: "ENG-1".into(),
+            team_key: Some("ENG".into()),
+            title: "Title, with \"quotes\"".into(),
+            state: "Done".into(),
+            state_type: Some("completed".into()),
+            priority: 1,
+            previous_priority: Some(3),
+            state_changed: true,
+            assignee: None,
+            issue_created_at: Some(at("2026-10-01T08:00:00Z")),
+            completed_at: Some(at("2026-10-06T10:00:00Z")),
+            disposition: "failed".into(),
+            target: Some("webhook".into()),
+            outcome: Some("boom".into()),
+        };
+        for received_at in ["2026-10-05T23:59:59Z", "2026-10-07T00:00:00Z"] {
+            let outside = Event {
+                received_at: at(received_at),
+                ..event.clone()
+            };
+            storage.events().record(outside).await.unwrap();
+        }
+        storage.events().record(event.clone()).await.unwrap();
+
+        let events = storage
+            .events()
+            .between(at("2026-10-06T00:00:00Z"), at("2026-10-07T00:00:00Z"))
+            .await
+            .unwrap();
+
+        assert_eq!(events.len(), 1);
+        let read = &events[0];
+        assert_eq!(read.received_at, event.received_at);
+        assert_eq!(read.title, event.title);
+        assert_eq!(read.previous_priority, Some(3));
+        assert!(read.completed() && read.escalated());
+        assert_eq!(read.issue_created_at, event.issue_created_at);
+        assert_eq!(read.completed_at, event.completed_at);
+        assert_eq!(read.outcome.as_deref(), Some("boom"));
+    }
+}
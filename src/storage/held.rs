@@ -0,0 +1,138 @@
+//! Issue updates waiting out the debounce window, kept so a restart does
+//! not lose them.
+
+use chrono::{DateTime, Utc};
+
+use super::{Event, Storage, StorageError};
+
+/// One held card, by destination url and issue id.
+#[derive(Debug, Clone)]
+pub struct HeldCard {
+    pub url: String,
+    pub issue_id: String,
+    /// The route, as in [`crate::routes::Destination`].
+    pub label: String,
+    pub release_at: DateTime<Utc>,
+    /// The card's content, as the delivery queue serializes it.
+    pub notification: serde_json::Value,
+    /// The event of the latest update folded into the card.
+    pub event: Event,
+}
+
+pub struct HeldCards<'a>(pub(super) &'a Storage);
+
+impl HeldCards<'_> {
+    /// Stores `card`, replacing the one held for the same url and issue.
+    pub async fn put(&self, card: HeldCard) -> Result<(), StorageError> {
+        let notification = serde_json::to_string(&card.notification)?;
+        let event = serde_json::to_string(&card.event)?;
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO held_cards
+                         (url, issue_id, label, release_at, notification, event)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    (
+                        card.url,
+                        card.issue_id,
+                        card.label,
+                        card.release_at,
+                        notification,
+                        event,
+                    ),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn remove(&self, url: &str, issue_id: &str) -> Result<(), StorageError> {
+        let (url, issue_id) = (url.to_string(), issue_id.to_string());
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "DELETE FROM held_cards WHERE url = ?1 AND issue_id = ?2",
+                    (url, issue_id),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Every held card, the first to be released first.
+    pub async fn list(&self) -> Result<Vec<HeldCard>, StorageError> {
+        self.0
+            .call(|conn| {
+                let rows: Vec<(HeldCard, String, String)> = conn
+                    .prepare(
+                        "SELECT url, issue_id, label, release_at, notification, event
+                         FROM held_cards ORDER BY release_at, rowid",
+                    )?
+                    .query_map([], |row| {
+                        Ok((
+                            HeldCard {
+                                url: row.get(0)?,
+                                issue_id: row.get(1)?,
+                                label: row.get(2)?,
+                                release_at: row.get(3)?,
+                                notification: serde_json::Value::Null,
+                                event: Event::default(),
+                            },
+                            row.get(4)?,
+                            row.get(5)?,
+                        ))
+                    })?
+                    .collect::<Result<_, _>>()?;
+                rows.into_iter()
+                    .map(|(mut card, notification, event)| {
+                        card.notification = serde_json::from_str(&notification)?;
+                        card.event = serde_json::from_str(&event)?;
+                        Ok(card)
+                    })
+                    .collect()
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    fn card(issue_id: &str, release_at: DateTime<Utc>) -> HeldCard {
+        HeldCard {
+            url: "https://lark.test/eng".into(),
+            issue_id: issue_id.into(),
+            label: "default".into(),
+            release_at,
+            notification: serde_json::json!({ "id": issue_id }),
+            event: Event {
+                issue_id: issue_id.into(),
+                ..Event::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_cards_in_release_order_until_removed() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let held = storage.held_cards();
+        let now = Utc::now();
+        held.put(card("i2", now + chrono::TimeDelta::seconds(5)))
+            .await
+            .unwrap();
+        held.put(card("i1", now)).await.unwrap();
+        held.put(card("i1", now)).await.unwrap();
+
+        let cards = held.list().await.unwrap();
+        let ids: Vec<_> = cards.iter().map(|c| c.issue_id.as_str()).collect();
+        assert_eq!(ids, ["i1", "i2"]);
+        assert_eq!(cards[0].notification["id"], "i1");
+        assert_eq!(cards[0].event.issue_id, "i1");
+
+        held.remove("https://lark.test/eng", "i1").await.unwrap();
+        assert_eq!(held.list().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,71 @@
+//! What the latest card with buttons showed about each issue, so a card
+//! callback can apply its action and re-render the card from the issue id
+//! its button carries.
+
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+
+use super::{Storage, StorageError};
+
+pub struct IssueCards<'a>(pub(super) &'a Storage);
+
+impl IssueCards<'_> {
+    pub async fn get(&self, issue_id: &str) -> Result<Option<serde_json::Value>, StorageError> {
+        let issue_id = issue_id.to_string();
+        self.0
+            .call(move |conn| {
+                let summary: Option<String> = conn
+                    .query_row(
+                        "SELECT summary FROM issue_cards WHERE issue_id = ?1",
+                        [issue_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(summary.map(|s| serde_json::from_str(&s)).transpose()?)
+            })
+            .await
+    }
+
+    /// Replaces what is kept for `issue_id` with `summary`.
+    pub async fn set(
+        &self,
+        issue_id: &str,
+        summary: &serde_json::Value,
+    ) -> Result<(), StorageError> {
+        let (issue_id, summary) = (issue_id.to_string(), serde_json::to_string(summary)?);
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT OR REPLACE INTO issue_cards (issue_id, summary, updated_at)
+                     VALUES (?1, ?2, ?3)",
+                    (issue_id, summary, Utc::now()),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn keeps_the_latest_summary() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let cards = storage.issue_cards();
+        assert!(cards.get("i1").await.unwrap().is_none());
+
+        cards
+            .set("i1", &serde_json::json!({ "title": "Old" }))
+            .await
+            .unwrap();
+        cards
+            .set("i1", &serde_json::json!({ "title": "New" }))
+            .await
+            .unwrap();
+        assert_eq!(cards.get("i1").await.unwrap().unwrap()["title"], "New");
+    }
+}
@@ -0,0 +1,44 @@
+//! Single JSON values under a fixed key, for small state such as tokens.
+
+use rusqlite::OptionalExtension;
+use serde::{Serialize, de::DeserializeOwned};
+
+use super::{Storage, StorageError};
+
+pub struct Kv<'a>(pub(super) &'a Storage);
+
+impl Kv<'_> {
+    pub async fn get<T>(&self, key: &'static str) -> Result<Option<T>, StorageError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.0
+            .call(move |conn| {
+                let value: Option<String> = conn
+                    .query_row("SELECT value FROM kv WHERE key = ?1", [key], |row| {
+                        row.get(0)
+                    })
+                    .optional()?;
+                Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+            })
+            .await
+    }
+
+    pub async fn set<T: Serialize>(
+        &self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let value = serde_json::to_string(value)?;
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO kv (key, value) VALUES (?1, ?2)
+                     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                    (key, value),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
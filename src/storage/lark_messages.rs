@@ -0,0 +1,239 @@
+//! The bot message last sent about each issue to each chat, so later
+//! changes edit that card instead of posting a new one.
+
+use chrono::{DateTime, Utc};
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+
+use super::{Storage, StorageError};
+
+/// How many cards are mapped, for `/health`.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MappingCounts {
+    pub messages: u64,
+    pub chats: u64,
+}
+
+/// The card shown for an issue in a chat.
+#[derive(Debug, PartialEq)]
+pub struct MappedMessage {
+    pub message_id: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+pub struct LarkMessages<'a>(pub(super) &'a Storage);
+
+impl LarkMessages<'_> {
+    pub async fn get(
+        &self,
+        issue_id: &str,
+        chat_id: &str,
+    ) -> Result<Option<MappedMessage>, StorageError> {
+        let (issue_id, chat_id) = (issue_id.to_string(), chat_id.to_string());
+        self.0
+            .call(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT message_id, sent_at FROM lark_messages
+                         WHERE issue_id = ?1 AND chat_id = ?2",
+                        (issue_id, chat_id),
+                        |row| {
+                            Ok(MappedMessage {
+                                message_id: row.get(0)?,
+                                sent_at: row.get(1)?,
+                            })
+                        },
+                    )
+                    .optional()?)
+            })
+            .await
+    }
+
+    /// Remembers `message_id` as the first card for the issue in the chat.
+    /// When another send got there first, its message is kept, and that
+    /// is the one returned.
+    pub async fn claim(
+        &self,
+        issue_id: &str,
+        chat_id: &str,
+        message_id: &str,
+    ) -> Result<String, StorageError> {
+        let row = (
+            issue_id.to_string(),
+            chat_id.to_string(),
+            message_id.to_string(),
+        );
+        self.0
+            .call(move |conn| {
+                let now = Utc::now();
+                conn.execute(
+                    "INSERT INTO lark_messages (issue_id, chat_id, message_id, sent_at, last_used_at)
+                     VALUES (?1, ?2, ?3, ?4, ?4)
+                     ON CONFLICT (issue_id, chat_id) DO NOTHING",
+                    (&row.0, &row.1, &row.2, now),
+                )?;
+                Ok(conn.query_row(
+                    "SELECT message_id FROM lark_messages WHERE issue_id = ?1 AND chat_id = ?2",
+                    (row.0, row.1),
+                    |row| row.get(0),
+                )?)
+            })
+            .await
+    }
+
+    /// Puts `message_id` in place of `old`, a card that can no longer be
+    /// edited. A mapping that already moved on from `old` is left alone.
+    pub async fn replace(
+        &self,
+        issue_id: &str,
+        chat_id: &str,
+        old: &str,
+        message_id: &str,
+    ) -> Result<(), StorageError> {
+        let row = (
+            issue_id.to_string(),
+            chat_id.to_string(),
+            old.to_string(),
+            message_id.to_string(),
+        );
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE lark_messages SET message_id = ?4, sent_at = ?5, last_used_at = ?5
+                     WHERE issue_id = ?1 AND chat_id = ?2 AND message_id = ?3",
+                    (row.0, row.1, row.2, row.3, Utc::now()),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Marks the card as just edited, which keeps it from being evicted.
+    pub async fn touch(&self, issue_id: &str, chat_id: &str) -> Result<(), StorageError> {
+        let (issue_id, chat_id) = (issue_id.to_string(), chat_id.to_string());
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE lark_messages SET last_used_at = ?3 WHERE issue_id = ?1 AND chat_id = ?2",
+                    (issue_id, chat_id, Utc::now()),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn count(&self) -> Result<MappingCounts, StorageError> {
+        self.0
+            .call(|conn| {
+                Ok(conn.query_row(
+                    "SELECT COUNT(*), COUNT(DISTINCT chat_id) FROM lark_messages",
+                    [],
+                    |row| {
+                        Ok(MappingCounts {
+                            messages: row.get(0)?,
+                            chats: row.get(1)?,
+                        })
+                    },
+                )?)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn keeps_the_latest_message_per_chat() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let messages = storage.lark_messages();
+        assert_eq!(messages.get("i1", "oc_eng").await.unwrap(), None);
+
+        messages.claim("i1", "oc_eng", "om_1").await.unwrap();
+        messages.claim("i1", "oc_ops", "om_2").await.unwrap();
+        messages
+            .replace("i1", "oc_eng", "om_1", "om_3")
+            .await
+            .unwrap();
+        assert_eq!(
+            messages
+                .get("i1", "oc_eng")
+                .await
+                .unwrap()
+                .map(|m| m.message_id)
+                .as_deref(),
+            Some("om_3")
+        );
+        assert_eq!(
+            messages
+                .get("i1", "oc_ops")
+                .await
+                .unwrap()
+                .map(|m| m.message_id)
+                .as_deref(),
+            Some("om_2")
+        );
+        assert_eq!(messages.get("i2", "oc_eng").await.unwrap(), None);
+        assert_eq!(
+            messages.count().await.unwrap(),
+            MappingCounts {
+                messages: 2,
+                chats: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn the_first_of_two_concurrent_sends_wins() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let messages = storage.lark_messages();
+        let (first, second) = tokio::join!(
+            messages.claim("i1", "oc_eng", "om_1"),
+            messages.claim("i1", "oc_eng", "om_2"),
+        );
+        assert_eq!(first.unwrap(), second.unwrap());
+
+        let kept = messages.get("i1", "oc_eng").await.unwrap().unwrap();
+        messages
+            .replace("i1", "oc_eng", "om_9", "om_3")
+            .await
+            .unwrap();
+        assert_eq!(messages.get("i1", "oc_eng").await.unwrap(), Some(kept));
+    }
+
+    #[tokio::test]
+    async fn edited_cards_outlive_their_retention_since_sending() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        let messages = storage.lark_messages();
+        messages.claim("i1", "oc_eng", "om_1").await.unwrap();
+        messages.claim("i2", "oc_eng", "om_2").await.unwrap();
+        storage
+            .call(|conn| {
+                let old = Utc::now() - chrono::TimeDelta::days(100);
+                conn.execute(
+                    "UPDATE lark_messages SET sent_at = ?1, last_used_at = ?1",
+                    [old],
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        messages.touch("i1", "oc_eng").await.unwrap();
+
+        let retention = crate::storage::Retention {
+            events: std::time::Duration::from_secs(90 * 24 * 60 * 60),
+            dedup: std::time::Duration::from_secs(7 * 24 * 60 * 60),
+            dead_letters: std::time::Duration::from_secs(30 * 24 * 60 * 60),
+            mappings: std::time::Duration::from_secs(90 * 24 * 60 * 60),
+        };
+        let report = storage.maintain(&retention).await.unwrap();
+        assert_eq!(report.lark_messages_deleted, 1);
+        assert!(messages.get("i1", "oc_eng").await.unwrap().is_some());
+        assert_eq!(messages.get("i2", "oc_eng").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,180 @@
+//! Retention deletes and vacuuming that keep the database file bounded.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use super::{Storage, StorageError};
+
+/// Rows deleted per statement, so no single delete holds the write lock
+/// for long and other queries get the storage thread in between.
+const BATCH_SIZE: usize = 1000;
+
+/// How long each table keeps its rows.
+#[derive(Debug, Clone)]
+pub struct Retention {
+    pub events: Duration,
+    pub dedup: Duration,
+    /// Unreplayed dead letters.
+    pub dead_letters: Duration,
+    /// Which bot message shows each issue, and what its buttons act on.
+    /// Cards left unedited for longer get a new message, and the buttons
+    /// of stored cards this old stop working.
+    pub mappings: Duration,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MaintenanceReport {
+    pub events_deleted: usize,
+    pub dedup_deleted: usize,
+    pub dead_letters_deleted: usize,
+    pub lark_messages_deleted: usize,
+    pub issue_cards_deleted: usize,
+    pub pages_reclaimed: u64,
+}
+
+impl Storage {
+    /// Deletes rows past their retention, then returns the freed pages to
+    /// the filesystem.
+    pub async fn maintain(&self, retention: &Retention) -> Result<MaintenanceReport, StorageError> {
+        let events_deleted = self
+            .delete_before("events", "received_at", retention.events)
+            .await?;
+        let dedup_deleted = self
+            .delete_before("dedup", "seen_at", retention.dedup)
+            .await?;
+        let dead_letters_deleted = self
+            .delete_before("dead_letters", "failed_at", retention.dead_letters)
+            .await?;
+        let lark_messages_deleted = self
+            .delete_before("lark_messages", "last_used_at", retention.mappings)
+            .await?;
+        let issue_cards_deleted = self
+            .delete_before("issue_cards", "updated_at", retention.mappings)
+            .await?;
+        let pages_reclaimed = self
+            .call(|conn| {
+                let free = |conn: &rusqlite::Connection| {
+                    conn.pragma_query_value(None, "freelist_count", |row| row.get::<_, u64>(0))
+                };
+                let before = free(conn)?;
+                conn.execute_batch("PRAGMA incremental_vacuum")?;
+                Ok(before.saturating_sub(free(conn)?))
+            })
+            .await?;
+
+        Ok(MaintenanceReport {
+            events_deleted,
+            dedup_deleted,
+            dead_letters_deleted,
+            lark_messages_deleted,
+            issue_cards_deleted,
+            pages_reclaimed,
+        })
+    }
+
+    async fn delete_before(
+        &self,
+        table: &'static str,
+        column: &'static str,
+        retention: Duration,
+    ) -> Result<usize, StorageError> {
+        let before = Utc::now() - retention;
+        let mut deleted = 0;
+        loop {
+            let batch = self
+                .call(move |conn| {
+                    Ok(conn.execute(
+                        &format!(
+                            "DELETE FROM {table} WHERE rowid IN
+                                 (SELECT rowid FROM {table} WHERE {column} < ?1 LIMIT {BATCH_SIZE})"
+                        ),
+                        [before],
+                    )?)
+                })
+                .await?;
+            deleted += batch;
+            if batch < BATCH_SIZE {
+                return Ok(deleted);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::TempDb;
+
+    #[tokio::test]
+    async fn deletes_expired_events_across_batches_and_vacuums() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        storage
+            .call(|conn| {
+                let tx = conn.transaction()?;
+                let old = Utc::now() - chrono::TimeDelta::days(100);
+                let mut received = vec![old; 2 * BATCH_SIZE + 1];
+                received.push(Utc::now());
+                for received_at in received {
+                    tx.execute(
+                        "INSERT INTO events (received_at, kind, action, issue_id, identifier,
+                             title, state, priority, disposition)
+                         VALUES (?1, 'Issue', 'update', 'i1', 'ENG-1', 'Title', 'Todo', 0, 'sent')",
+                        [received_at],
+                    )?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let retention = Retention {
+            events: Duration::from_secs(90 * 24 * 60 * 60),
+            dedup: Duration::from_secs(7 * 24 * 60 * 60),
+            dead_letters: Duration::from_secs(30 * 24 * 60 * 60),
+            mappings: Duration::from_secs(90 * 24 * 60 * 60),
+        };
+        let report = storage.maintain(&retention).await.unwrap();
+        assert_eq!(report.events_deleted, 2 * BATCH_SIZE + 1);
+        assert!(report.pages_reclaimed > 0);
+
+        let left: usize = storage
+            .call(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))?))
+            .await
+            .unwrap();
+        assert_eq!(left, 1);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_expire_on_their_own_schedule() {
+        let db = TempDb::new();
+        let storage = Storage::open(&db.0).unwrap();
+        for age in [40, 10] {
+            let letter = crate::storage::DeadLetter {
+                id: 0,
+                failed_at: Utc::now() - chrono::TimeDelta::days(age),
+                identifier: "ENG-1".into(),
+                label: "default".into(),
+                url: "https://lark.test/eng".into(),
+                message: serde_json::json!({}),
+                attempts: 5,
+                error: "rate limited".into(),
+            };
+            storage.dead_letters().add(letter).await.unwrap();
+        }
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let retention = Retention {
+            events: 90 * day,
+            dedup: 7 * day,
+            dead_letters: 30 * day,
+            mappings: 90 * day,
+        };
+        let report = storage.maintain(&retention).await.unwrap();
+        assert_eq!(report.dead_letters_deleted, 1);
+        assert_eq!(storage.dead_letters().count().await.unwrap(), 1);
+    }
+}
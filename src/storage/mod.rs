@@ -0,0 +1,320 @@
+//! Local persistence in a single SQLite database at `STORE_PATH`.
+//!
+//! Everything the bridge has to remember across restarts goes through a
+//! typed repository here ([`Storage::events`], [`Storage::reminders`], ...)
+//! instead of its own file. One connection lives on a dedicated thread and
+//! every query is sent to it, so async code awaits a reply rather than
+//! blocking on disk.
+
+mod alerts;
+mod dead_letters;
+mod dedup;
+mod events;
+mod held;
+mod issue_cards;
+mod kv;
+mod lark_messages;
+mod maintenance;
+mod reminders;
+
+use std::{fmt, io, path::Path, sync::mpsc, thread, time::Duration};
+
+use rusqlite::Connection;
+use tracing::info;
+
+pub use alerts::{Alerts, SlaMarker};
+pub use dead_letters::{DeadLetter, DeadLetters};
+pub use dedup::Dedup;
+pub use events::{Event, Events, HistoryFilter};
+pub use held::{HeldCard, HeldCards};
+pub use issue_cards::IssueCards;
+pub use kv::Kv;
+pub use lark_messages::{LarkMessages, MappedMessage};
+pub use maintenance::{MaintenanceReport, Retention};
+pub use reminders::{DueMarker, Reminders};
+
+/// Applied in order at startup; `PRAGMA user_version` records how many
+/// already ran. Released entries must never change, only new ones appended.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE kv (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+
+    CREATE TABLE events (
+        id          INTEGER PRIMARY KEY,
+        received_at TEXT NOT NULL,
+        kind        TEXT NOT NULL,
+        action      TEXT NOT NULL,
+        issue_id    TEXT NOT NULL,
+        identifier  TEXT NOT NULL,
+        team_key    TEXT,
+        title       TEXT NOT NULL,
+        state       TEXT NOT NULL,
+        state_type  TEXT,
+        priority    INTEGER NOT NULL,
+        assignee    TEXT,
+        disposition TEXT NOT NULL,
+        target      TEXT,
+        outcome     TEXT
+    );
+    CREATE INDEX events_received_at ON events (received_at);
+    CREATE INDEX events_identifier ON events (identifier, received_at);
+
+    CREATE TABLE dedup (
+        key     TEXT PRIMARY KEY,
+        seen_at TEXT NOT NULL
+    );
+
+    CREATE TABLE due_reminders (
+        issue_id    TEXT PRIMARY KEY,
+        due_date    TEXT NOT NULL,
+        reminded_on TEXT NOT NULL
+    );
+
+    CREATE TABLE mutes (
+        identifier TEXT PRIMARY KEY,
+        until      TEXT NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE events ADD COLUMN previous_priority INTEGER;
+    ALTER TABLE events ADD COLUMN state_changed INTEGER NOT NULL DEFAULT 0;
+"#,
+    r#"
+    ALTER TABLE events ADD COLUMN issue_created_at TEXT;
+    ALTER TABLE events ADD COLUMN completed_at TEXT;
+"#,
+    r#"
+    CREATE TABLE sla_alerts (
+        issue_id    TEXT PRIMARY KEY,
+        breaches_at TEXT NOT NULL,
+        breached    INTEGER NOT NULL,
+        alerted_at  TEXT NOT NULL
+    );
+"#,
+    r#"
+    CREATE TABLE dead_letters (
+        id         INTEGER PRIMARY KEY,
+        failed_at  TEXT NOT NULL,
+        identifier TEXT NOT NULL,
+        label      TEXT NOT NULL,
+        url        TEXT NOT NULL,
+        message    TEXT NOT NULL,
+        attempts   INTEGER NOT NULL,
+        error      TEXT NOT NULL
+    );
+"#,
+    r#"
+    CREATE TABLE lark_messages (
+        issue_id   TEXT NOT NULL,
+        chat_id    TEXT NOT NULL,
+        message_id TEXT NOT NULL,
+        sent_at    TEXT NOT NULL,
+        PRIMARY KEY (issue_id, chat_id)
+    );
+"#,
+    r#"
+    CREATE TABLE held_cards (
+        url          TEXT NOT NULL,
+        issue_id     TEXT NOT NULL,
+        label        TEXT NOT NULL,
+        release_at   TEXT NOT NULL,
+        notification TEXT NOT NULL,
+        event        TEXT NOT NULL,
+        PRIMARY KEY (url, issue_id)
+    );
+"#,
+    r#"
+    CREATE TABLE issue_cards (
+        issue_id   TEXT PRIMARY KEY,
+        summary    TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE lark_messages ADD COLUMN last_used_at TEXT NOT NULL DEFAULT '';
+    UPDATE lark_messages SET last_used_at = sent_at;
+"#,
+];
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+    /// The storage thread is gone, which only happens if it panicked.
+    Closed,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "sqlite: {e}"),
+            Self::Json(e) => write!(f, "stored value: {e}"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Closed => write!(f, "storage thread stopped"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// Handle to the storage thread. Cheap to clone.
+#[derive(Clone)]
+pub struct Storage {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Storage {
+    /// Opens (or creates) the database and brings its schema up to date.
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let mut conn = Connection::open(path)?;
+        restrict_permissions(path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        enable_incremental_vacuum(&conn)?;
+        migrate(&mut conn)?;
+
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("storage".into())
+            .spawn(move || {
+                for job in queue {
+                    job(&mut conn);
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+
+    pub fn alerts(&self) -> Alerts<'_> {
+        Alerts(self)
+    }
+
+    pub fn dead_letters(&self) -> DeadLetters<'_> {
+        DeadLetters(self)
+    }
+
+    pub fn events(&self) -> Events<'_> {
+        Events(self)
+    }
+
+    pub fn dedup(&self) -> Dedup<'_> {
+        Dedup(self)
+    }
+
+    pub fn held_cards(&self) -> HeldCards<'_> {
+        HeldCards(self)
+    }
+
+    pub fn issue_cards(&self) -> IssueCards<'_> {
+        IssueCards(self)
+    }
+
+    pub fn lark_messages(&self) -> LarkMessages<'_> {
+        LarkMessages(self)
+    }
+
+    pub fn reminders(&self) -> Reminders<'_> {
+        Reminders(self)
+    }
+
+    pub fn kv(&self) -> Kv<'_> {
+        Kv(self)
+    }
+
+    /// Runs `f` on the storage thread and waits for its result.
+    async fn call<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+    {
+        let (reply, result) = tokio::sync::oneshot::channel();
+        self.jobs
+            .send(Box::new(move |conn| {
+                let _ = reply.send(f(conn));
+            }))
+            .map_err(|_| StorageError::Closed)?;
+        result.await.map_err(|_| StorageError::Closed)?
+    }
+}
+
+/// Lets maintenance hand freed pages back to the filesystem. Databases
+/// created without it are converted once, which rewrites the file.
+fn enable_incremental_vacuum(conn: &Connection) -> Result<(), StorageError> {
+    const INCREMENTAL: u8 = 2;
+    let mode: u8 = conn.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+    if mode != INCREMENTAL {
+        conn.pragma_update(None, "auto_vacuum", INCREMENTAL)?;
+        conn.execute_batch("VACUUM")?;
+        info!("store switched to incremental vacuum");
+    }
+    Ok(())
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (version, sql) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        info!("store migrated to schema version {}", version + 1);
+    }
+    Ok(())
+}
+
+/// The database holds OAuth tokens, so it is only readable by the owner.
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    /// A database path in the temp dir, removed with its WAL files on drop.
+    pub struct TempDb(pub PathBuf);
+
+    impl TempDb {
+        pub fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("bridge-{}.db", rand::random::<u64>())))
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut file = self.0.clone().into_os_string();
+                file.push(suffix);
+                std::fs::remove_file(file).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Bookkeeping for the scheduled reminder jobs: which issues were already
+//! listed on a due-date card, and which are muted on the stale issues card.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDate;
+
+use super::{Storage, StorageError};
+
+/// When an issue was last listed, and for which due date, so that moving
+/// the due date earns a fresh reminder.
+#[derive(Debug)]
+pub struct DueMarker {
+    pub due_date: String,
+    pub on: NaiveDate,
+}
+
+pub struct Reminders<'a>(pub(super) &'a Storage);
+
+impl Reminders<'_> {
+    pub async fn due_markers(&self) -> Result<HashMap<String, DueMarker>, StorageError> {
+        self.0
+            .call(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT issue_id, due_date, reminded_on FROM due_reminders")?;
+                let markers = stmt
+                    .query_map([], |row| {
+                        Ok((
+                            row.get(0)?,
+                            DueMarker {
+                                due_date: row.get(1)?,
+                                on: row.get(2)?,
+                            },
+                        ))
+                    })?
+                    .collect::<Result<_, _>>()?;
+                Ok(markers)
+            })
+            .await
+    }
+
+    pub async fn mark_due(
+        &self,
+        issue_id: &str,
+        due_date: &str,
+        on: NaiveDate,
+    ) -> Result<(), StorageError> {
+        let (issue_id, due_date) = (issue_id.to_string(), due_date.to_string());
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO due_reminders (issue_id, due_date, reminded_on) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (issue_id) DO UPDATE
+                         SET due_date = excluded.due_date, reminded_on = excluded.reminded_on",
+                    (issue_id, due_date, on),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Drops the markers of every issue not in `open`.
+    pub async fn forget_due_except(&self, open: HashSet<String>) -> Result<(), StorageError> {
+        self.0
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                let known: Vec<String> = tx
+                    .prepare("SELECT issue_id FROM due_reminders")?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?;
+                for issue_id in known.iter().filter(|id| !open.contains(*id)) {
+                    tx.execute("DELETE FROM due_reminders WHERE issue_id = ?1", [issue_id])?;
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Keeps `identifier` off the stale issues card until `until`
+    /// (inclusive).
+    pub async fn mute(&self, identifier: &str, until: NaiveDate) -> Result<(), StorageError> {
+        let identifier = identifier.to_string();
+        self.0
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO mutes (identifier, until) VALUES (?1, ?2)
+                     ON CONFLICT (identifier) DO UPDATE SET until = excluded.until",
+                    (identifier, until),
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Returns whether `identifier` was muted.
+    pub async fn unmute(&self, identifier: &str) -> Result<bool, StorageError> {
+        let identifier = identifier.to_string();
+        self.0
+            .call(move |conn| {
+                let removed =
+                    conn.execute("DELETE FROM mutes WHERE identifier = ?1", [identifier])?;
+                Ok(removed > 0)
+            })
+            .await
+    }
+
+    /// Identifiers still muted on `today`. Expired mutes are removed.
+    pub async fn muted(&self, today: NaiveDate) -> Result<HashSet<String>, StorageError> {
+        self.0
+            .call(move |conn| {
+                conn.execute("DELETE FROM mutes WHERE until < ?1", [today])?;
+                let muted = conn
+                    .prepare("SELECT identifier FROM mutes")?
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?;
+                Ok(muted)
+            })
+            .await
+    }
+}
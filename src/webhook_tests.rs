@@ -0,0 +1,1270 @@
+//! End-to-end checks of `POST /webhook`: fixture payloads from
+//! `tests/fixtures/` are signed and sent through the real router, with a
+//! mock Lark webhook capturing the cards.
+//!
+//! Card JSON is snapshotted with insta; after an intended card change, run
+//! `INSTA_UPDATE=always cargo test` (or `cargo insta review`) and review
+//! the diff under `src/snapshots/`.
+//!
+//! Webhook cards go through the delivery queue, so the helpers that read
+//! the mock wait for it to empty first.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{HeaderValue, Request, StatusCode};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::enrich::Enricher;
+use crate::filters::IgnoreRules;
+use crate::lark::app::LarkApp;
+use crate::lark::contact::Contacts;
+use crate::lark::delivery::{Delivery, DeliveryConfig};
+use crate::linear::api::{LinearAuth, LinearClient};
+use crate::routes::Routes;
+use crate::storage::tests::TempDb;
+use crate::storage::{Retention, Storage};
+use crate::{AppState, CardLanguage};
+
+const SECRET: &str = "fixture-secret";
+
+/// Value of `linear-signature` for `body` under `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(&path).unwrap_or_else(|e| panic!("reading {path}: {e}"))
+}
+
+/// A bridge with only the webhook configured, posting to a mock Lark.
+struct Harness {
+    lark: MockServer,
+    state: Arc<AppState>,
+    _db: TempDb,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        Self::start(|_, _| {}).await
+    }
+
+    /// With `POST /simulate` registered.
+    async fn dev() -> Self {
+        Self::start(|state, _| state.dev_mode = true).await
+    }
+
+    /// With the `ROUTES_CONFIG` that `config` builds from the mock's url.
+    async fn routed(config: impl FnOnce(&str) -> String) -> Self {
+        Self::start(|state, uri| state.routes = Routes::parse(&config(uri)).unwrap()).await
+    }
+
+    /// With a Lark app for mentions, whose contact API is the mock too.
+    async fn with_contacts() -> Self {
+        Self::start(|state, uri| {
+            let app = Arc::new(LarkApp::new(uri, "cli_test".into(), "app-secret".into()));
+            state.contacts = Some(Contacts::new(app));
+        })
+        .await
+    }
+
+    /// Posting as the Lark app's bot to the chat `oc_eng`, with the message
+    /// API on the mock answering `om_1` for new messages.
+    async fn bot() -> Self {
+        Self::start_bot(|_| {}).await
+    }
+
+    /// [`Harness::bot`], with `configure` applied to the state.
+    async fn start_bot(configure: impl FnOnce(&mut AppState)) -> Self {
+        let bridge = Self::start(|state, uri| {
+            let app = LarkApp::new(uri, "cli_test".into(), "app-secret".into());
+            state.lark_app = Some(Arc::new(app));
+            state.lark_webhook_url = "oc_eng".into();
+            configure(state);
+        })
+        .await;
+        lark_token(&bridge.lark).await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/im/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "code": 0,
+                "data": { "message_id": "om_1" },
+            })))
+            .with_priority(1)
+            .mount(&bridge.lark)
+            .await;
+        Mock::given(method("PATCH"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "code": 0 })),
+            )
+            .mount(&bridge.lark)
+            .await;
+        bridge
+    }
+
+    /// Holding issue updates for `wait`.
+    async fn debounced(wait: Duration) -> Self {
+        let config = DeliveryConfig {
+            debounce: wait,
+            ..Self::delivery_config()
+        };
+        Self::start_with(config, |_, _| {}).await
+    }
+
+    /// Quick retries, so failure tests stay fast.
+    fn delivery_config() -> DeliveryConfig {
+        DeliveryConfig {
+            max_attempts: 3,
+            backoff: Duration::from_millis(5),
+            ..DeliveryConfig::default()
+        }
+    }
+
+    /// `configure` adjusts the state, given the mock's url.
+    async fn start(configure: impl FnOnce(&mut AppState, &str)) -> Self {
+        Self::start_with(Self::delivery_config(), configure).await
+    }
+
+    async fn start_with(
+        delivery: DeliveryConfig,
+        configure: impl FnOnce(&mut AppState, &str),
+    ) -> Self {
+        let lark = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "code": 0 })),
+            )
+            .mount(&lark)
+            .await;
+        Self::start_on(lark, TempDb::new(), delivery, configure).await
+    }
+
+    /// On the mock and database an earlier bridge left, as after a restart.
+    async fn start_on(
+        lark: MockServer,
+        db: TempDb,
+        delivery: DeliveryConfig,
+        configure: impl FnOnce(&mut AppState, &str),
+    ) -> Self {
+        let storage = Storage::open(&db.0).unwrap();
+        let (delivery, jobs) = Delivery::new(delivery, storage.clone());
+        let mut state = AppState {
+            webhook_secret: SECRET.into(),
+            lark_webhook_url: format!("{}/hook", lark.uri()),
+            routes: Routes::default(),
+            ignore: IgnoreRules::default(),
+            lark_verification_token: None,
+            lark_encrypt_key: None,
+            lark_ops_webhook_url: None,
+            alerted_error_codes: Mutex::new(HashSet::new()),
+            linear: None,
+            linear_workspace: None,
+            card_template: None,
+            card_language: CardLanguage::default(),
+            card_include_description: true,
+            card_include_latest_comment: false,
+            card_update_max_age: Duration::from_secs(86_400),
+            persisted_counters: vec!["sent".into(), "dropped".into()],
+            enricher: Enricher::new(Duration::from_millis(100)),
+            workflow_states: Mutex::new(HashMap::new()),
+            oauth: None,
+            admin_token: None,
+            storage,
+            retention: Retention {
+                events: Duration::from_secs(86_400),
+                dedup: Duration::from_secs(86_400),
+                dead_letters: Duration::from_secs(86_400),
+                mappings: Duration::from_secs(86_400),
+            },
+            redact_content: false,
+            delivery,
+            metrics: crate::metrics::Metrics::default(),
+            lark_app: None,
+            contacts: None,
+            parse_mode: crate::drift::ParseMode::Lenient,
+            dev_mode: false,
+            timezone: chrono_tz::UTC,
+            http: reqwest::Client::new(),
+        };
+        configure(&mut state, &lark.uri());
+        let state = Arc::new(state);
+        tokio::spawn(crate::lark::delivery::run(state.clone(), jobs));
+        state.delivery.resume().await;
+
+        Self {
+            lark,
+            state,
+            _db: db,
+        }
+    }
+
+    async fn post(&self, body: Vec<u8>, signature: &str) -> StatusCode {
+        let signature = HeaderValue::from_str(signature).unwrap();
+        self.post_signed(body, vec![signature]).await
+    }
+
+    /// Posts `body` with one `linear-signature` header per value.
+    async fn post_signed(&self, body: Vec<u8>, signatures: Vec<HeaderValue>) -> StatusCode {
+        let mut request = Request::post("/webhook")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        for signature in signatures {
+            request.headers_mut().append("linear-signature", signature);
+        }
+        crate::router(self.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn simulate(&self, query: &str, body: Vec<u8>) -> (StatusCode, serde_json::Value) {
+        let request = Request::post(format!("/simulate{query}"))
+            .body(Body::from(body))
+            .unwrap();
+        let response = crate::router(self.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    /// Posts a fixture with a valid signature.
+    async fn deliver(&self, name: &str) -> StatusCode {
+        let body = fixture(name);
+        let signature = sign(SECRET, &body);
+        self.post(body, &signature).await
+    }
+
+    /// Posts a fixture as Linear's delivery `id`, the key of deduplication.
+    async fn deliver_as(&self, name: &str, id: &str) -> StatusCode {
+        let body = fixture(name);
+        let signature = sign(SECRET, &body);
+        let request = Request::post("/webhook")
+            .header("content-type", "application/json")
+            .header("linear-signature", signature)
+            .header("linear-delivery", id)
+            .body(Body::from(body))
+            .unwrap();
+        crate::router(self.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    /// Delivers fixture `name` after `edit` adjusted it.
+    async fn deliver_edited(&self, name: &str, edit: impl FnOnce(&mut serde_json::Value)) {
+        let mut payload: serde_json::Value = serde_json::from_slice(&fixture(name)).unwrap();
+        edit(&mut payload);
+        let body = serde_json::to_vec(&payload).unwrap();
+        let signature = sign(SECRET, &body);
+        assert_eq!(self.post(body, &signature).await, StatusCode::OK);
+    }
+
+    /// Dispositions in the event log, oldest first.
+    async fn dispositions(&self) -> Vec<String> {
+        self.settle().await;
+        let filter = crate::storage::HistoryFilter {
+            limit: 100,
+            ..Default::default()
+        };
+        let events = self.state.storage.events().history(filter).await.unwrap();
+        events.into_iter().map(|(_, e)| e.disposition).collect()
+    }
+
+    /// Waits for held cards to be released and the delivery queue to
+    /// empty.
+    async fn settle(&self) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while self.state.delivery.stats().held > 0 {
+            assert!(tokio::time::Instant::now() < deadline, "cards still held");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(self.state.delivery.drain(Duration::from_secs(5)).await);
+    }
+
+    /// Requests to the mock Lark webhooks, leaving out Open API calls.
+    async fn webhook_requests(&self) -> Vec<wiremock::Request> {
+        self.settle().await;
+        let mut requests = self.lark.received_requests().await.unwrap_or_default();
+        requests.retain(|r| !r.url.path().starts_with("/open-apis/"));
+        requests
+    }
+
+    /// Paths the mock Lark webhooks were posted to, sorted.
+    async fn paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .webhook_requests()
+            .await
+            .iter()
+            .map(|r| r.url.path().to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Every card the mock Lark webhooks received, in order.
+    async fn cards(&self) -> Vec<serde_json::Value> {
+        self.webhook_requests()
+            .await
+            .iter()
+            .map(|r| r.body_json().unwrap())
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn create_sends_an_issue_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn update_sends_an_issue_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("issue_update.json").await, StatusCode::OK);
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn rapid_updates_collapse_into_one_card() {
+    let bridge = Harness::debounced(Duration::from_millis(100)).await;
+    bridge.deliver("issue_update.json").await;
+    bridge
+        .deliver_edited("issue_update.json", |payload| {
+            payload["updatedFrom"] = serde_json::json!({ "title": "Login fails", "priority": 3 });
+        })
+        .await;
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    let card = cards[0].to_string();
+    assert!(card.contains("Login fails"));
+    assert!(card.contains("High"));
+    assert_eq!(bridge.dispositions().await, vec!["collapsed", "sent"]);
+}
+
+#[tokio::test]
+async fn a_full_queue_asks_linear_to_retry() {
+    let config = DeliveryConfig {
+        capacity: 1,
+        ..Harness::delivery_config()
+    };
+    let bridge = Harness::start_with(config, |_, _| {}).await;
+    Mock::given(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "code": 0 }))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .with_priority(1)
+        .mount(&bridge.lark)
+        .await;
+
+    assert_eq!(
+        bridge.deliver_as("issue_create.json", "d1").await,
+        StatusCode::OK
+    );
+    // Once the worker posts the first card, the second fills the queue.
+    while bridge.lark.received_requests().await.unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(
+        bridge.deliver_as("issue_update.json", "d2").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        bridge.deliver_as("issue_update.json", "d3").await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    let letters = bridge.state.storage.dead_letters().list().await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(
+        (letters[0].attempts, letters[0].error.as_str()),
+        (0, "delivery queue full")
+    );
+    assert_eq!(letters[0].message["msg_type"], "interactive");
+
+    bridge.settle().await;
+    assert_eq!(
+        bridge.deliver_as("issue_update.json", "d3").await,
+        StatusCode::OK
+    );
+    assert_eq!(bridge.cards().await.len(), 3);
+}
+
+/// The first bridge's runtime is dropped mid-window, taking its timers and
+/// worker with it, as a restart would.
+#[test]
+fn held_updates_survive_a_restart() {
+    let before = tokio::runtime::Runtime::new().unwrap();
+    let bridge = before.block_on(async {
+        let bridge = Harness::debounced(Duration::from_millis(200)).await;
+        bridge.deliver("issue_update.json").await;
+        bridge
+    });
+    drop(before);
+
+    let after = tokio::runtime::Runtime::new().unwrap();
+    after.block_on(async {
+        assert!(bridge.lark.received_requests().await.unwrap().is_empty());
+        let Harness { lark, _db: db, .. } = bridge;
+        let config = DeliveryConfig {
+            debounce: Duration::from_millis(200),
+            ..Harness::delivery_config()
+        };
+        let bridge = Harness::start_on(lark, db, config, |_, _| {}).await;
+        assert_eq!(bridge.cards().await.len(), 1);
+        assert_eq!(bridge.dispositions().await, vec!["sent"]);
+        assert!(
+            bridge
+                .state
+                .storage
+                .held_cards()
+                .list()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    });
+}
+
+#[tokio::test]
+async fn ignored_states_are_not_sent() {
+    let bridge = Harness::start(|state, _| {
+        state.ignore = IgnoreRules::new(vec!["todo".into()], Vec::new(), Vec::new());
+    })
+    .await;
+    bridge.deliver("issue_create.json").await;
+    assert!(bridge.cards().await.is_empty());
+    assert_eq!(bridge.dispositions().await, vec!["ignored"]);
+}
+
+#[tokio::test]
+async fn description_only_updates_are_not_sent() {
+    let bridge = Harness::new().await;
+    assert_eq!(
+        bridge.deliver("issue_update_description.json").await,
+        StatusCode::OK
+    );
+    assert!(bridge.cards().await.is_empty());
+}
+
+#[tokio::test]
+async fn remove_sends_a_deletion_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("issue_delete.json").await, StatusCode::OK);
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn other_kinds_are_acknowledged_without_a_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("test_ping.json").await, StatusCode::OK);
+    assert!(bridge.cards().await.is_empty());
+}
+
+#[tokio::test]
+async fn comments_send_a_comment_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("comment_create.json").await, StatusCode::OK);
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn comments_cannot_mention_the_chat() {
+    let bridge = Harness::new().await;
+    bridge
+        .deliver_edited("comment_create.json", |payload| {
+            payload["data"]["body"] = "<at id=all></at> please **look**".into();
+        })
+        .await;
+
+    let card = bridge.cards().await[0].to_string();
+    assert!(!card.contains("<at id=all>"));
+    assert!(card.contains("please **look**"));
+}
+
+#[tokio::test]
+async fn project_changes_send_a_project_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(bridge.deliver("project_update.json").await, StatusCode::OK);
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn project_progress_alone_is_not_sent() {
+    let bridge = Harness::dev().await;
+    let mut payload: serde_json::Value =
+        serde_json::from_slice(&fixture("project_update.json")).unwrap();
+    payload["updatedFrom"] = serde_json::json!({ "progress": 0.3 });
+    let (_, report) = bridge
+        .simulate("", serde_json::to_vec(&payload).unwrap())
+        .await;
+    assert_eq!(report["outcome"], "ignored");
+}
+
+#[tokio::test]
+async fn project_updates_send_a_status_card() {
+    let bridge = Harness::new().await;
+    assert_eq!(
+        bridge.deliver("project_status_update.json").await,
+        StatusCode::OK
+    );
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 1);
+    insta::assert_json_snapshot!(cards[0]);
+}
+
+#[tokio::test]
+async fn project_routes_match_project_cards() {
+    let bridge =
+        Harness::routed(|uri| format!("[projects]\n\"SSO hardening\" = \"{uri}/sso\"")).await;
+    bridge.deliver("project_update.json").await;
+    bridge.deliver("project_status_update.json").await;
+    assert_eq!(bridge.paths().await, vec!["/sso", "/sso"]);
+}
+
+#[tokio::test]
+async fn label_routes_win_over_team_routes() {
+    let bridge = Harness::routed(|uri| {
+        format!("[teams]\nENG = \"{uri}/eng\"\n[labels]\nsecurity = \"{uri}/security\"")
+    })
+    .await;
+    bridge
+        .deliver_edited("issue_create.json", |payload| {
+            payload["data"]["labels"] = serde_json::json!([{ "id": "l1", "name": "security" }]);
+        })
+        .await;
+    bridge.deliver("issue_update.json").await;
+    assert_eq!(bridge.paths().await, vec!["/eng", "/security"]);
+}
+
+/// The auth API hands out tenant tokens.
+async fn lark_token(lark: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "code": 0,
+            "tenant_access_token": "t-test",
+            "expire": 7200,
+        })))
+        .with_priority(1)
+        .mount(lark)
+        .await;
+}
+
+/// The contact API answers `open_id` for ann@acme.test.
+async fn lark_directory(lark: &MockServer, open_id: Option<&str>) {
+    lark_token(lark).await;
+    Mock::given(method("POST"))
+        .and(path("/open-apis/contact/v3/users/batch_get_id"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "code": 0,
+            "data": { "user_list": [{ "email": "ann@acme.test", "user_id": open_id }] },
+        })))
+        .with_priority(1)
+        .mount(lark)
+        .await;
+}
+
+fn author_field(card: &serde_json::Value) -> &str {
+    card["card"]["elements"][2]["fields"][0]["text"]["content"]
+        .as_str()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn comment_authors_are_mentioned_and_looked_up_once() {
+    let bridge = Harness::with_contacts().await;
+    lark_directory(&bridge.lark, Some("ou_ann")).await;
+    bridge.deliver("comment_create.json").await;
+    bridge.deliver("comment_create.json").await;
+
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 2);
+    for card in &cards {
+        assert_eq!(author_field(card), "**Author:** <at id=ou_ann></at>");
+    }
+    let lookups = bridge
+        .lark
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path().ends_with("/batch_get_id"))
+        .count();
+    assert_eq!(lookups, 1);
+}
+
+#[tokio::test]
+async fn unknown_lark_users_are_named() {
+    let bridge = Harness::with_contacts().await;
+    lark_directory(&bridge.lark, None).await;
+    bridge.deliver("comment_create.json").await;
+    assert_eq!(
+        author_field(&bridge.cards().await[0]),
+        "**Author:** Ann Lee"
+    );
+}
+
+#[tokio::test]
+async fn failed_lookups_fall_back_to_names() {
+    let bridge = Harness::with_contacts().await;
+    Mock::given(method("POST"))
+        .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "code": 10014, "msg": "app secret invalid" })),
+        )
+        .with_priority(1)
+        .mount(&bridge.lark)
+        .await;
+    bridge.deliver("comment_create.json").await;
+    assert_eq!(
+        author_field(&bridge.cards().await[0]),
+        "**Author:** Ann Lee"
+    );
+}
+
+/// A Linear API client whose requests go to the mock `linear`.
+fn linear_client(linear: &MockServer) -> LinearClient {
+    LinearClient::new(
+        LinearAuth::ApiKey("lin_test".into()),
+        Duration::from_secs(10),
+    )
+    .with_endpoint(format!("{}/graphql", linear.uri()))
+}
+
+/// Answers every Linear query with the workflow states of
+/// `issue_update.json`'s team, after `delay`.
+async fn linear_workflow_states(linear: &MockServer, delay: Duration) {
+    let states = serde_json::json!({ "data": { "team": { "states": { "nodes": [{
+        "id": "8a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+        "name": "Todo",
+        "type": "unstarted",
+        "position": 0.0,
+    }] } } } });
+    Mock::given(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(states)
+                .set_delay(delay),
+        )
+        .mount(linear)
+        .await;
+}
+
+#[tokio::test]
+async fn previous_states_are_named_from_the_workflow() {
+    let linear = MockServer::start().await;
+    linear_workflow_states(&linear, Duration::ZERO).await;
+    let bridge = Harness::start(|state, _| state.linear = Some(linear_client(&linear))).await;
+    bridge.deliver("issue_update.json").await;
+
+    assert!(
+        bridge.cards().await[0]
+            .to_string()
+            .contains("Todo → In Progress")
+    );
+}
+
+#[tokio::test]
+async fn slow_workflow_states_leave_the_previous_state_out() {
+    let linear = MockServer::start().await;
+    linear_workflow_states(&linear, Duration::from_secs(3)).await;
+    let bridge = Harness::start(|state, _| state.linear = Some(linear_client(&linear))).await;
+    let started = tokio::time::Instant::now();
+    bridge.deliver("issue_update.json").await;
+
+    let cards = bridge.cards().await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(cards.len(), 1);
+    assert!(!cards[0].to_string().contains("Todo"));
+}
+
+#[tokio::test]
+async fn webhooks_are_answered_without_waiting_on_linear_or_lark() {
+    let linear = MockServer::start().await;
+    linear_workflow_states(&linear, Duration::from_secs(3)).await;
+    let bridge = Harness::start(|state, uri| {
+        state.linear = Some(linear_client(&linear));
+        let app = Arc::new(LarkApp::new(uri, "cli_test".into(), "app-secret".into()));
+        state.contacts = Some(Contacts::new(app));
+    })
+    .await;
+    lark_token(&bridge.lark).await;
+    Mock::given(method("POST"))
+        .and(path("/open-apis/contact/v3/users/batch_get_id"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+        .with_priority(1)
+        .mount(&bridge.lark)
+        .await;
+
+    let started = tokio::time::Instant::now();
+    bridge
+        .deliver_edited("issue_update.json", |payload| {
+            payload["data"]["assignee"]["email"] = "bob@acme.test".into();
+        })
+        .await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(bridge.cards().await.len(), 1);
+}
+
+#[tokio::test]
+async fn bot_edits_the_card_it_sent_for_an_issue() {
+    let bridge = Harness::bot().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    bridge.deliver("issue_update.json").await;
+    bridge.settle().await;
+
+    let requests = bridge.lark.received_requests().await.unwrap();
+    let messages: Vec<_> = requests
+        .iter()
+        .filter(|r| r.url.path().starts_with("/open-apis/im/"))
+        .collect();
+    let calls: Vec<_> = messages
+        .iter()
+        .map(|r| format!("{} {}", r.method, r.url.path()))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            "POST /open-apis/im/v1/messages",
+            "PATCH /open-apis/im/v1/messages/om_1"
+        ]
+    );
+
+    let sent: serde_json::Value = messages[0].body_json().unwrap();
+    assert_eq!(sent["receive_id"], "oc_eng");
+    assert_eq!(sent["msg_type"], "interactive");
+    let card: serde_json::Value = serde_json::from_str(sent["content"].as_str().unwrap()).unwrap();
+    assert_eq!(card["config"]["update_multi"], true);
+    assert!(bridge.webhook_requests().await.is_empty());
+}
+
+/// The bot's message calls, as "METHOD path".
+async fn message_calls(lark: &MockServer) -> Vec<String> {
+    lark.received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path().starts_with("/open-apis/im/"))
+        .map(|r| format!("{} {}", r.method, r.url.path()))
+        .collect()
+}
+
+/// The next `times` edits fail with `code` and HTTP `status`.
+async fn failing_edits(lark: &MockServer, status: u16, code: i64, times: u64) {
+    Mock::given(method("PATCH"))
+        .respond_with(
+            ResponseTemplate::new(status).set_body_json(serde_json::json!({ "code": code })),
+        )
+        .up_to_n_times(times)
+        .with_priority(1)
+        .mount(lark)
+        .await;
+}
+
+#[tokio::test]
+async fn failed_edits_are_retried_instead_of_reposted() {
+    let bridge = Harness::bot().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    failing_edits(&bridge.lark, 503, 0, 1).await;
+    bridge.deliver("issue_update.json").await;
+    bridge.settle().await;
+
+    assert_eq!(
+        message_calls(&bridge.lark).await,
+        [
+            "POST /open-apis/im/v1/messages",
+            "PATCH /open-apis/im/v1/messages/om_1",
+            "PATCH /open-apis/im/v1/messages/om_1",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn completions_get_a_new_card() {
+    let bridge = Harness::bot().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    bridge
+        .deliver_edited("issue_update.json", |payload| {
+            payload["data"]["state"]["name"] = "Done".into();
+            payload["data"]["state"]["type"] = "completed".into();
+        })
+        .await;
+    bridge.settle().await;
+
+    assert_eq!(
+        message_calls(&bridge.lark).await,
+        [
+            "POST /open-apis/im/v1/messages",
+            "POST /open-apis/im/v1/messages",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn old_cards_get_a_new_card_instead_of_an_edit() {
+    let bridge = Harness::start_bot(|state| state.card_update_max_age = Duration::ZERO).await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    bridge.deliver("issue_update.json").await;
+    bridge.settle().await;
+
+    assert_eq!(
+        message_calls(&bridge.lark).await,
+        [
+            "POST /open-apis/im/v1/messages",
+            "POST /open-apis/im/v1/messages",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn deleted_cards_are_replaced_by_a_new_one() {
+    let bridge = Harness::bot().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    failing_edits(&bridge.lark, 200, 230110, 1).await;
+    bridge.deliver("issue_update.json").await;
+    bridge.settle().await;
+
+    assert_eq!(
+        message_calls(&bridge.lark).await,
+        [
+            "POST /open-apis/im/v1/messages",
+            "PATCH /open-apis/im/v1/messages/om_1",
+            "POST /open-apis/im/v1/messages",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn bad_signatures_are_rejected() {
+    let bridge = Harness::new().await;
+    let body = fixture("issue_create.json");
+    let forged = sign("other-secret", &body);
+    assert_eq!(bridge.post(body, &forged).await, StatusCode::UNAUTHORIZED);
+    assert!(bridge.cards().await.is_empty());
+}
+
+#[tokio::test]
+async fn simulate_reports_without_sending() {
+    let bridge = Harness::dev().await;
+    let (status, report) = bridge.simulate("", fixture("issue_create.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["outcome"], "rendered");
+    assert_eq!(report["routes"][0]["label"], "webhook");
+    assert_eq!(report["routes"][0]["url"], bridge.state.lark_webhook_url);
+    assert_eq!(
+        report["card"]["card"]["header"]["title"]["content"],
+        "[Linear] Created: ENG-51"
+    );
+    assert!(bridge.cards().await.is_empty());
+}
+
+#[tokio::test]
+async fn simulate_sends_on_request() {
+    let bridge = Harness::dev().await;
+    let (_, report) = bridge
+        .simulate("?send=true", fixture("issue_create.json"))
+        .await;
+    assert_eq!(report["outcome"], "sent");
+    assert_eq!(bridge.cards().await.len(), 1);
+}
+
+#[tokio::test]
+async fn simulate_lists_the_failed_filter() {
+    let bridge = Harness::dev().await;
+    let (_, report) = bridge.simulate("", fixture("test_ping.json")).await;
+    assert_eq!(report["outcome"], "ignored");
+    assert_eq!(report["filters"][0]["passed"], false);
+    assert_eq!(report["filters"][0]["value"], "WebhookTest");
+}
+
+#[tokio::test]
+async fn simulate_is_not_routed_outside_dev_mode() {
+    let bridge = Harness::new().await;
+    let (status, _) = bridge.simulate("", fixture("issue_create.json")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+fn header(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap()
+}
+
+#[tokio::test]
+async fn padded_signatures_are_trimmed() {
+    let bridge = Harness::new().await;
+    let body = fixture("issue_create.json");
+    let signature = header(&format!(" {}\t ", sign(SECRET, &body)));
+    assert_eq!(
+        bridge.post_signed(body, vec![signature]).await,
+        StatusCode::OK
+    );
+    assert_eq!(bridge.cards().await.len(), 1);
+}
+
+#[tokio::test]
+async fn repeated_signatures_are_accepted_when_they_agree() {
+    let bridge = Harness::new().await;
+    let body = fixture("issue_create.json");
+    let signature = sign(SECRET, &body);
+    let repeated = vec![header(&signature), header(&format!("{signature} "))];
+    assert_eq!(
+        bridge.post_signed(body.clone(), repeated).await,
+        StatusCode::OK
+    );
+
+    // Header folding joins repeats into one comma-separated value.
+    let folded = vec![header(&format!("{signature}, {signature}"))];
+    assert_eq!(bridge.post_signed(body, folded).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn conflicting_signatures_are_a_bad_request() {
+    let bridge = Harness::new().await;
+    let body = fixture("issue_create.json");
+    let signatures = vec![header(&sign(SECRET, &body)), header(&sign("other", &body))];
+    assert_eq!(
+        bridge.post_signed(body, signatures).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert!(bridge.cards().await.is_empty());
+}
+
+#[tokio::test]
+async fn non_ascii_signatures_are_a_bad_request() {
+    let bridge = Harness::new().await;
+    let body = fixture("issue_create.json");
+    let mangled = HeaderValue::from_bytes(b"\xfe\xff").unwrap();
+    assert_eq!(
+        bridge.post_signed(body.clone(), vec![mangled]).await,
+        StatusCode::BAD_REQUEST
+    );
+    // A missing signature stays unauthorized.
+    assert_eq!(
+        bridge.post_signed(body, vec![]).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn team_routes_fan_out_to_every_listed_webhook() {
+    let bridge =
+        Harness::routed(|uri| format!("[teams]\nENG = [\"{uri}/eng-1\", \"{uri}/eng-2\"]")).await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+    assert_eq!(bridge.paths().await, vec!["/eng-1", "/eng-2"]);
+}
+
+#[tokio::test]
+async fn unrouted_issues_fall_back_to_the_default_webhook() {
+    let bridge = Harness::routed(|uri| format!("[teams]\nOPS = \"{uri}/ops\"")).await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+    assert_eq!(bridge.paths().await, vec!["/hook"]);
+}
+
+#[tokio::test]
+async fn digests_follow_team_routes_without_a_default_webhook() {
+    let bridge = Harness::start(|state, uri| {
+        state.routes = Routes::parse(&format!("[teams]\nENG = \"{uri}/eng\"")).unwrap();
+        state.lark_webhook_url = String::new();
+    })
+    .await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+
+    assert_eq!(crate::jobs::digest::run(&bridge.state).await, 1);
+    assert_eq!(bridge.paths().await, vec!["/eng", "/eng"]);
+}
+
+#[tokio::test]
+async fn weekly_summaries_follow_team_routes() {
+    let bridge = Harness::start(|state, uri| {
+        state.routes = Routes::parse(&format!("[teams]\nENG = \"{uri}/eng\"")).unwrap();
+        state.lark_webhook_url = String::new();
+    })
+    .await;
+    // A week ago is always within last week's Monday to Sunday.
+    let event = crate::storage::Event {
+        received_at: chrono::Utc::now() - chrono::Duration::days(7),
+        kind: "Issue".into(),
+        action: "create".into(),
+        issue_id: "i1".into(),
+        identifier: "ENG-1".into(),
+        team_key: Some("ENG".into()),
+        disposition: "sent".into(),
+        ..Default::default()
+    };
+    bridge.state.storage.events().record(event).await.unwrap();
+
+    crate::jobs::weekly::run(&bridge.state).await;
+    assert_eq!(bridge.paths().await, vec!["/eng"]);
+}
+
+/// Lark answers `code` with HTTP `status` for the first `times` posts to
+/// `/hook`, then succeeds.
+async fn failing(lark: &MockServer, status: u16, code: i64, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(
+            ResponseTemplate::new(status).set_body_json(serde_json::json!({ "code": code })),
+        )
+        .up_to_n_times(times)
+        .with_priority(1)
+        .mount(lark)
+        .await;
+}
+
+#[tokio::test]
+async fn rate_limited_cards_are_retried() {
+    let bridge = Harness::new().await;
+    failing(&bridge.lark, 200, 9499, 1).await;
+    failing(&bridge.lark, 429, 0, 1).await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+
+    assert_eq!(bridge.cards().await.len(), 3);
+    let stats = bridge.state.delivery.stats();
+    assert_eq!((stats.sent, stats.retried, stats.dropped), (1, 2, 0));
+}
+
+#[tokio::test]
+async fn cards_are_dropped_after_the_last_attempt() {
+    let bridge = Harness::new().await;
+    failing(&bridge.lark, 200, 9499, 10).await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+
+    assert_eq!(bridge.cards().await.len(), 3);
+    let stats = bridge.state.delivery.stats();
+    assert_eq!((stats.sent, stats.retried, stats.dropped), (0, 2, 1));
+}
+
+/// An admin request to the bridge, with its JSON answer if there is one.
+async fn admin(
+    bridge: &Harness,
+    method: &str,
+    uri: &str,
+) -> (StatusCode, Option<serde_json::Value>) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", "Bearer admin-token")
+        .body(Body::empty())
+        .unwrap();
+    let response = crate::router(bridge.state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+#[tokio::test]
+async fn dropped_cards_are_kept_for_replay() {
+    let bridge = Harness::start(|state, _| state.admin_token = Some("admin-token".into())).await;
+    failing(&bridge.lark, 200, 9499, 3).await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+
+    let (_, list) = admin(&bridge, "GET", "/admin/dead-letters").await;
+    let letters = list.unwrap()["dead_letters"].as_array().unwrap().clone();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0]["identifier"], "ENG-51");
+    assert_eq!(letters[0]["attempts"], 3);
+    assert!(letters[0].get("url").is_none());
+
+    let id = letters[0]["id"].as_i64().unwrap();
+    let (status, _) = admin(&bridge, "POST", &format!("/admin/dead-letters/{id}/replay")).await;
+    assert_eq!(status, StatusCode::OK);
+    let cards = bridge.cards().await;
+    assert_eq!(cards.len(), 4);
+    assert_eq!(cards[3], cards[0]);
+
+    let (status, _) = admin(&bridge, "DELETE", &format!("/admin/dead-letters/{id}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn other_errors_are_not_retried() {
+    let bridge = Harness::new().await;
+    failing(&bridge.lark, 200, 19021, 10).await;
+    assert_eq!(bridge.deliver("issue_create.json").await, StatusCode::OK);
+
+    assert_eq!(bridge.cards().await.len(), 1);
+    assert_eq!(bridge.state.delivery.stats().dropped, 1);
+}
+
+#[tokio::test]
+async fn strict_mode_counts_payload_drift() {
+    let bridge =
+        Harness::start(|state, _| state.parse_mode = crate::drift::ParseMode::Strict).await;
+    bridge.deliver("issue_create.json").await;
+    bridge
+        .deliver_edited("issue_update.json", |payload| {
+            payload["data"]["brandNewField"] = true.into();
+        })
+        .await;
+    assert_eq!(bridge.cards().await.len(), 2);
+
+    let text = bridge.state.metrics.render();
+    assert!(
+        text.lines()
+            .any(|l| l == "linear_lark_payload_drift_total{type=\"Issue\"} 1"),
+        "{text}"
+    );
+}
+
+#[tokio::test]
+async fn metrics_count_webhooks_and_sends() {
+    let bridge = Harness::new().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.post(fixture("issue_create.json"), "0badc0de").await;
+    bridge.settle().await;
+    crate::jobs::maintenance::run(&bridge.state, &bridge.state.retention).await;
+
+    let request = Request::get("/metrics")
+        .header("x-request-id", "scrape-1")
+        .body(Body::empty())
+        .unwrap();
+    let response = crate::router(bridge.state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "scrape-1");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    for line in [
+        "linear_lark_webhooks_total{type=\"Issue\",action=\"create\"} 1",
+        "linear_lark_signature_failures_total 1",
+        "linear_lark_lark_sends_total{status=\"200\",code=\"0\"} 1",
+        "linear_lark_delivery_latency_seconds_count 1",
+        "linear_lark_delivery_sent_total 1",
+        "linear_lark_http_requests_total{method=\"POST\",route=\"/webhook\",status=\"401\"} 1",
+        "linear_lark_maintenance_rows_deleted_total{table=\"events\"} 0",
+        "linear_lark_enrichment_total{kind=\"description\",outcome=\"hit\"} 0",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejected_app_credentials_alert_ops_once() {
+    let bridge = Harness::start(|state, uri| {
+        let app = LarkApp::new(uri, "cli_test".into(), "wrong".into());
+        state.lark_app = Some(Arc::new(app));
+        state.lark_webhook_url = "oc_eng".into();
+        state.lark_ops_webhook_url = Some(format!("{uri}/ops"));
+    })
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "code": 10014,
+            "msg": "app secret invalid",
+        })))
+        .with_priority(1)
+        .mount(&bridge.lark)
+        .await;
+    bridge.deliver("issue_create.json").await;
+    bridge.deliver("comment_create.json").await;
+
+    assert_eq!(bridge.paths().await, ["/ops"]);
+    let alert = bridge.cards().await[0]["content"]["text"].to_string();
+    assert!(alert.contains("LARK_APP_SECRET"), "{alert}");
+    let fetches = bridge.lark.received_requests().await.unwrap();
+    let fetches = fetches.iter().filter(|r| r.url.path().contains("/auth/"));
+    assert_eq!(fetches.count(), 1);
+
+    let response = crate::router(bridge.state.clone())
+        .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(health["lark_app"]["credentials_rejected"], true);
+}
+
+#[tokio::test]
+async fn delivery_totals_carry_over_a_restart() {
+    let bridge = Harness::new().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+    crate::jobs::counters::snapshot(&bridge.state).await;
+
+    let Harness { lark, _db: db, .. } = bridge;
+    let bridge = Harness::start_on(lark, db, Harness::delivery_config(), |_, _| {}).await;
+    crate::jobs::counters::restore(&bridge.state).await;
+    bridge.deliver("comment_create.json").await;
+    bridge.settle().await;
+
+    assert_eq!(bridge.state.delivery.stats().sent, 1);
+    assert_eq!(bridge.state.delivery.totals().sent, 2);
+}
+
+#[tokio::test]
+async fn health_reports_delivery_counters() {
+    let bridge = Harness::new().await;
+    bridge.deliver("issue_create.json").await;
+    bridge.settle().await;
+
+    let response = crate::router(bridge.state.clone())
+        .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["delivery"]["queued"], 0);
+    assert_eq!(health["delivery"]["sent"], 1);
+    assert_eq!(health["dead_letters"], 0);
+    assert_eq!(health["mappings"]["messages"], 0);
+}